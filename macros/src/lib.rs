@@ -0,0 +1,101 @@
+use nestegg::{assemble_tokens, CpuVariant};
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Assembles 6502 code written inline in Rust into the bytes from the first one emitted to the
+/// last, with labels resolved
+///
+/// ```
+/// use nestegg_macros::asm6502;
+///
+/// let program = asm6502! {
+///     ldx #3;
+///     loop: dex; bne loop;    // statements end with a ;
+///     .byte "ok", $FF
+/// };
+/// assert_eq!(program, vec![0xA2, 0x03, 0xCA, 0xD0, 0xFD, b'o', b'k', 0xFF]);
+///
+/// let cmos = asm6502!([Wdc65C02] stz $10; bra * );
+/// assert_eq!(cmos, vec![0x64, 0x10, 0x80, 0xFE]);
+/// ```
+///
+/// The syntax is the assembler's, with Rust comments in place of `;` ones since `;` separates
+/// statements instead of line breaks, which macros can't see. The code has to be valid Rust
+/// tokens, so the few hex numbers Rust reads as a float with a missing exponent, such as `$1E`,
+/// are written `0x1E` instead.
+///
+/// The code is assembled when the macro expands, so invalid assembly fails to compile with the
+/// assembler's error at the statement it's in:
+///
+/// ```compile_fail
+/// let program = nestegg_macros::asm6502!(lda #1; stz $10);
+/// ```
+#[proc_macro]
+pub fn asm6502(input: TokenStream) -> TokenStream {
+    let mut tokens: Vec<TokenTree> = input.into_iter().collect();
+    let mut variant = CpuVariant::Nmos6502;
+    if let Some(TokenTree::Group(group)) = tokens.first() {
+        if group.delimiter() == Delimiter::Bracket {
+            variant = match variant_named(&group.stream().to_string()) {
+                Some(variant) => variant,
+                None => return compile_error("asm6502!: unknown CPU variant", group.span()),
+            };
+            tokens.remove(0);
+        }
+    }
+
+    // The assembler numbers the statements as lines, so errors can point at theirs
+    let mut statements = Vec::new();
+    let mut statement_starts = true;
+    for token in &tokens {
+        if statement_starts {
+            statements.push(token.span());
+        }
+        statement_starts = matches!(token, TokenTree::Punct(punct) if punct.as_char() == ';');
+    }
+
+    let source = tokens.into_iter().collect::<TokenStream>().to_string();
+    match assemble_tokens(&source, variant) {
+        Ok(bytes) => {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:#04X}", byte)).collect();
+            let expression = format!("<[u8]>::to_vec(&[{}])", bytes.join(", "));
+            expression.parse().unwrap()
+        }
+        Err(error) => {
+            let span = match error.file {
+                Some(_) => None,
+                None => error.line.checked_sub(1).and_then(|index| statements.get(index)),
+            };
+            let message = format!("asm6502!: {}", error);
+            compile_error(&message, span.copied().unwrap_or_else(Span::call_site))
+        }
+    }
+}
+
+fn variant_named(name: &str) -> Option<CpuVariant> {
+    match name {
+        "Nmos6502" => Some(CpuVariant::Nmos6502),
+        "Ricoh2A03" => Some(CpuVariant::Ricoh2A03),
+        "Mos6507" => Some(CpuVariant::Mos6507),
+        "Mos6510" => Some(CpuVariant::Mos6510),
+        "Wdc65C02" => Some(CpuVariant::Wdc65C02),
+        "Wdc65SC02" => Some(CpuVariant::Wdc65SC02),
+        _ => None,
+    }
+}
+
+/// `compile_error!("message")`, reported at the span
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    let mut literal = Literal::string(message);
+    literal.set_span(span);
+    let mut arguments = Group::new(Delimiter::Parenthesis, TokenTree::from(literal).into());
+    arguments.set_span(span);
+    vec![
+        TokenTree::from(Ident::new("compile_error", span)),
+        TokenTree::from(bang),
+        TokenTree::from(arguments),
+    ]
+    .into_iter()
+    .collect()
+}
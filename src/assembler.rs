@@ -0,0 +1,570 @@
+mod dsl;
+mod expression;
+mod first_pass;
+mod lexer;
+mod listing;
+mod parser;
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::instruction::opcode::encode_instruction;
+use crate::instruction::operand_mode::OperandMode;
+use crate::instruction::operation::Operation;
+use crate::instruction::Instruction;
+use crate::variant::CpuVariant;
+use expression::Expression;
+pub use dsl::assemble_tokens;
+use first_pass::FirstPass;
+pub use listing::{BlockCycles, Listing, ListingLine};
+use parser::{DataItem, Line, Operand, Statement};
+
+/// The output of the assembler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    /// 64K of memory with the code and data at their addresses and zeros elsewhere, ready for
+    /// ComputerState::initialize_from_image
+    pub image: Vec<u8>,
+    /// Labels and constants by name, with local labels named `global@local`
+    pub symbols: BTreeMap<String, u16>,
+    /// What each line assembled to and how many cycles it takes, which displays as a listing file
+    pub listing: Listing,
+}
+
+impl Assembly {
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).copied()
+    }
+
+    /// Names by value, for ComputerState::disassemble_with_symbols
+    pub fn symbols_by_address(&self) -> HashMap<u16, String> {
+        self.symbols
+            .iter()
+            .map(|(name, address)| (*address, name.clone()))
+            .collect()
+    }
+}
+
+/// Why a source didn't assemble, with the 1-based line it happened on
+/// Lines from macros are reported at the macro call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblerError {
+    /// The included file the line is in, or None for the source itself
+    pub file: Option<String>,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}: {}", file, self.line, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl Error for AssemblerError {}
+
+/// Assembles 6502 source in the usual syntax
+///
+/// ```text
+/// count = 3
+///         .org $8000
+/// start:  ldx #count
+/// loop:   lda table,x     ; zero page or absolute is picked from the address
+///         sta $0200,x
+///         dex
+///         bpl loop
+///         jmp (vector)
+/// table:  .byte 1, 2, "ab"
+/// vector: .word start, <start, >start+1
+///         .res 4, $EA
+/// ```
+///
+/// Expressions are numbers (`42`, `$2A`, `%101010`, `'*'`), symbols and `*` for the address of
+/// the line, combined with `+` and `-`, and `<` and `>` for the low and high byte
+///
+/// ```text
+///         .macro add16 dest, value  ; dest += value
+///         clc
+///         lda dest
+///         adc #<value
+///         sta dest
+///         bcc @done               ; local labels are renamed for each use of the macro
+///         inc dest+1
+/// @done:
+///         .endmacro
+///
+///         .include "constants.inc"
+///         .if SOUND               ; .ifdef and .ifndef check whether a symbol is defined
+///         .incbin "sound.bin"
+///         .else
+///         .res 16
+///         .endif
+///
+/// reset:  add16 $20, 1
+/// @wait:  bit $D011           ; @wait belongs to reset, so other labels can have one too
+///         bpl @wait
+/// :       dex                 ; an anonymous label
+///         bne :-              ; the first anonymous label back, :+ is the first forward
+/// ```
+///
+/// Conditions and `.include` names must be known when they're reached
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Assembler {
+    variant: CpuVariant,
+    include_paths: Vec<PathBuf>,
+    files: HashMap<String, Vec<u8>>,
+}
+
+/// Assembles the source for the NMOS 6502
+pub fn assemble(source: &str) -> Result<Assembly, AssemblerError> {
+    Assembler::default().assemble(source)
+}
+
+impl Assembler {
+    /// An assembler for the variant's instruction set
+    pub fn new(variant: CpuVariant) -> Assembler {
+        Assembler {
+            variant,
+            ..Assembler::default()
+        }
+    }
+
+    /// Adds a directory to look for `.include` and `.incbin` files in, after the ones added
+    /// before it and before the working directory
+    pub fn include_path(mut self, path: impl Into<PathBuf>) -> Assembler {
+        self.include_paths.push(path.into());
+        self
+    }
+
+    /// Provides the contents of a file for `.include` and `.incbin`, which is used instead of
+    /// looking for it on disk
+    pub fn file(mut self, name: &str, contents: impl Into<Vec<u8>>) -> Assembler {
+        self.files.insert(String::from(name), contents.into());
+        self
+    }
+
+    fn load(&self, name: &str) -> Result<Vec<u8>, String> {
+        if let Some(contents) = self.files.get(name) {
+            return Ok(contents.clone());
+        }
+        self.include_paths
+            .iter()
+            .map(|directory| directory.join(name))
+            .chain(std::iter::once(PathBuf::from(name)))
+            .find_map(|path| std::fs::read(path).ok())
+            .ok_or_else(|| format!("Can't read '{}'", name))
+    }
+
+    /// Assembles in two passes: the first assigns addresses to labels and decides on the size of
+    /// each instruction, and the second emits the bytes
+    /// Operands referring to labels further down are assembled as absolute addresses, since
+    /// they're not known to be on the zero page yet
+    pub fn assemble(&self, source: &str) -> Result<Assembly, AssemblerError> {
+        let mut first_pass = FirstPass::new(self);
+        first_pass.run(source)?;
+        let mut symbols = first_pass.symbols;
+
+        let mut image = vec![0; 0x10000];
+        let mut address = 0;
+        let mut listing_lines = Vec::with_capacity(first_pass.lines.len());
+        let mut labels = Vec::new();
+        for laid_out in first_pass.lines {
+            let bytes = self
+                .emit(&laid_out.line, laid_out.mode, &mut symbols, &mut image, &mut address)
+                .map_err(|message| laid_out.location.error(message))?;
+
+            if let Some(label) = laid_out.line.label {
+                labels.push((listing_lines.len(), label));
+            }
+            let instruction = match (laid_out.mode, laid_out.line.statement) {
+                (Some(mode), Some(Statement::Instruction(operation, _))) => {
+                    Some(Instruction(mode, operation))
+                }
+                _ => None,
+            };
+            listing_lines.push(ListingLine::new(
+                laid_out.location.file,
+                laid_out.location.line,
+                (address - bytes.len() as u32) as u16,
+                bytes,
+                laid_out.text,
+                instruction,
+                self.variant,
+            ));
+        }
+        let listing = Listing::new(listing_lines, &labels);
+
+        // Anonymous labels have no name to look them up by
+        let symbols = symbols
+            .into_iter()
+            .filter(|(name, _)| !name.starts_with(':'))
+            .map(|(name, value)| (name, value as u16))
+            .collect();
+        Ok(Assembly {
+            image,
+            symbols,
+            listing,
+        })
+    }
+
+    /// First pass: defines the symbols on the line and moves the address past it
+    /// Returns the addressing mode if the line is an instruction
+    fn lay_out(
+        &self,
+        line: &Line,
+        symbols: &mut HashMap<String, i64>,
+        address: &mut u32,
+    ) -> Result<Option<OperandMode>, String> {
+        if let Some(label) = &line.label {
+            define(symbols, label, *address as i64)?;
+        }
+
+        let current = *address as u16;
+        let known = |expression: &Expression, symbols: &HashMap<String, i64>| {
+            expression.evaluate(symbols, current).map_err(|error| {
+                error.describe(|name| format!("'{}' must be defined before it's used here", name))
+            })
+        };
+        let mut mode = None;
+        let size = match &line.statement {
+            None => 0,
+            Some(Statement::Constant(name, expression)) => {
+                if let Ok(value) = expression.evaluate(symbols, current) {
+                    define(symbols, name, value)?;
+                }
+                0
+            }
+            Some(Statement::Org(expression)) => {
+                *address = check_range(known(expression, symbols)?, 0, 0xFFFF)? as u32;
+                0
+            }
+            Some(Statement::Instruction(operation, operand)) => {
+                let resolved = self.resolve_mode(*operation, operand, symbols, current)?;
+                mode = Some(resolved);
+                1 + resolved.operand_length() as u32
+            }
+            Some(Statement::Byte(items)) => items
+                .iter()
+                .map(|item| match item {
+                    DataItem::Expression(_) => 1,
+                    DataItem::String(string) => string.len() as u32,
+                })
+                .sum(),
+            Some(Statement::Word(expressions)) => 2 * expressions.len() as u32,
+            Some(Statement::Binary(bytes)) => bytes.len() as u32,
+            Some(Statement::IncludeBinary(_)) => unreachable!("Loaded by the first pass"),
+            Some(Statement::Reserve(count, _)) => {
+                check_range(known(count, symbols)?, 0, 0x10000)? as u32
+            }
+        };
+
+        *address += size;
+        if *address > 0x10000 {
+            return Err(String::from("Code runs past the end of memory"));
+        }
+        Ok(mode)
+    }
+
+    /// Picks the addressing mode from the operand syntax, using zero page modes when the
+    /// address is already known to be on the zero page
+    fn resolve_mode(
+        &self,
+        operation: Operation,
+        operand: &Operand,
+        symbols: &HashMap<String, i64>,
+        address: u16,
+    ) -> Result<OperandMode, String> {
+        let supports =
+            |mode| encode_instruction(Instruction(mode, operation), self.variant).is_some();
+        let pick = |zero_page, absolute, expression: &Expression| {
+            let on_zero_page = matches!(expression.evaluate(symbols, address), Ok(0..=0xFF));
+            if (on_zero_page && supports(zero_page)) || !supports(absolute) {
+                zero_page
+            } else {
+                absolute
+            }
+        };
+
+        let mode = match operand {
+            Operand::None if supports(OperandMode::Implied) => OperandMode::Implied,
+            Operand::None | Operand::Accumulator => OperandMode::Accumulator,
+            Operand::Immediate(_) if operation.is_branch() => {
+                return Err(format!("{:?} takes a branch target, not an immediate", operation))
+            }
+            Operand::Immediate(_) => OperandMode::Immediate,
+            // Branches are encoded as Immediate, with the offset to the target
+            Operand::Direct(_) if operation.is_branch() => OperandMode::Immediate,
+            Operand::Direct(e) => pick(OperandMode::ZeroPage, OperandMode::Absolute, e),
+            Operand::IndexedX(e) => pick(OperandMode::ZeroPageX, OperandMode::AbsoluteX, e),
+            Operand::IndexedY(e) => pick(OperandMode::ZeroPageY, OperandMode::AbsoluteY, e),
+            Operand::Indirect(_) if supports(OperandMode::Indirect) => OperandMode::Indirect,
+            Operand::Indirect(_) => OperandMode::ZeroPageIndirect,
+            Operand::IndirectX(_) if supports(OperandMode::AbsoluteIndirectX) => {
+                OperandMode::AbsoluteIndirectX
+            }
+            Operand::IndirectX(_) => OperandMode::IndirectX,
+            Operand::IndirectY(_) => OperandMode::IndirectY,
+            Operand::Relative(_, _) => OperandMode::ZeroPageRelative,
+        };
+        if supports(mode) {
+            Ok(mode)
+        } else {
+            Err(format!("{:?} doesn't support {:?} addressing", operation, mode))
+        }
+    }
+
+    /// Second pass: writes the bytes for the line to the image and returns them
+    fn emit(
+        &self,
+        line: &Line,
+        mode: Option<OperandMode>,
+        symbols: &mut HashMap<String, i64>,
+        image: &mut [u8],
+        address: &mut u32,
+    ) -> Result<Vec<u8>, String> {
+        let current = *address as u16;
+        let evaluate = |expression: &Expression, symbols: &HashMap<String, i64>| {
+            expression
+                .evaluate(symbols, current)
+                .map_err(|error| error.describe(|name| format!("Undefined symbol '{}'", name)))
+        };
+
+        let mut bytes = Vec::new();
+        match &line.statement {
+            None => {}
+            Some(Statement::Constant(name, expression)) => {
+                let value = evaluate(expression, symbols)?;
+                symbols.insert(name.clone(), value);
+            }
+            Some(Statement::Org(expression)) => {
+                *address = evaluate(expression, symbols)? as u32;
+            }
+            Some(Statement::Instruction(operation, operand)) => {
+                let mode = mode.expect("Instructions have a mode from the first pass");
+                let instruction = Instruction(mode, *operation);
+                let opcode = encode_instruction(instruction, self.variant)
+                    .expect("The first pass only picks supported modes");
+                bytes.push(opcode);
+
+                let next_instruction = current as i64 + 1 + mode.operand_length() as i64;
+                let branch_offset = |target: i64| {
+                    check_range(target - next_instruction, -128, 127)
+                        .map(|offset| offset as u8)
+                        .map_err(|_| String::from("Branch target out of range"))
+                };
+                match (mode, operand) {
+                    (_, Operand::None) | (_, Operand::Accumulator) => {}
+                    (OperandMode::Immediate, Operand::Direct(target)) => {
+                        bytes.push(branch_offset(evaluate(target, symbols)?)?);
+                    }
+                    (_, Operand::Relative(zero_page, target)) => {
+                        bytes.push(check_range(evaluate(zero_page, symbols)?, 0, 0xFF)? as u8);
+                        bytes.push(branch_offset(evaluate(target, symbols)?)?);
+                    }
+                    (OperandMode::Immediate, Operand::Immediate(e)) => {
+                        bytes.push(check_range(evaluate(e, symbols)?, -128, 0xFF)? as u8);
+                    }
+                    (_, Operand::Direct(e))
+                    | (_, Operand::IndexedX(e))
+                    | (_, Operand::IndexedY(e))
+                    | (_, Operand::Indirect(e))
+                    | (_, Operand::IndirectX(e))
+                    | (_, Operand::IndirectY(e))
+                    | (_, Operand::Immediate(e)) => {
+                        let value = evaluate(e, symbols)?;
+                        if mode.operand_length() == 1 {
+                            bytes.push(check_range(value, 0, 0xFF)? as u8);
+                        } else {
+                            let word = check_range(value, 0, 0xFFFF)? as u16;
+                            bytes.extend_from_slice(&word.to_le_bytes());
+                        }
+                    }
+                }
+            }
+            Some(Statement::Byte(items)) => {
+                for item in items {
+                    match item {
+                        DataItem::Expression(e) => {
+                            bytes.push(check_range(evaluate(e, symbols)?, -128, 0xFF)? as u8);
+                        }
+                        DataItem::String(string) => bytes.extend_from_slice(string.as_bytes()),
+                    }
+                }
+            }
+            Some(Statement::Word(expressions)) => {
+                for e in expressions {
+                    let word = check_range(evaluate(e, symbols)?, -0x8000, 0xFFFF)? as u16;
+                    bytes.extend_from_slice(&word.to_le_bytes());
+                }
+            }
+            Some(Statement::Reserve(count, fill)) => {
+                let fill = match fill {
+                    Some(e) => check_range(evaluate(e, symbols)?, -128, 0xFF)? as u8,
+                    None => 0,
+                };
+                bytes.resize(evaluate(count, symbols)? as usize, fill);
+            }
+            Some(Statement::Binary(binary)) => bytes.extend_from_slice(binary),
+            Some(Statement::IncludeBinary(_)) => unreachable!("Loaded by the first pass"),
+        }
+
+        let start = *address as usize;
+        image[start..start + bytes.len()].copy_from_slice(&bytes);
+        *address += bytes.len() as u32;
+        Ok(bytes)
+    }
+}
+
+fn define(symbols: &mut HashMap<String, i64>, name: &str, value: i64) -> Result<(), String> {
+    if symbols.insert(name.to_string(), value).is_some() {
+        return Err(format!("'{}' is already defined", name));
+    }
+    Ok(())
+}
+
+fn check_range(value: i64, min: i64, max: i64) -> Result<i64, String> {
+    if value < min || value > max {
+        return Err(format!("Value {} doesn't fit in {}..{}", value, min, max));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    mod describe_assembler {
+        use super::*;
+
+        fn assemble_at(origin: usize, source: &str, variant: CpuVariant) -> Vec<u8> {
+            let assembly = Assembler::new(variant).assemble(source).unwrap();
+            let end = assembly.image.iter().rposition(|&b| b != 0).map_or(origin, |i| i + 1);
+            assembly.image[origin..end].to_vec()
+        }
+
+        #[test]
+        fn it_assembles_each_addressing_mode() {
+            let source = "
+                .org $C000
+                lda #$01
+                asl a
+                asl
+                lda $10,x
+                ldx $1234,y
+                stx $10,y
+                jmp ($FFFC)
+                lda ($20,x)
+                sta ($22),y
+                lda $0010
+                sta $10
+            ";
+            assert_eq!(
+                assemble_at(0xC000, source, CpuVariant::Nmos6502),
+                vec![
+                    0xA9, 0x01, 0x0A, 0x0A, 0xB5, 0x10, 0xBE, 0x34, 0x12, 0x96, 0x10, 0x6C,
+                    0xFC, 0xFF, 0xA1, 0x20, 0x91, 0x22, 0xA5, 0x10, 0x85, 0x10,
+                ]
+            );
+        }
+
+        #[test]
+        fn it_assembles_cmos_modes() {
+            let source = "
+                .org $0200
+                lda ($10)
+                jmp ($2000,x)
+            target:
+                bbs0 $12,target
+                bra target
+                inc
+            ";
+            assert_eq!(
+                assemble_at(0x0200, source, CpuVariant::Wdc65C02),
+                vec![0xB2, 0x10, 0x7C, 0x00, 0x20, 0x8F, 0x12, 0xFD, 0x80, 0xFB, 0x1A]
+            );
+        }
+
+        #[test]
+        fn it_resolves_labels_and_expressions() {
+            let source = "
+                ptr = $20
+                    .org $8000
+                start:
+                    lda #<data
+                    sta ptr
+                    lda #>data
+                    sta ptr+1
+                    jsr later
+                loop:
+                    bne loop
+                    jmp start
+                later:
+                    rts
+                data:
+                    .word start, *
+                    .byte 'A', -1, \"hi\"
+                    .res 2, $EA
+                end:
+            ";
+            let assembly = assemble(source).unwrap();
+            assert_eq!(assembly.symbol("start"), Some(0x8000));
+            assert_eq!(assembly.symbol("ptr"), Some(0x20));
+            assert_eq!(assembly.symbol("data"), Some(0x8011));
+            assert_eq!(assembly.symbol("end"), Some(0x801B));
+            assert_eq!(
+                &assembly.image[0x8000..0x801B],
+                &[
+                    0xA9, 0x11, 0x85, 0x20, 0xA9, 0x80, 0x85, 0x21, 0x20, 0x10, 0x80, 0xD0,
+                    0xFE, 0x4C, 0x00, 0x80, 0x60, 0x00, 0x80, 0x11, 0x80, 0x41, 0xFF, 0x68,
+                    0x69, 0xEA, 0xEA,
+                ][..]
+            );
+        }
+
+        #[test]
+        fn it_assembles_forward_references_as_absolute() {
+            let source = "
+                lda value
+                value = $10
+                lda value
+            ";
+            assert_eq!(
+                assemble_at(0, source, CpuVariant::Nmos6502),
+                vec![0xAD, 0x10, 0x00, 0xA5, 0x10]
+            );
+        }
+
+        #[test]
+        fn it_reports_errors_with_line_numbers() {
+            let error = |source: &str| assemble(source).unwrap_err();
+
+            assert_eq!(
+                error("nop\nlda missing\n"),
+                AssemblerError {
+                    file: None,
+                    line: 2,
+                    message: String::from("Undefined symbol 'missing'")
+                }
+            );
+            assert_eq!(error("a: nop\na: nop").line, 2);
+            assert_eq!(error("bne far\n.res 200\nfar:").message, "Branch target out of range");
+            assert_eq!(error("lda #$100").line, 1);
+            assert_eq!(error("stx $1234,x").line, 1);
+            assert_eq!(error(".org $FFFF\nnop\nnop").line, 3);
+            assert_eq!(error(".org later\nlater:").line, 1);
+            assert_eq!(
+                error("nop\n.word $7FFFFFFFFFFFFFFF+1").to_string(),
+                "line 2: Expression overflows 64 bits"
+            );
+            assert_eq!(
+                error("lda").to_string(),
+                "line 1: LDA doesn't support Accumulator addressing"
+            );
+        }
+    }
+}
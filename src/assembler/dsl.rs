@@ -0,0 +1,119 @@
+use super::{Assembler, AssemblerError};
+use crate::variant::CpuVariant;
+
+/// The implementation of nestegg_macros::asm6502!, which calls it when the macro expands
+/// The tokens are the macro's input stringified, and errors number its statements as lines
+#[doc(hidden)]
+pub fn assemble_tokens(tokens: &str, variant: CpuVariant) -> Result<Vec<u8>, AssemblerError> {
+    let assembly = Assembler::new(variant).assemble(&source_from_tokens(tokens))?;
+
+    let emitted = assembly.listing.lines.iter().filter(|line| !line.bytes.is_empty());
+    let start = emitted.clone().map(|line| line.address as usize).min();
+    let end = emitted.map(|line| line.address as usize + line.bytes.len()).max();
+    match (start, end) {
+        (Some(start), Some(end)) => Ok(assembly.image[start..end].to_vec()),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Turns stringified tokens back into source lines
+/// Any line breaks in the tokens come from the pretty printer rather than the code, so lines are
+/// split at semicolons, and the spaces and line breaks it can put after prefixes like `$` are
+/// removed
+fn source_from_tokens(tokens: &str) -> String {
+    let mut source = String::new();
+    let mut quote = None;
+    let mut chars = tokens.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), _) if q == c => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, ';') => {
+                source.push('\n');
+                continue;
+            }
+            (None, '\n') => {
+                source.push(' ');
+                continue;
+            }
+            (None, '$') | (None, '%') | (None, '@') | (None, '.') => {
+                source.push(c);
+                while matches!(chars.peek(), Some(' ' | '\n')) {
+                    chars.next();
+                }
+                continue;
+            }
+            (None, '0') if starts_token(&source) && matches!(chars.peek(), Some('x' | 'X')) => {
+                chars.next();
+                source.push('$');
+                continue;
+            }
+            _ => {}
+        }
+        source.push(c);
+    }
+    source
+}
+
+fn starts_token(source: &str) -> bool {
+    !source.ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use nestegg_macros::asm6502;
+
+    mod describe_asm6502 {
+        use super::*;
+
+        #[test]
+        fn it_turns_tokens_into_source_lines() {
+            assert_eq!(
+                source_from_tokens("lda #$ 20; sta $0200,x\n .byte \";\", ';'; @ loop: bne @loop"),
+                "lda #$20\n sta $0200,x  .byte \";\", ';'\n @loop: bne @loop"
+            );
+            assert_eq!(source_from_tokens("lda 0x1E; lda label0x1"), "lda $1E\n lda label0x1");
+        }
+
+        #[test]
+        fn it_assembles_inline_code() {
+            let program = asm6502! {
+                ptr = $FB;
+                    .org $C000;
+                start:
+                    lda #<data;     // comments are Rust ones
+                    sta ptr; lda #>data; sta ptr+1;
+                    ldy #0;
+                @loop:
+                    lda (ptr),y;
+                    beq :+;
+                    iny;
+                    bne @loop;
+                :   jmp start;
+                data:
+                    .byte "hi", 0x1E, 0
+            };
+            assert_eq!(
+                program,
+                vec![
+                    0xA9, 0x14, 0x85, 0xFB, 0xA9, 0xC0, 0x85, 0xFC, 0xA0, 0x00, 0xB1, 0xFB,
+                    0xF0, 0x03, 0xC8, 0xD0, 0xF9, 0x4C, 0x00, 0xC0, b'h', b'i', 0x1E, 0x00,
+                ]
+            );
+        }
+
+        #[test]
+        fn it_assembles_for_a_variant() {
+            assert_eq!(asm6502!([Wdc65C02] lda ($10); inc), vec![0xB2, 0x10, 0x1A]);
+            assert_eq!(asm6502!(), Vec::<u8>::new());
+        }
+
+        #[test]
+        fn it_reports_errors_by_statement() {
+            let error = assemble_tokens("lda #1 ; stz $ 10", CpuVariant::Nmos6502).unwrap_err();
+            assert_eq!(error.to_string(), "line 2: Unknown instruction 'stz'");
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use super::lexer::{describe, Token, TokenStream};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Expression {
+    Number(i64),
+    Symbol(String),
+    /// `*`, the address of the current line
+    CurrentAddress,
+    /// `:+`, `:++`, `:-` and so on, the nth anonymous label after or before the line
+    Anonymous(i64),
+    LowByte(Box<Expression>),
+    HighByte(Box<Expression>),
+    Negate(Box<Expression>),
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum EvaluationError {
+    /// The name of the first symbol that isn't defined (yet)
+    Undefined(String),
+    Overflow,
+}
+
+impl EvaluationError {
+    /// The message for the error, where what to say about an undefined symbol depends on where
+    /// the expression is
+    pub(crate) fn describe(self, undefined: impl FnOnce(String) -> String) -> String {
+        match self {
+            EvaluationError::Undefined(name) => undefined(name),
+            EvaluationError::Overflow => String::from("Expression overflows 64 bits"),
+        }
+    }
+}
+
+impl Expression {
+    pub(crate) fn evaluate(
+        &self,
+        symbols: &HashMap<String, i64>,
+        address: u16,
+    ) -> Result<i64, EvaluationError> {
+        let value = match self {
+            Expression::Number(value) => Some(*value),
+            Expression::Symbol(name) => match symbols.get(name) {
+                Some(value) => Some(*value),
+                None => return Err(EvaluationError::Undefined(name.clone())),
+            },
+            Expression::CurrentAddress => Some(address as i64),
+            Expression::Anonymous(_) => return Err(EvaluationError::Undefined(String::from(":"))),
+            Expression::LowByte(e) => Some(e.evaluate(symbols, address)? & 0xFF),
+            Expression::HighByte(e) => Some((e.evaluate(symbols, address)? >> 8) & 0xFF),
+            Expression::Negate(e) => e.evaluate(symbols, address)?.checked_neg(),
+            Expression::Add(a, b) => {
+                a.evaluate(symbols, address)?.checked_add(b.evaluate(symbols, address)?)
+            }
+            Expression::Subtract(a, b) => {
+                a.evaluate(symbols, address)?.checked_sub(b.evaluate(symbols, address)?)
+            }
+        };
+        value.ok_or(EvaluationError::Overflow)
+    }
+
+    /// Names local labels after the global label they belong to, and anonymous label references
+    /// after the anonymous label they point to, given how many were defined before the line
+    pub(crate) fn qualify(&mut self, scope: &str, anonymous_labels: usize) -> Result<(), String> {
+        match self {
+            Expression::Symbol(name) if name.starts_with('@') => {
+                *name = format!("{}{}", scope, name);
+            }
+            Expression::Anonymous(count) => {
+                let index = if *count > 0 {
+                    anonymous_labels as i64 + *count - 1
+                } else {
+                    anonymous_labels as i64 + *count
+                };
+                if index < 0 {
+                    return Err(String::from("No anonymous label before this line"));
+                }
+                *self = Expression::Symbol(anonymous_label(index as usize));
+            }
+            Expression::LowByte(e) | Expression::HighByte(e) | Expression::Negate(e) => {
+                e.qualify(scope, anonymous_labels)?;
+            }
+            Expression::Add(a, b) | Expression::Subtract(a, b) => {
+                a.qualify(scope, anonymous_labels)?;
+                b.qualify(scope, anonymous_labels)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Parses terms joined by + and -
+    pub(crate) fn parse(tokens: &mut TokenStream) -> Result<Expression, String> {
+        let mut expression = Expression::parse_unary(tokens)?;
+        loop {
+            if tokens.accept(&Token::Plus) {
+                let term = Expression::parse_unary(tokens)?;
+                expression = Expression::Add(Box::new(expression), Box::new(term));
+            } else if tokens.accept(&Token::Minus) {
+                let term = Expression::parse_unary(tokens)?;
+                expression = Expression::Subtract(Box::new(expression), Box::new(term));
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+
+    /// The < and > operators bind to the term right after them, so `<label+1` is `(<label)+1`
+    fn parse_unary(tokens: &mut TokenStream) -> Result<Expression, String> {
+        match tokens.next() {
+            Some(Token::LessThan) => Ok(Expression::LowByte(Box::new(Self::parse_unary(tokens)?))),
+            Some(Token::GreaterThan) => {
+                Ok(Expression::HighByte(Box::new(Self::parse_unary(tokens)?)))
+            }
+            Some(Token::Minus) => Ok(Expression::Negate(Box::new(Self::parse_unary(tokens)?))),
+            Some(Token::Number(value)) => Ok(Expression::Number(value)),
+            Some(Token::Identifier(name)) => Ok(Expression::Symbol(name)),
+            Some(Token::Star) => Ok(Expression::CurrentAddress),
+            Some(Token::Colon) => {
+                let mut count = 0;
+                if tokens.peek() == Some(&Token::Minus) {
+                    while tokens.accept(&Token::Minus) {
+                        count -= 1;
+                    }
+                } else {
+                    while tokens.accept(&Token::Plus) {
+                        count += 1;
+                    }
+                }
+                if count == 0 {
+                    return Err(String::from("Expected + or - after : in an anonymous label"));
+                }
+                Ok(Expression::Anonymous(count))
+            }
+            token => Err(format!("Expected an expression, found {}", describe(token.as_ref()))),
+        }
+    }
+}
+
+/// The symbol name for the nth anonymous label, which can't clash with a label in the source
+pub(crate) fn anonymous_label(index: usize) -> String {
+    format!(":{}", index)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::assembler::lexer::tokenize;
+
+    mod describe_expression {
+        use super::*;
+
+        fn evaluate(source: &str) -> Result<i64, EvaluationError> {
+            let mut tokens = TokenStream::new(tokenize(source).unwrap());
+            let expression = Expression::parse(&mut tokens).unwrap();
+            assert!(tokens.is_empty());
+            let symbols = HashMap::from([
+                (String::from("label"), 0x1234),
+                (String::from("minimum"), i64::MIN),
+            ]);
+            expression.evaluate(&symbols, 0x8000)
+        }
+
+        #[test]
+        fn it_evaluates_expressions() {
+            assert_eq!(evaluate("$FF"), Ok(255));
+            assert_eq!(evaluate("%1010 + 10 - 'A'"), Ok(10 + 10 - 65));
+            assert_eq!(evaluate("<label"), Ok(0x34));
+            assert_eq!(evaluate(">label"), Ok(0x12));
+            assert_eq!(evaluate("label+1"), Ok(0x1235));
+            assert_eq!(evaluate(">label+1"), Ok(0x13));
+            assert_eq!(evaluate("* - 2"), Ok(0x7FFE));
+            assert_eq!(evaluate("-1"), Ok(-1));
+            assert_eq!(
+                evaluate("missing + label"),
+                Err(EvaluationError::Undefined(String::from("missing")))
+            );
+        }
+
+        #[test]
+        fn it_reports_overflow() {
+            assert_eq!(evaluate("$7FFFFFFFFFFFFFFF + 1"), Err(EvaluationError::Overflow));
+            assert_eq!(evaluate("0 - $7FFFFFFFFFFFFFFF - 2"), Err(EvaluationError::Overflow));
+            assert_eq!(evaluate("-minimum"), Err(EvaluationError::Overflow));
+            assert_eq!(evaluate("$7FFFFFFFFFFFFFFF - 1 + 1"), Ok(i64::MAX));
+        }
+    }
+}
@@ -0,0 +1,641 @@
+use std::collections::HashMap;
+
+use super::expression::{anonymous_label, EvaluationError, Expression};
+use super::lexer::{tokenize, Token, TokenStream};
+use super::parser::{parse_line, parse_string, Line, Statement};
+use super::{Assembler, AssemblerError};
+use crate::instruction::operand_mode::OperandMode;
+
+/// How deep includes and macro expansions can nest, which catches recursive ones
+const MAX_NESTING: usize = 32;
+
+/// Where a line came from, None being the source passed to the assembler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Location {
+    pub(crate) file: Option<String>,
+    pub(crate) line: usize,
+}
+
+impl Location {
+    pub(crate) fn error(&self, message: String) -> AssemblerError {
+        AssemblerError {
+            file: self.file.clone(),
+            line: self.line,
+            message,
+        }
+    }
+}
+
+/// A line that made it through conditional assembly and macro expansion, with its symbols
+/// qualified and its addressing mode decided
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LaidOutLine {
+    pub(crate) location: Location,
+    /// The line as written, or as expanded for lines from macros
+    pub(crate) text: String,
+    pub(crate) line: Line,
+    pub(crate) mode: Option<OperandMode>,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<String>,
+}
+
+struct Conditional {
+    location: Location,
+    /// Whether the enclosing block is assembled
+    outer_active: bool,
+    active: bool,
+    condition: bool,
+    in_else: bool,
+}
+
+/// Walks the source in order, expanding includes and macros and skipping inactive conditional
+/// blocks as it goes, and lays out the lines that remain
+pub(crate) struct FirstPass<'a> {
+    assembler: &'a Assembler,
+    macros: HashMap<String, Macro>,
+    conditionals: Vec<Conditional>,
+    /// The last global label, which local labels belong to
+    scope: String,
+    anonymous_labels: usize,
+    expansions: usize,
+    nesting: usize,
+    pub(crate) symbols: HashMap<String, i64>,
+    pub(crate) address: u32,
+    pub(crate) lines: Vec<LaidOutLine>,
+}
+
+impl<'a> FirstPass<'a> {
+    pub(crate) fn new(assembler: &'a Assembler) -> FirstPass<'a> {
+        FirstPass {
+            assembler,
+            macros: HashMap::new(),
+            conditionals: Vec::new(),
+            scope: String::new(),
+            anonymous_labels: 0,
+            expansions: 0,
+            nesting: 0,
+            symbols: HashMap::new(),
+            address: 0,
+            lines: Vec::new(),
+        }
+    }
+
+    pub(crate) fn run(&mut self, source: &str) -> Result<(), AssemblerError> {
+        self.process(&lines_of(source, None))?;
+        match self.conditionals.last() {
+            Some(conditional) => {
+                Err(conditional.location.error(String::from(".if without .endif")))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn process(&mut self, lines: &[(Location, String)]) -> Result<(), AssemblerError> {
+        let mut index = 0;
+        while index < lines.len() {
+            let (location, text) = &lines[index];
+            index += 1;
+            let nested = self
+                .process_line(location, text, lines, &mut index)
+                .map_err(|message| location.error(message))?;
+            if let Some(nested) = nested {
+                self.nest(location, &nested)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the lines of an include or macro expansion, which are processed in turn
+    fn process_line(
+        &mut self,
+        location: &Location,
+        text: &str,
+        lines: &[(Location, String)],
+        index: &mut usize,
+    ) -> Result<Option<Vec<(Location, String)>>, String> {
+        let code = strip_comment(text).trim();
+        let (directive, rest) = match code.split_once(char::is_whitespace) {
+            Some((first, rest)) => (first.to_ascii_lowercase(), rest),
+            None => (code.to_ascii_lowercase(), ""),
+        };
+        let active = match self.conditionals.last() {
+            Some(conditional) => conditional.active,
+            None => true,
+        };
+
+        match directive.as_str() {
+            ".if" | ".ifdef" | ".ifndef" => {
+                let condition = active && self.evaluate_condition(&directive, rest)?;
+                self.conditionals.push(Conditional {
+                    location: location.clone(),
+                    outer_active: active,
+                    active: condition,
+                    condition,
+                    in_else: false,
+                });
+            }
+            ".else" => {
+                let conditional = self.conditionals.last_mut().ok_or(".else without .if")?;
+                if conditional.in_else {
+                    return Err(String::from("Second .else for the same .if"));
+                }
+                conditional.in_else = true;
+                conditional.active = conditional.outer_active && !conditional.condition;
+            }
+            ".endif" => {
+                self.conditionals.pop().ok_or(".endif without .if")?;
+            }
+            ".macro" => {
+                let body = collect_macro_body(lines, index)?;
+                if active {
+                    self.define_macro(rest, body)?;
+                }
+            }
+            ".endmacro" | ".endm" => return Err(format!("{} without .macro", directive)),
+            _ if !active => {}
+            ".include" => {
+                let name = parse_string(&mut TokenStream::new(tokenize(rest)?))?;
+                let source = self.assembler.load(&name)?;
+                let source = String::from_utf8(source)
+                    .map_err(|_| format!("'{}' isn't valid UTF-8", name))?;
+                return Ok(Some(lines_of(&source, Some(name))));
+            }
+            _ => match self.macro_call(code) {
+                Some((label, name, arguments)) => {
+                    // The call is kept for the listing, with the label if it has one
+                    let label = label.map_or(String::new(), |label| format!("{}:", label));
+                    self.lay_out(location, text, &label)?;
+                    return self.expand_macro(location, &name, &arguments).map(Some);
+                }
+                None => self.lay_out(location, text, code)?,
+            },
+        }
+        Ok(None)
+    }
+
+    fn nest(
+        &mut self,
+        location: &Location,
+        lines: &[(Location, String)],
+    ) -> Result<(), AssemblerError> {
+        if self.nesting >= MAX_NESTING {
+            return Err(location.error(String::from("Includes or macros nested too deeply")));
+        }
+        self.nesting += 1;
+        let result = self.process(lines);
+        self.nesting -= 1;
+        result
+    }
+
+    fn evaluate_condition(&self, directive: &str, rest: &str) -> Result<bool, String> {
+        let mut tokens = TokenStream::new(tokenize(rest)?);
+        let mut expression = match directive {
+            ".if" => Expression::parse(&mut tokens)?,
+            _ => match tokens.next() {
+                Some(Token::Identifier(name)) => Expression::Symbol(name),
+                _ => return Err(format!("{} takes a symbol name", directive)),
+            },
+        };
+        tokens.expect_end()?;
+        expression.qualify(&self.scope, self.anonymous_labels)?;
+
+        let value = expression.evaluate(&self.symbols, self.address as u16);
+        let defined = match value {
+            Ok(value) => Ok(value),
+            Err(EvaluationError::Undefined(name)) => Err(name),
+            Err(error) => return Err(error.describe(|name| name)),
+        };
+        match directive {
+            ".if" => defined
+                .map(|value| value != 0)
+                .map_err(|name| format!("'{}' must be defined before it's used here", name)),
+            ".ifdef" => Ok(defined.is_ok()),
+            _ => Ok(defined.is_err()),
+        }
+    }
+
+    fn define_macro(&mut self, declaration: &str, body: Vec<String>) -> Result<(), String> {
+        let mut tokens = TokenStream::new(tokenize(declaration)?);
+        let name = match tokens.next() {
+            Some(Token::Identifier(name)) if !name.starts_with('.') => name,
+            _ => return Err(String::from(".macro needs a name")),
+        };
+        if parse_line(&name, self.assembler.variant).is_ok() {
+            return Err(format!("Macro '{}' has the same name as an instruction", name));
+        }
+        let mut parameters = Vec::new();
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Identifier(parameter) => parameters.push(parameter),
+                _ => return Err(format!("Invalid parameters for macro '{}'", name)),
+            }
+            if !tokens.accept(&Token::Comma) {
+                tokens.expect_end()?;
+            }
+        }
+        if self.macros.contains_key(&name) {
+            return Err(format!("Macro '{}' is already defined", name));
+        }
+        self.macros.insert(name, Macro { parameters, body });
+        Ok(())
+    }
+
+    /// The label, name and arguments if the line calls a macro
+    fn macro_call(&self, code: &str) -> Option<(Option<String>, String, Vec<String>)> {
+        let (label, rest) = match code.split_once(':') {
+            Some((label, rest))
+                if is_identifier(label.trim()) && !rest.starts_with(&['+', '-'][..]) =>
+            {
+                (Some(label.trim()), rest.trim())
+            }
+            _ => (None, code),
+        };
+        let (name, arguments) = match rest.split_once(char::is_whitespace) {
+            Some((name, arguments)) => (name, arguments.trim()),
+            None => (rest, ""),
+        };
+        if !self.macros.contains_key(name) {
+            return None;
+        }
+        let arguments = if arguments.is_empty() {
+            Vec::new()
+        } else {
+            split_arguments(arguments)
+        };
+        Some((label.map(String::from), String::from(name), arguments))
+    }
+
+    /// Substitutes the arguments into the macro's body, and gives local labels in the body a
+    /// name of their own for each expansion so the macro can be used more than once per scope
+    fn expand_macro(
+        &mut self,
+        location: &Location,
+        name: &str,
+        arguments: &[String],
+    ) -> Result<Vec<(Location, String)>, String> {
+        let definition = &self.macros[name];
+        if arguments.len() != definition.parameters.len() {
+            return Err(format!(
+                "Macro '{}' takes {} arguments, got {}",
+                name,
+                definition.parameters.len(),
+                arguments.len()
+            ));
+        }
+        self.expansions += 1;
+        let expansion = self.expansions;
+        let substitute = |word: &str| {
+            if let Some(position) = definition.parameters.iter().position(|p| p == word) {
+                arguments[position].clone()
+            } else if let Some(local) = word.strip_prefix('@') {
+                format!("@{}_{}", expansion, local)
+            } else {
+                String::from(word)
+            }
+        };
+        Ok(definition
+            .body
+            .iter()
+            .map(|line| (location.clone(), replace_identifiers(line, &substitute)))
+            .collect())
+    }
+
+    fn lay_out(&mut self, location: &Location, text: &str, code: &str) -> Result<(), String> {
+        let mut line = parse_line(code, self.assembler.variant)?;
+
+        match line.label.as_deref() {
+            Some(":") => {
+                line.label = Some(anonymous_label(self.anonymous_labels));
+                self.anonymous_labels += 1;
+            }
+            Some(label) if label.starts_with('@') => {
+                line.label = Some(format!("{}{}", self.scope, label));
+            }
+            Some(label) => self.scope = String::from(label),
+            None => {}
+        }
+        if let Some(statement) = &mut line.statement {
+            if let Statement::Constant(name, _) = statement {
+                if name.starts_with('@') {
+                    *name = format!("{}{}", self.scope, name);
+                }
+            }
+            for expression in statement.expressions_mut() {
+                expression.qualify(&self.scope, self.anonymous_labels)?;
+            }
+            if let Statement::IncludeBinary(name) = statement {
+                *statement = Statement::Binary(self.assembler.load(name)?);
+            }
+        }
+
+        let mode = self
+            .assembler
+            .lay_out(&line, &mut self.symbols, &mut self.address)?;
+        self.lines.push(LaidOutLine {
+            location: location.clone(),
+            text: String::from(text.trim_end()),
+            line,
+            mode,
+        });
+        Ok(())
+    }
+}
+
+fn lines_of(source: &str, file: Option<String>) -> Vec<(Location, String)> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(index, text)| {
+            let location = Location {
+                file: file.clone(),
+                line: index + 1,
+            };
+            (location, String::from(text))
+        })
+        .collect()
+}
+
+/// Takes the lines up to the matching .endmacro, which macros can't be nested in
+fn collect_macro_body(
+    lines: &[(Location, String)],
+    index: &mut usize,
+) -> Result<Vec<String>, String> {
+    let mut body = Vec::new();
+    while let Some((_, text)) = lines.get(*index) {
+        *index += 1;
+        let first_word = strip_comment(text).split_whitespace().next().unwrap_or("");
+        match first_word.to_ascii_lowercase().as_str() {
+            ".endmacro" | ".endm" => return Ok(body),
+            ".macro" => return Err(String::from("Macros can't be defined inside macros")),
+            _ => body.push(text.clone()),
+        }
+    }
+    Err(String::from(".macro without .endmacro"))
+}
+
+/// The line up to the comment, if any
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (None, ';') => return &text[..index],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Splits on the commas outside of parentheses and strings
+fn split_arguments(arguments: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut quote = None;
+    for c in arguments.chars() {
+        match (quote, c) {
+            (None, ',') if depth == 0 => {
+                split.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+        current.push(c);
+    }
+    split.push(current.trim().to_string());
+    split
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '@')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replaces each identifier outside of strings and comments with what the function returns
+/// Directives and the digits of numbers like $AB are left alone
+fn replace_identifiers(line: &str, replace: &dyn Fn(&str) -> String) -> String {
+    let code = strip_comment(line);
+    let mut replaced = String::new();
+    let mut word = String::new();
+    let mut literal = false;
+    let mut quote = None;
+    for c in code.chars() {
+        let starts_word = c.is_ascii_alphabetic() || c == '_' || c == '@' || c == '.';
+        if quote.is_none() && (c.is_ascii_alphanumeric() || c == '_' || starts_word) {
+            if word.is_empty() {
+                literal = !starts_word || c == '.' || replaced.ends_with('$');
+            }
+            word.push(c);
+            continue;
+        }
+        if literal {
+            replaced.push_str(&word);
+        } else if !word.is_empty() {
+            replaced.push_str(&replace(&word));
+        }
+        word.clear();
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+        replaced.push(c);
+    }
+    if literal {
+        replaced.push_str(&word);
+    } else if !word.is_empty() {
+        replaced.push_str(&replace(&word));
+    }
+    replaced.push_str(&line[code.len()..]);
+    replaced
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::assembler::Assembly;
+    use crate::variant::CpuVariant;
+
+    mod describe_first_pass {
+        use super::*;
+
+        fn assemble(assembler: Assembler, source: &str) -> Assembly {
+            assembler.assemble(source).unwrap()
+        }
+
+        fn bytes(assembly: &Assembly, start: usize, end: &str) -> Vec<u8> {
+            let end = assembly.symbol(end).unwrap() as usize;
+            assembly.image[start..end].to_vec()
+        }
+
+        #[test]
+        fn it_expands_macros_with_parameters() {
+            let source = "
+                    .macro add16 dest, value ; dest += value
+                    clc
+                    lda dest
+                    adc #<value
+                    sta dest
+                    bcc @done
+                    inc dest+1
+                @done:
+                    .endmacro
+                    .macro ret
+                    rts
+                    .endm
+                start:
+                    add16 $20, $0102
+                first: add16 $22, 'd' ; the second use gets its own @done
+                    ret
+                end:
+            ";
+            let assembly = assemble(Assembler::default(), source);
+            assert_eq!(
+                bytes(&assembly, 0, "end"),
+                vec![
+                    0x18, 0xA5, 0x20, 0x69, 0x02, 0x85, 0x20, 0x90, 0x02, 0xE6, 0x21, 0x18,
+                    0xA5, 0x22, 0x69, 0x64, 0x85, 0x22, 0x90, 0x02, 0xE6, 0x23, 0x60,
+                ]
+            );
+            assert_eq!(assembly.symbol("first"), Some(11));
+            assert_eq!(assembly.symbol("first@2_done"), Some(22));
+        }
+
+        #[test]
+        fn it_assembles_conditional_blocks() {
+            let source = "
+                debug = 1
+                    .if debug
+                    .byte 1
+                    .if debug - 1
+                    .byte 2
+                    .else
+                    .byte 3
+                    .endif
+                    .else
+                    .byte 4
+                    .if debug ; inactive, so its .else is too
+                    .else
+                    .byte 5
+                    .endif
+                    .bogus ; never parsed
+                    .endif
+                    .ifdef debug
+                    .byte 6
+                    .endif
+                    .ifndef release
+                    .byte 7
+                    .endif
+                end:
+            ";
+            let assembly = assemble(Assembler::default(), source);
+            assert_eq!(bytes(&assembly, 0, "end"), vec![1, 3, 6, 7]);
+        }
+
+        #[test]
+        fn it_includes_source_and_binary_files() {
+            let assembler = Assembler::new(CpuVariant::Nmos6502)
+                .file("constants.inc", "screen = $0400\n.include \"more.inc\"")
+                .file("more.inc", "colour = 2")
+                .file("data.bin", vec![0xDE, 0xAD, 0xBE, 0xEF]);
+            let source = "
+                    .include \"constants.inc\"
+                    lda #colour
+                    sta screen
+                    .incbin \"data.bin\"
+                end:
+            ";
+            let assembly = assemble(assembler, source);
+            assert_eq!(
+                bytes(&assembly, 0, "end"),
+                vec![0xA9, 0x02, 0x8D, 0x00, 0x04, 0xDE, 0xAD, 0xBE, 0xEF]
+            );
+            assert_eq!(assembly.symbol("screen"), Some(0x0400));
+        }
+
+        #[test]
+        fn it_scopes_local_labels_to_the_global_label_before_them() {
+            let source = "
+                first:
+                    ldx #2
+                @loop:
+                    dex
+                    bne @loop
+                second:
+                    ldy #2
+                @loop:
+                    dey
+                    bne @loop
+                end:
+            ";
+            let assembly = assemble(Assembler::default(), source);
+            assert_eq!(
+                bytes(&assembly, 0, "end"),
+                vec![0xA2, 0x02, 0xCA, 0xD0, 0xFD, 0xA0, 0x02, 0x88, 0xD0, 0xFD]
+            );
+            assert_eq!(assembly.symbol("first@loop"), Some(2));
+            assert_eq!(assembly.symbol("second@loop"), Some(7));
+        }
+
+        #[test]
+        fn it_resolves_anonymous_labels() {
+            let source = "
+                :   dex
+                    bne :-
+                    beq :++
+                :   nop
+                :   jmp :--
+                end:
+            ";
+            let assembly = assemble(Assembler::default(), source);
+            assert_eq!(
+                bytes(&assembly, 0, "end"),
+                vec![0xCA, 0xD0, 0xFD, 0xF0, 0x01, 0xEA, 0x4C, 0x05, 0x00]
+            );
+            assert_eq!(assembly.symbols.keys().collect::<Vec<_>>(), vec!["end"]);
+        }
+
+        #[test]
+        fn it_reports_where_errors_happened() {
+            let error =
+                |assembler: Assembler, source: &str| assembler.assemble(source).unwrap_err();
+            let assembler = Assembler::default().file("bad.inc", "nop\nlda missing");
+
+            assert_eq!(
+                error(assembler.clone(), "nop\n.include \"bad.inc\""),
+                AssemblerError {
+                    file: Some(String::from("bad.inc")),
+                    line: 2,
+                    message: String::from("Undefined symbol 'missing'")
+                }
+            );
+            assert_eq!(
+                error(assembler, ".include \"bad.inc\"").to_string(),
+                "bad.inc:2: Undefined symbol 'missing'"
+            );
+
+            let default = Assembler::default;
+            assert_eq!(error(default(), ".macro m\nlda missing\n.endm\n\nm").line, 5);
+            assert_eq!(error(default(), "nop\n.if 1\nnop").line, 2);
+            assert_eq!(error(default(), ".endif").line, 1);
+            assert_eq!(error(default(), ".if later\n.endif\nlater:").line, 1);
+            assert_eq!(error(default(), ".macro m\nnop").line, 1);
+            assert_eq!(error(default(), ".macro m a\n.endm\nm 1, 2").line, 3);
+            assert_eq!(error(default(), ".macro nop\n.endm").line, 1);
+            assert_eq!(error(default(), ".macro m\nm\n.endm\nm").line, 4);
+            assert_eq!(error(default(), ".include \"missing.inc\"").line, 1);
+            assert_eq!(error(default(), "bne :+").line, 1);
+            assert_eq!(error(default(), "bne :-").line, 1);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Token {
+    Number(i64),
+    /// Labels, mnemonics, registers and directives, which keep their leading dot
+    Identifier(String),
+    String(String),
+    Hash,
+    OpenParen,
+    CloseParen,
+    Comma,
+    Plus,
+    Minus,
+    LessThan,
+    GreaterThan,
+    Star,
+    Colon,
+    Equals,
+}
+
+/// Splits a line into tokens, dropping the comment
+pub(crate) fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            ';' => break,
+            _ if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '$' => {
+                chars.next();
+                Token::Number(parse_number(&mut chars, 16)?)
+            }
+            '%' => {
+                chars.next();
+                Token::Number(parse_number(&mut chars, 2)?)
+            }
+            '0'..='9' => Token::Number(parse_number(&mut chars, 10)?),
+            '\'' => {
+                chars.next();
+                let value = chars.next().ok_or("Unterminated character literal")?;
+                if chars.next() != Some('\'') || !value.is_ascii() {
+                    return Err(String::from("Character literals must be a single ASCII character"));
+                }
+                Token::Number(value as i64)
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) if c.is_ascii() => string.push(c),
+                        Some(_) => return Err(String::from("Strings must be ASCII")),
+                        None => return Err(String::from("Unterminated string")),
+                    }
+                }
+                Token::String(string)
+            }
+            _ if is_identifier_start(c) => {
+                let mut identifier = String::new();
+                identifier.push(c);
+                chars.next();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    identifier.push(c);
+                    chars.next();
+                }
+                Token::Identifier(identifier)
+            }
+            _ => {
+                chars.next();
+                match c {
+                    '#' => Token::Hash,
+                    '(' => Token::OpenParen,
+                    ')' => Token::CloseParen,
+                    ',' => Token::Comma,
+                    '+' => Token::Plus,
+                    '-' => Token::Minus,
+                    '<' => Token::LessThan,
+                    '>' => Token::GreaterThan,
+                    '*' => Token::Star,
+                    ':' => Token::Colon,
+                    '=' => Token::Equals,
+                    _ => return Err(format!("Unexpected character '{}'", c)),
+                }
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '.' || c == '@'
+}
+
+fn parse_number(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    radix: u32,
+) -> Result<i64, String> {
+    let mut digits = String::new();
+    while let Some(&c) = chars.peek() {
+        if !c.is_ascii_alphanumeric() {
+            break;
+        }
+        digits.push(c);
+        chars.next();
+    }
+    i64::from_str_radix(&digits, radix).map_err(|_| format!("Invalid number '{}'", digits))
+}
+
+/// The tokens of a line, consumed front to back by the parsers
+pub(crate) struct TokenStream {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl TokenStream {
+    pub(crate) fn new(tokens: Vec<Token>) -> TokenStream {
+        TokenStream {
+            tokens,
+            position: 0,
+        }
+    }
+
+    pub(crate) fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    pub(crate) fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.position + offset)
+    }
+
+    pub(crate) fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    /// Consumes the token if it's the given one
+    pub(crate) fn accept(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn expect(&mut self, token: &Token) -> Result<(), String> {
+        if self.accept(token) {
+            Ok(())
+        } else {
+            Err(format!("Expected {}", describe(Some(token))))
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    pub(crate) fn expect_end(&self) -> Result<(), String> {
+        match self.peek() {
+            None => Ok(()),
+            token => Err(format!("Unexpected {}", describe(token))),
+        }
+    }
+}
+
+pub(crate) fn describe(token: Option<&Token>) -> String {
+    match token {
+        None => String::from("end of line"),
+        Some(Token::Number(value)) => format!("number {}", value),
+        Some(Token::Identifier(name)) => format!("'{}'", name),
+        Some(Token::String(string)) => format!("string \"{}\"", string),
+        Some(Token::Hash) => String::from("'#'"),
+        Some(Token::OpenParen) => String::from("'('"),
+        Some(Token::CloseParen) => String::from("')'"),
+        Some(Token::Comma) => String::from("','"),
+        Some(Token::Plus) => String::from("'+'"),
+        Some(Token::Minus) => String::from("'-'"),
+        Some(Token::LessThan) => String::from("'<'"),
+        Some(Token::GreaterThan) => String::from("'>'"),
+        Some(Token::Star) => String::from("'*'"),
+        Some(Token::Colon) => String::from("':'"),
+        Some(Token::Equals) => String::from("'='"),
+    }
+}
@@ -0,0 +1,275 @@
+use std::fmt;
+
+use crate::instruction::operand_mode::OperandMode;
+use crate::instruction::operation::Operation;
+use crate::instruction::{calculate_cycles, CycleCount, Instruction};
+use crate::variant::CpuVariant;
+
+/// The bytes shown on each row of the listing, with the rest continued on the rows below
+const BYTES_PER_ROW: usize = 4;
+
+/// A source line with what it assembled to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingLine {
+    /// The included file the line is in, or None for the source itself
+    pub file: Option<String>,
+    pub line: usize,
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// The line as written, or as expanded for lines from macros
+    pub source: String,
+    pub instruction: Option<Instruction>,
+    /// The timing from calculate_cycles, for instructions
+    pub cycles: Option<CycleCount>,
+    /// The cycles for a branch when it's taken, including the extra one if the target is on
+    /// another page
+    pub branch_taken_cycles: Option<u8>,
+}
+
+impl ListingLine {
+    pub(crate) fn new(
+        file: Option<String>,
+        line: usize,
+        address: u16,
+        bytes: Vec<u8>,
+        source: String,
+        instruction: Option<Instruction>,
+        variant: CpuVariant,
+    ) -> ListingLine {
+        let cycles = instruction.and_then(|instruction| calculate_cycles(&instruction, variant));
+        let branch_taken_cycles = match (instruction, cycles) {
+            (Some(Instruction(mode, operation)), Some(cycles)) if operation.is_branch() => {
+                let next = address as i32 + bytes.len() as i32;
+                let offset = bytes[1 + (mode == OperandMode::ZeroPageRelative) as usize] as i8;
+                let crosses_page = (next + offset as i32) & 0xFF00 != next & 0xFF00;
+                Some(cycles.cycles + 1 + crosses_page as u8)
+            }
+            _ => None,
+        };
+        ListingLine {
+            file,
+            line,
+            address,
+            bytes,
+            source,
+            instruction,
+            cycles,
+            branch_taken_cycles,
+        }
+    }
+
+    /// The cycles when things go well and when they don't: branches not taken and taken, and
+    /// indexed accesses within a page and across one
+    /// BRA is always taken, so it's the same both ways
+    fn cycle_range(&self) -> Option<(u32, u32)> {
+        let cycles = self.cycles?;
+        let base = cycles.cycles as u32;
+        Some(match self.branch_taken_cycles {
+            Some(taken) if self.operation() == Some(Operation::BRA) => {
+                (taken as u32, taken as u32)
+            }
+            Some(taken) => (base, taken as u32),
+            None => (base, base + cycles.page_boundary_costs_extra as u32),
+        })
+    }
+
+    fn operation(&self) -> Option<Operation> {
+        self.instruction.map(|Instruction(_, operation)| operation)
+    }
+}
+
+impl fmt::Display for ListingLine {
+    /// e.g. `$8000  BD 00 02  4/5    lda $0200,x`, with bytes past the fourth on the rows below
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cycles = match self.cycle_range() {
+            Some((best, worst)) if best == worst => best.to_string(),
+            Some((best, worst)) => format!("{}/{}", best, worst),
+            None => String::new(),
+        };
+        let mut rows = self.bytes.chunks(BYTES_PER_ROW);
+        let bytes = rows.next().map_or(String::new(), hex);
+        let row = format!("${:04X}  {:<11}  {:<5}  {}", self.address, bytes, cycles, self.source);
+        write!(f, "{}", row.trim_end())?;
+        for (index, row) in rows.enumerate() {
+            let address = self.address as usize + (index + 1) * BYTES_PER_ROW;
+            write!(f, "\n${:04X}  {}", address & 0xFFFF, hex(row))?;
+        }
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    bytes.join(" ")
+}
+
+/// The cycles from a label to the end of the straight-line code after it, which is the next
+/// label or the first branch, jump, return or data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockCycles {
+    pub label: String,
+    pub address: u16,
+    /// With every branch not taken and no page crossings, apart from a branch ending the block
+    pub cycles: u32,
+    /// With the branch ending the block taken and every access that can cross a page crossing
+    pub worst_case_cycles: u32,
+}
+
+/// The lines of an assembly as a listing file, with cycle counts for each instruction and each
+/// labelled block of straight-line code
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Listing {
+    pub lines: Vec<ListingLine>,
+    pub blocks: Vec<BlockCycles>,
+}
+
+impl Listing {
+    /// Totals the blocks after each label, given with the index of the line it's on
+    /// Anonymous labels end blocks but don't start one, having no name to show
+    pub(crate) fn new(lines: Vec<ListingLine>, labels: &[(usize, String)]) -> Listing {
+        let mut blocks = Vec::new();
+        let mut current: Option<BlockCycles> = None;
+        let mut labels = labels.iter().peekable();
+        for (index, line) in lines.iter().enumerate() {
+            while let Some((_, label)) = labels.next_if(|(line, _)| *line == index) {
+                blocks.extend(current.take().filter(|block| block.worst_case_cycles > 0));
+                if !label.starts_with(':') {
+                    current = Some(BlockCycles {
+                        label: label.clone(),
+                        address: line.address,
+                        cycles: 0,
+                        worst_case_cycles: 0,
+                    });
+                }
+            }
+            let block = match &mut current {
+                Some(block) => block,
+                None => continue,
+            };
+            match line.cycle_range() {
+                Some((best, worst)) => {
+                    block.cycles += best;
+                    block.worst_case_cycles += worst;
+                    if ends_block(line) {
+                        blocks.extend(current.take());
+                    }
+                }
+                None if line.bytes.is_empty() => {}
+                None => blocks.extend(current.take().filter(|block| block.worst_case_cycles > 0)),
+            }
+        }
+        blocks.extend(current.filter(|block| block.worst_case_cycles > 0));
+        Listing { lines, blocks }
+    }
+}
+
+fn ends_block(line: &ListingLine) -> bool {
+    line.branch_taken_cycles.is_some()
+        || matches!(
+            line.operation(),
+            Some(Operation::JMP)
+                | Some(Operation::RTS)
+                | Some(Operation::RTI)
+                | Some(Operation::BRK)
+                | Some(Operation::STP)
+        )
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        if !self.blocks.is_empty() {
+            writeln!(f, "\nCycles per block")?;
+        }
+        for block in &self.blocks {
+            let cycles = if block.cycles == block.worst_case_cycles {
+                block.cycles.to_string()
+            } else {
+                format!("{}/{}", block.cycles, block.worst_case_cycles)
+            };
+            writeln!(f, "${:04X}  {:<7}  {}", block.address, cycles, block.label)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::assembler::{assemble, Assembler};
+
+    mod describe_listing {
+        use super::*;
+
+        #[test]
+        fn it_lists_addresses_bytes_and_cycles() {
+            let source = "
+        .org $80FA
+start:  ldx #3          ; load
+@loop:  lda $0200,x
+        dex
+        bne @loop
+        .byte 1, 2, 3, 4, 5";
+            let listing = assemble(source).unwrap().listing;
+            let line = &listing.lines[2];
+            assert_eq!(line.line, 3);
+            assert_eq!(line.address, 0x80FA);
+            assert_eq!(line.bytes, vec![0xA2, 0x03]);
+            let cycles = CycleCount {
+                cycles: 2,
+                page_boundary_costs_extra: false,
+            };
+            assert_eq!(line.cycles, Some(cycles));
+            assert_eq!(listing.lines[5].branch_taken_cycles, Some(4));
+
+            assert_eq!(
+                listing.to_string(),
+                "\
+$0000
+$80FA                              .org $80FA
+$80FA  A2 03        2      start:  ldx #3          ; load
+$80FC  BD 00 02     4/5    @loop:  lda $0200,x
+$80FF  CA           2              dex
+$8100  D0 FA        2/4            bne @loop
+$8102  01 02 03 04                 .byte 1, 2, 3, 4, 5
+$8106  05
+
+Cycles per block
+$80FA  2        start
+$80FC  8/11     start@loop
+"
+            );
+        }
+
+        #[test]
+        fn it_ends_blocks_at_jumps_data_and_the_next_label() {
+            let source = "
+                first:
+                    nop
+                    jsr first
+                    rts
+                    nop
+                second:
+                    nop
+                :   nop
+                    bra :-
+                third:
+                    .byte 0
+                    nop
+                fourth:
+                    sta $1234
+                    jmp first
+            ";
+            let assembler = Assembler::new(CpuVariant::Wdc65C02);
+            let listing = assembler.assemble(source).unwrap().listing;
+            let blocks: Vec<(&str, u32, u32)> = listing
+                .blocks
+                .iter()
+                .map(|block| (block.label.as_str(), block.cycles, block.worst_case_cycles))
+                .collect();
+            assert_eq!(blocks, vec![("first", 14, 14), ("second", 2, 2), ("fourth", 7, 7)]);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use super::expression::Expression;
+use super::lexer::{describe, tokenize, Token, TokenStream};
+use crate::instruction::opcode::opcode_table;
+use crate::instruction::operation::Operation;
+use crate::variant::CpuVariant;
+
+/// The operand as written, before the assembler picks between zero page and absolute modes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Operand {
+    None,
+    Accumulator,
+    Immediate(Expression),
+    Direct(Expression),
+    IndexedX(Expression),
+    IndexedY(Expression),
+    Indirect(Expression),
+    IndirectX(Expression),
+    IndirectY(Expression),
+    /// The zero page address and branch target of BBR and BBS
+    Relative(Expression, Expression),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum DataItem {
+    Expression(Expression),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Statement {
+    Instruction(Operation, Operand),
+    /// `name = expression`
+    Constant(String, Expression),
+    Org(Expression),
+    Byte(Vec<DataItem>),
+    Word(Vec<Expression>),
+    /// Reserves a number of bytes, filled with the value or zero
+    Reserve(Expression, Option<Expression>),
+    /// `.incbin "file"`, replaced by Binary with the file's contents in the first pass
+    IncludeBinary(String),
+    Binary(Vec<u8>),
+}
+
+impl Statement {
+    pub(crate) fn expressions_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Statement::Instruction(_, operand) => match operand {
+                Operand::None | Operand::Accumulator => vec![],
+                Operand::Immediate(e)
+                | Operand::Direct(e)
+                | Operand::IndexedX(e)
+                | Operand::IndexedY(e)
+                | Operand::Indirect(e)
+                | Operand::IndirectX(e)
+                | Operand::IndirectY(e) => vec![e],
+                Operand::Relative(a, b) => vec![a, b],
+            },
+            Statement::Constant(_, e) | Statement::Org(e) => vec![e],
+            Statement::Byte(items) => items
+                .iter_mut()
+                .filter_map(|item| match item {
+                    DataItem::Expression(e) => Some(e),
+                    DataItem::String(_) => None,
+                })
+                .collect(),
+            Statement::Word(expressions) => expressions.iter_mut().collect(),
+            Statement::Reserve(count, fill) => std::iter::once(count).chain(fill).collect(),
+            Statement::IncludeBinary(_) | Statement::Binary(_) => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Line {
+    /// `:` for an anonymous label
+    pub(crate) label: Option<String>,
+    pub(crate) statement: Option<Statement>,
+}
+
+pub(crate) fn parse_line(text: &str, variant: CpuVariant) -> Result<Line, String> {
+    let mut tokens = TokenStream::new(tokenize(text)?);
+
+    // `bne :+` is a branch to an anonymous label rather than a label called bne
+    let mut label = None;
+    let anonymous_reference = matches!(tokens.peek_at(2), Some(Token::Plus) | Some(Token::Minus));
+    if let (Some(Token::Identifier(name)), Some(Token::Colon), false) =
+        (tokens.peek(), tokens.peek_at(1), anonymous_reference)
+    {
+        label = Some(name.clone());
+        tokens.next();
+        tokens.next();
+    } else if tokens.accept(&Token::Colon) {
+        label = Some(String::from(":"));
+    }
+
+    let statement = match tokens.next() {
+        None => None,
+        Some(Token::Identifier(name)) if tokens.accept(&Token::Equals) => {
+            Some(Statement::Constant(name, Expression::parse(&mut tokens)?))
+        }
+        Some(Token::Identifier(name)) if name.starts_with('.') => {
+            Some(parse_directive(&name, &mut tokens)?)
+        }
+        Some(Token::Identifier(name)) => {
+            let operation = parse_mnemonic(&name, variant)
+                .ok_or_else(|| format!("Unknown instruction '{}'", name))?;
+            Some(Statement::Instruction(operation, parse_operand(&mut tokens)?))
+        }
+        token => return Err(format!("Unexpected {}", describe(token.as_ref()))),
+    };
+    tokens.expect_end()?;
+
+    Ok(Line { label, statement })
+}
+
+fn parse_directive(name: &str, tokens: &mut TokenStream) -> Result<Statement, String> {
+    match name.to_ascii_lowercase().as_str() {
+        ".org" => Ok(Statement::Org(Expression::parse(tokens)?)),
+        ".byte" => {
+            let mut items = Vec::new();
+            loop {
+                match tokens.peek() {
+                    Some(Token::String(string)) => {
+                        items.push(DataItem::String(string.clone()));
+                        tokens.next();
+                    }
+                    _ => items.push(DataItem::Expression(Expression::parse(tokens)?)),
+                }
+                if !tokens.accept(&Token::Comma) {
+                    return Ok(Statement::Byte(items));
+                }
+            }
+        }
+        ".word" => Ok(Statement::Word(parse_list(tokens)?)),
+        ".incbin" => Ok(Statement::IncludeBinary(parse_string(tokens)?)),
+        ".res" => {
+            let count = Expression::parse(tokens)?;
+            let fill = if tokens.accept(&Token::Comma) {
+                Some(Expression::parse(tokens)?)
+            } else {
+                None
+            };
+            Ok(Statement::Reserve(count, fill))
+        }
+        _ => Err(format!("Unknown directive '{}'", name)),
+    }
+}
+
+pub(crate) fn parse_string(tokens: &mut TokenStream) -> Result<String, String> {
+    match tokens.next() {
+        Some(Token::String(string)) => Ok(string),
+        token => Err(format!("Expected a string, found {}", describe(token.as_ref()))),
+    }
+}
+
+fn parse_list(tokens: &mut TokenStream) -> Result<Vec<Expression>, String> {
+    let mut expressions = vec![Expression::parse(tokens)?];
+    while tokens.accept(&Token::Comma) {
+        expressions.push(Expression::parse(tokens)?);
+    }
+    Ok(expressions)
+}
+
+/// Mnemonics are matched case-insensitively against the variant's instruction set
+fn parse_mnemonic(name: &str, variant: CpuVariant) -> Option<Operation> {
+    let name = name.to_ascii_uppercase();
+    opcode_table(variant)
+        .iter()
+        .flatten()
+        .map(|info| info.operation)
+        .find(|operation| format!("{:?}", operation) == name)
+}
+
+fn parse_operand(tokens: &mut TokenStream) -> Result<Operand, String> {
+    if tokens.is_empty() {
+        return Ok(Operand::None);
+    }
+    if let (Some(Token::Identifier(name)), None) = (tokens.peek(), tokens.peek_at(1)) {
+        if name.eq_ignore_ascii_case("a") {
+            tokens.next();
+            return Ok(Operand::Accumulator);
+        }
+    }
+    if tokens.accept(&Token::Hash) {
+        return Ok(Operand::Immediate(Expression::parse(tokens)?));
+    }
+
+    if tokens.accept(&Token::OpenParen) {
+        let address = Expression::parse(tokens)?;
+        if tokens.accept(&Token::Comma) {
+            expect_register(tokens, "x")?;
+            tokens.expect(&Token::CloseParen)?;
+            return Ok(Operand::IndirectX(address));
+        }
+        tokens.expect(&Token::CloseParen)?;
+        if tokens.accept(&Token::Comma) {
+            expect_register(tokens, "y")?;
+            return Ok(Operand::IndirectY(address));
+        }
+        return Ok(Operand::Indirect(address));
+    }
+
+    let address = Expression::parse(tokens)?;
+    if !tokens.accept(&Token::Comma) {
+        return Ok(Operand::Direct(address));
+    }
+    match tokens.peek() {
+        Some(Token::Identifier(name)) if name.eq_ignore_ascii_case("x") => {
+            tokens.next();
+            Ok(Operand::IndexedX(address))
+        }
+        Some(Token::Identifier(name)) if name.eq_ignore_ascii_case("y") => {
+            tokens.next();
+            Ok(Operand::IndexedY(address))
+        }
+        _ => Ok(Operand::Relative(address, Expression::parse(tokens)?)),
+    }
+}
+
+fn expect_register(tokens: &mut TokenStream, register: &str) -> Result<(), String> {
+    match tokens.next() {
+        Some(Token::Identifier(name)) if name.eq_ignore_ascii_case(register) => Ok(()),
+        token => Err(format!(
+            "Expected {}, found {}",
+            register.to_ascii_uppercase(),
+            describe(token.as_ref())
+        )),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    mod describe_parse_line {
+        use super::*;
+
+        fn parse(text: &str) -> Line {
+            parse_line(text, CpuVariant::Wdc65C02).unwrap()
+        }
+
+        fn operand(text: &str) -> Operand {
+            match parse(text).statement {
+                Some(Statement::Instruction(_, operand)) => operand,
+                statement => panic!("Not an instruction: {:?}", statement),
+            }
+        }
+
+        fn number(value: i64) -> Expression {
+            Expression::Number(value)
+        }
+
+        #[test]
+        fn it_parses_operand_syntaxes() {
+            assert_eq!(operand("nop"), Operand::None);
+            assert_eq!(operand("asl a"), Operand::Accumulator);
+            assert_eq!(operand("lda #$01"), Operand::Immediate(number(1)));
+            assert_eq!(operand("lda $10"), Operand::Direct(number(0x10)));
+            assert_eq!(operand("lda $10,x"), Operand::IndexedX(number(0x10)));
+            assert_eq!(operand("ldx $1234, Y"), Operand::IndexedY(number(0x1234)));
+            assert_eq!(operand("jmp ($FFFC)"), Operand::Indirect(number(0xFFFC)));
+            assert_eq!(operand("lda ($20,X)"), Operand::IndirectX(number(0x20)));
+            assert_eq!(operand("lda ($20),y"), Operand::IndirectY(number(0x20)));
+            assert_eq!(operand("bbr0 $12,$0300"), Operand::Relative(number(0x12), number(0x300)));
+            assert_eq!(operand("bne :+"), Operand::Direct(Expression::Anonymous(1)));
+            assert_eq!(operand("bne :--"), Operand::Direct(Expression::Anonymous(-2)));
+        }
+
+        #[test]
+        fn it_parses_labels_constants_and_directives() {
+            let line = parse("start: lda #1 ; comment");
+            assert_eq!(line.label, Some(String::from("start")));
+            assert!(matches!(line.statement, Some(Statement::Instruction(Operation::LDA, _))));
+
+            assert_eq!(parse("loop:"), Line { label: Some(String::from("loop")), statement: None });
+            assert_eq!(
+                parse("count = 3").statement,
+                Some(Statement::Constant(String::from("count"), number(3)))
+            );
+            assert_eq!(parse(".ORG $8000").statement, Some(Statement::Org(number(0x8000))));
+            assert_eq!(
+                parse(".byte \"hi\", 0").statement,
+                Some(Statement::Byte(vec![
+                    DataItem::String(String::from("hi")),
+                    DataItem::Expression(number(0))
+                ]))
+            );
+            assert_eq!(
+                parse(".res 4, $EA").statement,
+                Some(Statement::Reserve(number(4), Some(number(0xEA))))
+            );
+        }
+
+        #[test]
+        fn it_rejects_malformed_lines() {
+            assert!(parse_line("lda ($20,y)", CpuVariant::Nmos6502).is_err());
+            assert!(parse_line("lda #", CpuVariant::Nmos6502).is_err());
+            assert!(parse_line("lda $10 $20", CpuVariant::Nmos6502).is_err());
+            assert!(parse_line("stz $10", CpuVariant::Nmos6502).is_err());
+            assert!(parse_line(".bogus", CpuVariant::Nmos6502).is_err());
+        }
+    }
+}
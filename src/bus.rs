@@ -0,0 +1,24 @@
+/// Everything the CPU can address
+/// Reads and writes are the CPU's bus accesses and may have side effects, like acknowledging an
+/// interrupt or advancing a FIFO, while peeks inspect the same address without any, for debuggers
+/// and tests.
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    fn peek(&self, address: u16) -> u8;
+}
+
+/// Flat RAM, the image is mapped from $0000 upwards
+impl Bus for Vec<u8> {
+    fn read(&mut self, address: u16) -> u8 {
+        self[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self[address as usize] = value;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self[address as usize]
+    }
+}
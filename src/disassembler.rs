@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::bus::Bus;
+use crate::instruction::decode_instruction;
+use crate::instruction::operand_mode::OperandMode;
+use crate::instruction::Instruction;
+use crate::ComputerState;
+
+/// One disassembled instruction, or a byte that doesn't decode
+/// Displays as e.g. `$C000  A9 01     LDA #$01`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledLine {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// None for bytes emitted as `.byte`
+    pub instruction: Option<Instruction>,
+    /// The instruction in assembler syntax, e.g. `LDA #$01`
+    pub text: String,
+}
+
+impl fmt::Display for DisassembledLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "${:04X}  {:<8}  {}", self.address, bytes.join(" "), self.text)
+    }
+}
+
+impl<B: Bus> ComputerState<B> {
+    /// Disassembles the range without side effects on the bus
+    /// An instruction that would run past the end of the range is emitted as `.byte` instead
+    pub fn disassemble(&self, range: RangeInclusive<u16>) -> Vec<DisassembledLine> {
+        self.disassemble_with_symbols(range, &HashMap::new())
+    }
+
+    /// Like disassemble, but addresses with a symbol are shown by name
+    pub fn disassemble_with_symbols(
+        &self,
+        range: RangeInclusive<u16>,
+        symbols: &HashMap<u16, String>,
+    ) -> Vec<DisassembledLine> {
+        let end = *range.end() as u32;
+        let mut address = *range.start() as u32;
+        let mut lines = Vec::new();
+        while address <= end {
+            let line = self.disassemble_line(address as u16, end, symbols);
+            address += line.bytes.len() as u32;
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn disassemble_line(
+        &self,
+        address: u16,
+        end: u32,
+        symbols: &HashMap<u16, String>,
+    ) -> DisassembledLine {
+        let opcode = self.peek_byte_from_memory(address as usize);
+        let decoded = decode_instruction(opcode, self.variant)
+            .filter(|Instruction(mode, _)| address as u32 + mode.operand_length() as u32 <= end);
+        let instruction = match decoded {
+            Some(instruction) => instruction,
+            None => {
+                return DisassembledLine {
+                    address,
+                    bytes: vec![opcode],
+                    instruction: None,
+                    text: format!(".byte ${:02X}", opcode),
+                }
+            }
+        };
+
+        let Instruction(mode, operation) = instruction;
+        let bytes: Vec<u8> = (0..=mode.operand_length() as u16)
+            .map(|offset| self.peek_byte_from_memory(address.wrapping_add(offset) as usize))
+            .collect();
+        let operand = format_operand(address, &bytes, instruction, symbols);
+        let text = if operand.is_empty() {
+            format!("{:?}", operation)
+        } else {
+            format!("{:?} {}", operation, operand)
+        };
+        DisassembledLine {
+            address,
+            bytes,
+            instruction: Some(instruction),
+            text,
+        }
+    }
+}
+
+/// Branches are decoded as Immediate, so their offset is resolved to the target here
+fn format_operand(
+    address: u16,
+    bytes: &[u8],
+    Instruction(mode, operation): Instruction,
+    symbols: &HashMap<u16, String>,
+) -> String {
+    let byte = || format_address(bytes[1] as u16, 2, symbols);
+    let word = || format_address(u16::from_le_bytes([bytes[1], bytes[2]]), 4, symbols);
+    let target = |offset: u8| {
+        let next_instruction = address.wrapping_add(bytes.len() as u16);
+        format_address(next_instruction.wrapping_add(offset as i8 as u16), 4, symbols)
+    };
+
+    match mode {
+        OperandMode::Immediate if operation.is_branch() => target(bytes[1]),
+        OperandMode::Immediate => format!("#${:02X}", bytes[1]),
+        OperandMode::Implied | OperandMode::OneCycleImplied => String::new(),
+        OperandMode::Accumulator => String::from("A"),
+        OperandMode::ZeroPage => byte(),
+        OperandMode::ZeroPageX => format!("{},X", byte()),
+        OperandMode::ZeroPageY => format!("{},Y", byte()),
+        OperandMode::ZeroPageIndirect => format!("({})", byte()),
+        OperandMode::ZeroPageRelative => format!("{},{}", byte(), target(bytes[2])),
+        OperandMode::IndirectX => format!("({},X)", byte()),
+        OperandMode::IndirectY => format!("({}),Y", byte()),
+        OperandMode::Absolute | OperandMode::EightCycleAbsolute => word(),
+        OperandMode::AbsoluteX => format!("{},X", word()),
+        OperandMode::AbsoluteY => format!("{},Y", word()),
+        OperandMode::Indirect => format!("({})", word()),
+        OperandMode::AbsoluteIndirectX => format!("({},X)", word()),
+    }
+}
+
+fn format_address(address: u16, digits: usize, symbols: &HashMap<u16, String>) -> String {
+    match symbols.get(&address) {
+        Some(name) => name.clone(),
+        None => format!("${:0width$X}", address, width = digits),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use nestegg_macros::asm6502;
+    use crate::variant::CpuVariant;
+
+    mod describe_disassembler {
+        use super::*;
+
+        fn state_with_program(start: usize, program: &[u8], variant: CpuVariant) -> ComputerState {
+            let mut image = vec![0; 0x10000];
+            image[start..start + program.len()].copy_from_slice(program);
+            ComputerState::initialize_from_image_with_variant(image, variant)
+        }
+
+        fn render(lines: &[DisassembledLine]) -> Vec<String> {
+            lines.iter().map(|line| line.to_string()).collect()
+        }
+
+        #[test]
+        fn it_disassembles_each_addressing_mode() {
+            let program = asm6502! {
+                .org $C000;
+                lda #$01; asl a; lda $10,x; ldx $1234,y; jmp ($FFFC); lda ($20,x); sta ($22),y;
+                bne *; bpl $BF93
+            };
+            let state = state_with_program(0xC000, &program, CpuVariant::Nmos6502);
+
+            assert_eq!(
+                render(&state.disassemble(0xC000..=0xC012)),
+                vec![
+                    "$C000  A9 01     LDA #$01",
+                    "$C002  0A        ASL A",
+                    "$C003  B5 10     LDA $10,X",
+                    "$C005  BE 34 12  LDX $1234,Y",
+                    "$C008  6C FC FF  JMP ($FFFC)",
+                    "$C00B  A1 20     LDA ($20,X)",
+                    "$C00D  91 22     STA ($22),Y",
+                    "$C00F  D0 FE     BNE $C00F",
+                    "$C011  10 80     BPL $BF93",
+                ]
+            );
+        }
+
+        #[test]
+        fn it_disassembles_cmos_modes() {
+            let program = asm6502! {
+                [Wdc65C02]
+                .org $0200;
+                lda ($10); jmp ($2000,x);
+            loop:
+                bbs0 $12, loop+6; bra loop
+            };
+            let state = state_with_program(0x0200, &program, CpuVariant::Wdc65C02);
+
+            assert_eq!(
+                render(&state.disassemble(0x0200..=0x0209)),
+                vec![
+                    "$0200  B2 10     LDA ($10)",
+                    "$0202  7C 00 20  JMP ($2000,X)",
+                    "$0205  8F 12 03  BBS0 $12,$020B",
+                    "$0208  80 FB     BRA $0205",
+                ]
+            );
+        }
+
+        #[test]
+        fn it_emits_undecodable_bytes_as_data() {
+            let state = state_with_program(0x0000, &[0x02, 0xEA, 0xAD, 0x00], CpuVariant::Nmos6502);
+
+            let lines = state.disassemble(0x0000..=0x0003);
+            assert_eq!(
+                render(&lines),
+                vec![
+                    "$0000  02        .byte $02",
+                    "$0001  EA        NOP",
+                    "$0002  AD        .byte $AD",
+                    "$0003  00        BRK",
+                ]
+            );
+            assert_eq!(lines[0].instruction, None);
+        }
+
+        #[test]
+        fn it_names_addresses_with_symbols() {
+            let program = asm6502!(.org $8000; jsr wait; sta $FE; rts; wait: beq wait);
+            let state = state_with_program(0x8000, &program, CpuVariant::Nmos6502);
+            let symbols = HashMap::from([
+                (0x8006, String::from("wait")),
+                (0x00FE, String::from("counter")),
+            ]);
+
+            assert_eq!(
+                render(&state.disassemble_with_symbols(0x8000..=0x8007, &symbols)),
+                vec![
+                    "$8000  20 06 80  JSR wait",
+                    "$8003  85 FE     STA counter",
+                    "$8005  60        RTS",
+                    "$8006  F0 FE     BEQ wait",
+                ]
+            );
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::instruction::Instruction;
+
+/// Why an instruction couldn't be executed, and where
+/// program_counter is the address of the opcode, and cycles the count when it was fetched
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CpuError {
+    /// The opcode isn't an instruction on this CPU variant, e.g. one of the NMOS JAM opcodes
+    UnknownOpcode {
+        opcode: u8,
+        program_counter: u16,
+        cycles: u32,
+    },
+    /// The instruction decoded but has no cycle timings, which is a bug in the emulator
+    MissingTimings {
+        opcode: u8,
+        instruction: Instruction,
+        program_counter: u16,
+        cycles: u32,
+    },
+    /// An operation got an operand it can't use, which is a bug in the emulator
+    InvalidOperand {
+        opcode: u8,
+        instruction: Instruction,
+        program_counter: u16,
+        cycles: u32,
+        reason: &'static str,
+    },
+}
+
+impl CpuError {
+    pub fn opcode(&self) -> u8 {
+        match *self {
+            CpuError::UnknownOpcode { opcode, .. }
+            | CpuError::MissingTimings { opcode, .. }
+            | CpuError::InvalidOperand { opcode, .. } => opcode,
+        }
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        match *self {
+            CpuError::UnknownOpcode { program_counter, .. }
+            | CpuError::MissingTimings { program_counter, .. }
+            | CpuError::InvalidOperand { program_counter, .. } => program_counter,
+        }
+    }
+
+    pub fn cycles(&self) -> u32 {
+        match *self {
+            CpuError::UnknownOpcode { cycles, .. }
+            | CpuError::MissingTimings { cycles, .. }
+            | CpuError::InvalidOperand { cycles, .. } => cycles,
+        }
+    }
+
+    /// The decoded instruction, None if the opcode didn't decode
+    pub fn instruction(&self) -> Option<Instruction> {
+        match *self {
+            CpuError::UnknownOpcode { .. } => None,
+            CpuError::MissingTimings { instruction, .. }
+            | CpuError::InvalidOperand { instruction, .. } => Some(instruction),
+        }
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { opcode, .. } => write!(f, "unknown opcode ${:02X}", opcode)?,
+            CpuError::MissingTimings {
+                opcode,
+                instruction: Instruction(mode, op),
+                ..
+            } => write!(f, "no cycle timings for {:?} {:?} (${:02X})", op, mode, opcode)?,
+            CpuError::InvalidOperand {
+                opcode,
+                instruction: Instruction(mode, op),
+                reason,
+                ..
+            } => write!(f, "{:?} {:?} (${:02X}): {}", op, mode, opcode, reason)?,
+        }
+        write!(
+            f,
+            " at ${:04X} after {} cycles",
+            self.program_counter(),
+            self.cycles()
+        )
+    }
+}
+
+impl Error for CpuError {}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::instruction::operand_mode::OperandMode;
+    use crate::instruction::operation::Operation;
+
+    mod describe_cpu_error {
+        use super::*;
+
+        #[test]
+        fn it_describes_where_it_happened() {
+            let error = CpuError::UnknownOpcode {
+                opcode: 0x02,
+                program_counter: 0x1234,
+                cycles: 56,
+            };
+            assert_eq!(error.to_string(), "unknown opcode $02 at $1234 after 56 cycles");
+
+            let error = CpuError::InvalidOperand {
+                opcode: 0x4C,
+                instruction: Instruction(OperandMode::Absolute, Operation::JMP),
+                program_counter: 0x8000,
+                cycles: 7,
+                reason: "Jump must have Address-type operand",
+            };
+            assert_eq!(
+                error.to_string(),
+                "JMP Absolute ($4C): Jump must have Address-type operand at $8000 after 7 cycles"
+            );
+            assert_eq!(error.opcode(), 0x4C);
+            assert_eq!(error.cycles(), 7);
+            assert_eq!(
+                error.instruction(),
+                Some(Instruction(OperandMode::Absolute, Operation::JMP))
+            );
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+use crate::bus::Bus;
+use crate::ComputerState;
+
+/// The data bytes per Intel HEX and S-record line, and per hexdump row
+const BYTES_PER_RECORD: usize = 16;
+
+impl<B: Bus> ComputerState<B> {
+    /// The bytes in the range, read without side effects on the bus
+    pub fn export_raw(&self, range: RangeInclusive<u16>) -> Vec<u8> {
+        range
+            .map(|address| self.peek_byte_from_memory(address as usize))
+            .collect()
+    }
+
+    /// A Commodore .prg: the start of the range, little-endian, then the bytes
+    pub fn export_prg(&self, range: RangeInclusive<u16>) -> Vec<u8> {
+        let mut file = range.start().to_le_bytes().to_vec();
+        file.extend(self.export_raw(range));
+        file
+    }
+
+    /// Intel HEX data records for the range, with a start linear address record if there's a
+    /// start address
+    pub fn export_intel_hex(&self, range: RangeInclusive<u16>, start: Option<u16>) -> String {
+        let mut file = String::new();
+        let mut address = *range.start();
+        for chunk in self.export_raw(range).chunks(BYTES_PER_RECORD) {
+            file.push_str(&intel_hex_record(address, 0x00, chunk));
+            address = address.wrapping_add(chunk.len() as u16);
+        }
+        if let Some(start) = start {
+            file.push_str(&intel_hex_record(0, 0x05, &(start as u32).to_be_bytes()));
+        }
+        file.push_str(&intel_hex_record(0, 0x01, &[]));
+        file
+    }
+
+    /// Motorola S-records for the range: a header, S1 data records, an S5 count and an S9 with
+    /// the start address, or zero without one
+    pub fn export_s_records(&self, range: RangeInclusive<u16>, start: Option<u16>) -> String {
+        let mut file = s_record(b'0', 0, b"nestegg");
+        let mut address = *range.start();
+        let mut count = 0;
+        for chunk in self.export_raw(range).chunks(BYTES_PER_RECORD) {
+            file.push_str(&s_record(b'1', address, chunk));
+            address = address.wrapping_add(chunk.len() as u16);
+            count += 1;
+        }
+        // S5 only counts up to $FFFF records, which 64K in 16 byte records can't reach
+        file.push_str(&s_record(b'5', count, &[]));
+        file.push_str(&s_record(b'9', start.unwrap_or(0), &[]));
+        file
+    }
+
+    /// A classic hexdump with 16 bytes per row and the printable ones in an ASCII column
+    ///
+    /// ```text
+    /// $C000  A9 01 8D 00 03 00 48 65  6C 6C 6F 00 00 00 00 00  |......Hello.....|
+    /// ```
+    pub fn hexdump(&self, range: RangeInclusive<u16>) -> String {
+        let mut dump = String::new();
+        let mut address = *range.start();
+        for row in self.export_raw(range).chunks(BYTES_PER_RECORD) {
+            let mut hex = String::new();
+            for (index, byte) in row.iter().enumerate() {
+                let separator = if index == BYTES_PER_RECORD / 2 { "  " } else { " " };
+                if index > 0 {
+                    hex.push_str(separator);
+                }
+                write!(hex, "{:02X}", byte).unwrap();
+            }
+            let ascii: String = row
+                .iter()
+                .map(|&b| if (0x20..0x7F).contains(&b) { b as char } else { '.' })
+                .collect();
+            writeln!(dump, "${:04X}  {:<48}  |{}|", address, hex, ascii).unwrap();
+            address = address.wrapping_add(row.len() as u16);
+        }
+        dump
+    }
+}
+
+/// `:`, then the length, address, type, data and a checksum making them all sum to zero
+fn intel_hex_record(address: u16, record_type: u8, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&address.to_be_bytes());
+    bytes.push(record_type);
+    bytes.extend_from_slice(data);
+    let checksum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_sub(*b));
+    bytes.push(checksum);
+    format!(":{}\n", hex_string(&bytes))
+}
+
+/// `S` and the type, then the count, address, data and a checksum making them all sum to $FF
+fn s_record(record_type: u8, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8 + 3];
+    bytes.extend_from_slice(&address.to_be_bytes());
+    bytes.extend_from_slice(data);
+    let checksum = !bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    bytes.push(checksum);
+    format!("S{}{}\n", record_type as char, hex_string(&bytes))
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::loader::{ImageFormat, LoadedImage, Segment};
+
+    mod describe_export {
+        use super::*;
+
+        fn state() -> ComputerState {
+            let mut image = vec![0; 0x10000];
+            for (index, byte) in image[0xC000..0xC020].iter_mut().enumerate() {
+                *byte = index as u8 * 3;
+            }
+            image[0xC010..0xC015].copy_from_slice(b"Hello");
+            ComputerState::initialize_from_image(image)
+        }
+
+        fn segments(file: &[u8], format: ImageFormat) -> LoadedImage {
+            LoadedImage::parse(file, format).unwrap()
+        }
+
+        #[test]
+        fn it_exports_raw_and_prg_files() {
+            let state = state();
+            assert_eq!(state.export_raw(0xC001..=0xC003), vec![3, 6, 9]);
+            assert_eq!(state.export_prg(0xC001..=0xC002), vec![0x01, 0xC0, 3, 6]);
+
+            let prg = segments(&state.export_prg(0xC000..=0xC01F), ImageFormat::Prg);
+            assert_eq!(prg.segments[0].data, state.export_raw(0xC000..=0xC01F));
+        }
+
+        #[test]
+        fn it_exports_intel_hex() {
+            let state = state();
+            assert_eq!(
+                state.export_intel_hex(0xC001..=0xC003, Some(0xC000)),
+                ":03C001000306092A\n:040000050000C00037\n:00000001FF\n"
+            );
+
+            let file = state.export_intel_hex(0xC000..=0xC01F, None);
+            let loaded = segments(file.as_bytes(), ImageFormat::IntelHex);
+            assert_eq!(loaded.segments.len(), 2);
+            assert_eq!(loaded.segments[1].address, 0xC010);
+            assert_eq!(loaded.segments[1].data[..5], b"Hello"[..]);
+            assert_eq!(loaded.start, None);
+        }
+
+        #[test]
+        fn it_exports_s_records() {
+            let state = state();
+            let file = state.export_s_records(0xFFF8..=0xFFFF, Some(0x1234));
+            assert_eq!(
+                file,
+                "S00A00006E65737465676708\nS10BFFF80000000000000000FD\nS5030001FB\nS9031234B6\n"
+            );
+
+            let file = state.export_s_records(0xC000..=0xC01F, Some(0xC000));
+            let loaded = segments(file.as_bytes(), ImageFormat::SRecord);
+            assert_eq!(
+                loaded.segments[0],
+                Segment {
+                    address: 0xC000,
+                    data: state.export_raw(0xC000..=0xC00F)
+                }
+            );
+            assert_eq!(loaded.start, Some(0xC000));
+        }
+
+        #[test]
+        fn it_dumps_hex_with_an_ascii_column() {
+            let state = state();
+            assert_eq!(
+                state.hexdump(0xC00C..=0xC01E),
+                "\
+$C00C  24 27 2A 2D 48 65 6C 6C  6F 3F 42 45 48 4B 4E 51  |$'*-Hello?BEHKNQ|
+$C01C  54 57 5A                                          |TWZ|
+"
+            );
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+use crate::bus::Bus;
+use crate::io_port::IoPort;
+use crate::tick::Sequence;
+use crate::{ComputerState, RegisterFile};
+
+/// A point the history can rewind to, as it was before a step
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HistoryPoint {
+    pub cycles: u32,
+    pub registers: RegisterFile,
+}
+
+/// Everything but the bus, which is small enough to copy before every step
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Cpu {
+    registers: RegisterFile,
+    cycles: u32,
+    io_port: Option<IoPort>,
+    irq_line: bool,
+    nmi_line: bool,
+    nmi_pending: bool,
+    reset_pending: bool,
+    waiting_for_interrupt: bool,
+    stopped: bool,
+    branch_taken: bool,
+    sequence: Option<Sequence>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Checkpoint {
+    cpu: Cpu,
+    /// The addresses written since, with the values they held before, oldest first
+    overwritten: Vec<(u16, u8)>,
+}
+
+impl Checkpoint {
+    fn size(&self) -> usize {
+        size_of::<Checkpoint>() + self.overwritten.len() * size_of::<(u16, u8)>()
+    }
+}
+
+/// The most recent checkpoints that fit in the memory budget, oldest first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct History {
+    checkpoints: VecDeque<Checkpoint>,
+    budget: usize,
+    size: usize,
+}
+
+impl History {
+    fn new(budget: usize) -> History {
+        History {
+            checkpoints: VecDeque::new(),
+            budget,
+            size: 0,
+        }
+    }
+
+    fn push(&mut self, cpu: Cpu) {
+        let checkpoint = Checkpoint {
+            cpu,
+            overwritten: Vec::new(),
+        };
+        self.size += checkpoint.size();
+        self.checkpoints.push_back(checkpoint);
+        self.trim();
+    }
+
+    /// Writes before the first checkpoint can't be rewound past, so they aren't kept
+    pub(crate) fn record_write(&mut self, address: u16, old_value: u8) {
+        if let Some(checkpoint) = self.checkpoints.back_mut() {
+            checkpoint.overwritten.push((address, old_value));
+            self.size += size_of::<(u16, u8)>();
+            self.trim();
+        }
+    }
+
+    fn pop(&mut self) -> Option<Checkpoint> {
+        let checkpoint = self.checkpoints.pop_back()?;
+        self.size -= checkpoint.size();
+        Some(checkpoint)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.checkpoints.clear();
+        self.size = 0;
+    }
+
+    /// Drops the oldest checkpoints until the rest fit, but always keeps the newest
+    fn trim(&mut self) {
+        while self.size > self.budget && self.checkpoints.len() > 1 {
+            if let Some(checkpoint) = self.checkpoints.pop_front() {
+                self.size -= checkpoint.size();
+            }
+        }
+    }
+}
+
+impl<B: Bus> ComputerState<B> {
+    /// Starts recording the state before every step, so steps can be undone
+    /// Each step keeps a copy of the registers and the bytes it overwrote, and the oldest steps
+    /// are forgotten once they take more than the budget in bytes. Recording again starts over
+    /// Rewinding writes the bytes back through the bus as they peeked before, so devices see
+    /// those writes, and changes made through the memory field directly aren't undone. Tracked
+    /// writes which are undone are forgotten, though older ones the tracker already dropped
+    /// don't come back
+    pub fn record_history(&mut self, budget: usize) {
+        self.history = Some(History::new(budget));
+    }
+
+    /// Stops recording and forgets the history
+    pub fn stop_recording_history(&mut self) {
+        self.history = None;
+    }
+
+    /// The points the history can rewind to, oldest first
+    pub fn history(&self) -> Vec<HistoryPoint> {
+        match &self.history {
+            Some(history) => history
+                .checkpoints
+                .iter()
+                .map(|checkpoint| HistoryPoint {
+                    cycles: checkpoint.cpu.cycles,
+                    registers: checkpoint.cpu.registers,
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Undoes the last step, returning false if there's no history to undo it with
+    pub fn step_back(&mut self) -> bool {
+        self.rewind_steps(1) == 1
+    }
+
+    /// Undoes up to the number of steps, as many as the history goes back, and returns how many
+    pub fn rewind_steps(&mut self, steps: usize) -> usize {
+        let available = self.history.as_ref().map_or(0, |h| h.checkpoints.len());
+        let steps = steps.min(available);
+        self.rewind(steps);
+        steps
+    }
+
+    /// Rewinds to the latest point at least the number of cycles back
+    /// Returns false and leaves the state alone if the history doesn't go back that far
+    pub fn rewind_cycles(&mut self, cycles: u32) -> bool {
+        let target = match self.cycles.checked_sub(cycles) {
+            Some(target) => target,
+            None => return false,
+        };
+        let steps = self.history().iter().rev().position(|point| point.cycles <= target);
+        match steps {
+            Some(index) => {
+                self.rewind(index + 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rewinds to the latest point taken at exactly the cycle count, returning false and leaving
+    /// the state alone if there isn't one
+    pub fn rewind_to(&mut self, cycles: u32) -> bool {
+        let steps = self.history().iter().rev().position(|point| point.cycles == cycles);
+        match steps {
+            Some(index) => {
+                self.rewind(index + 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Takes a checkpoint before a step
+    pub(crate) fn record_step(&mut self) {
+        let cpu = Cpu {
+            registers: self.registers,
+            cycles: self.cycles,
+            io_port: self.io_port,
+            irq_line: self.irq_line,
+            nmi_line: self.nmi_line,
+            nmi_pending: self.nmi_pending,
+            reset_pending: self.reset_pending,
+            waiting_for_interrupt: self.waiting_for_interrupt,
+            stopped: self.stopped,
+            branch_taken: self.branch_taken,
+            sequence: self.sequence,
+        };
+        if let Some(history) = &mut self.history {
+            history.push(cpu);
+        }
+    }
+
+    /// Forgets the checkpoint of a step which failed without writing anything, since there's
+    /// nothing to undo
+    pub(crate) fn discard_failed_step(&mut self) {
+        if let Some(history) = &mut self.history {
+            if history.checkpoints.back().is_some_and(|c| c.overwritten.is_empty()) {
+                history.pop();
+            }
+        }
+    }
+
+    /// Writes the overwritten bytes back through the bus, newest first, and restores the CPU as
+    /// it was at the oldest of the checkpoints
+    /// Each byte written back undoes the newest tracked write to the address, since the history
+    /// and the tracker both see every write
+    fn rewind(&mut self, steps: usize) {
+        let mut history = match self.history.take() {
+            Some(history) => history,
+            None => return,
+        };
+        let mut cpu = None;
+        for _ in 0..steps {
+            let checkpoint = match history.pop() {
+                Some(checkpoint) => checkpoint,
+                None => break,
+            };
+            for &(address, value) in checkpoint.overwritten.iter().rev() {
+                self.memory.write(address, value);
+                if let Some(tracker) = &mut self.write_tracker {
+                    tracker.undo(address);
+                }
+            }
+            cpu = Some(checkpoint.cpu);
+        }
+        if let Some(cpu) = cpu {
+            self.registers = cpu.registers;
+            self.cycles = cpu.cycles;
+            self.io_port = cpu.io_port;
+            self.irq_line = cpu.irq_line;
+            self.nmi_line = cpu.nmi_line;
+            self.nmi_pending = cpu.nmi_pending;
+            self.reset_pending = cpu.reset_pending;
+            self.waiting_for_interrupt = cpu.waiting_for_interrupt;
+            self.stopped = cpu.stopped;
+            self.branch_taken = cpu.branch_taken;
+            self.sequence = cpu.sequence;
+        }
+        self.history = Some(history);
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::variant::CpuVariant;
+    use nestegg_macros::asm6502;
+
+    mod describe_history {
+        use super::*;
+
+        fn recording(budget: usize) -> ComputerState {
+            let program = asm6502! {
+                .org $0200;
+                ldx #0;
+                loop: txa; sta $0300,x; jsr count; inx; bne loop;
+                count: inc $10; rts
+            };
+            let mut state = ComputerState::with_program(CpuVariant::Nmos6502, 0x0200, &program);
+            state.record_history(budget);
+            state
+        }
+
+        #[test]
+        fn it_steps_back_exactly() {
+            let mut state = recording(1 << 20);
+            let start = state.clone();
+            state.multiple_steps(40).unwrap();
+            let middle = state.clone();
+            state.multiple_steps(40).unwrap();
+            state.set_irq_line(true);
+            state.step().unwrap();
+
+            assert!(state.step_back());
+            assert_eq!(state.rewind_steps(40), 40);
+            assert_eq!(state.history().len(), 40);
+            assert!(state.registers == middle.registers && state.memory == middle.memory);
+            assert_eq!(state.cycles, middle.cycles);
+
+            assert_eq!(state.rewind_steps(100), 40);
+            assert!(state == start);
+            assert!(!state.step_back());
+        }
+
+        #[test]
+        fn it_rewinds_by_cycles() {
+            let mut state = recording(1 << 20);
+            state.multiple_steps(3).unwrap();
+            // LDX #0, TXA and STA abs,X take 2, 2 and 5 cycles
+            assert_eq!(state.cycles, 9);
+
+            assert!(!state.rewind_cycles(10));
+            assert!(state.rewind_cycles(3));
+            assert_eq!(state.cycles, 4);
+            assert!(state.rewind_cycles(1));
+            assert_eq!(state.cycles, 2);
+        }
+
+        #[test]
+        fn it_rewinds_to_any_point() {
+            let mut state = recording(1 << 20);
+            state.multiple_steps(10).unwrap();
+            let points = state.history();
+            assert_eq!(points.len(), 10);
+            assert_eq!(points[0].cycles, 0);
+            assert_eq!(points[0].registers.program_counter(), 0x0200);
+
+            assert!(!state.rewind_to(points[4].cycles + 1));
+            assert!(state.rewind_to(points[4].cycles));
+            assert_eq!(state.registers, points[4].registers);
+            assert_eq!(state.history(), points[..4].to_vec());
+        }
+
+        #[test]
+        fn it_forgets_the_oldest_steps_over_the_budget() {
+            let mut state = recording(10 * size_of::<Checkpoint>());
+            state.multiple_steps(100).unwrap();
+
+            let points = state.history();
+            assert!(points.len() < 10);
+            assert_eq!(state.rewind_steps(100), points.len());
+            assert_eq!(state.cycles, points[0].cycles);
+
+            state.stop_recording_history();
+            state.step().unwrap();
+            assert!(!state.step_back());
+        }
+    }
+}
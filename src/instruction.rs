@@ -1,252 +1,552 @@
+pub mod opcode;
 pub mod operand_mode;
 pub mod operation;
 
 use operand_mode::OperandMode;
 use operation::Operation;
 
-#[derive(Debug)]
+use crate::variant::CpuVariant;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Instruction(pub OperandMode, pub Operation);
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct CycleCount {
     pub cycles: u8,
     pub page_boundary_costs_extra: bool,
 }
 
-pub fn decode_instruction(instruction: u8) -> Result<Instruction, &'static str> {
+pub const fn decode_instruction(
+    instruction: u8,
+    variant: CpuVariant,
+) -> Option<Instruction> {
+    if variant.is_cmos() {
+        decode_cmos_instruction(instruction, variant)
+    } else {
+        decode_nmos_instruction(instruction)
+    }
+}
+
+const fn decode_nmos_instruction(instruction: u8) -> Option<Instruction> {
     match instruction {
-        0x69 => Ok(Instruction(OperandMode::Immediate,   Operation::ADC)),
-        0x65 => Ok(Instruction(OperandMode::ZeroPage,    Operation::ADC)),
-        0x75 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::ADC)),
-        0x6D => Ok(Instruction(OperandMode::Absolute,    Operation::ADC)),
-        0x7D => Ok(Instruction(OperandMode::AbsoluteX,   Operation::ADC)),
-        0x79 => Ok(Instruction(OperandMode::AbsoluteY,   Operation::ADC)),
-        0x61 => Ok(Instruction(OperandMode::IndirectX,   Operation::ADC)),
-        0x71 => Ok(Instruction(OperandMode::IndirectY,   Operation::ADC)),
-        0x29 => Ok(Instruction(OperandMode::Immediate,   Operation::AND)),
-        0x25 => Ok(Instruction(OperandMode::ZeroPage,    Operation::AND)),
-        0x35 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::AND)),
-        0x2D => Ok(Instruction(OperandMode::Absolute,    Operation::AND)),
-        0x3D => Ok(Instruction(OperandMode::AbsoluteX,   Operation::AND)),
-        0x39 => Ok(Instruction(OperandMode::AbsoluteY,   Operation::AND)),
-        0x21 => Ok(Instruction(OperandMode::IndirectX,   Operation::AND)),
-        0x31 => Ok(Instruction(OperandMode::IndirectY,   Operation::AND)),
-        0x0A => Ok(Instruction(OperandMode::Accumulator, Operation::ASL)),
-        0x06 => Ok(Instruction(OperandMode::ZeroPage,    Operation::ASL)),
-        0x16 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::ASL)),
-        0x0E => Ok(Instruction(OperandMode::Absolute,    Operation::ASL)),
-        0x1E => Ok(Instruction(OperandMode::AbsoluteX,   Operation::ASL)),
-        0x90 => Ok(Instruction(OperandMode::Immediate,   Operation::BCC)),
-        0xB0 => Ok(Instruction(OperandMode::Immediate,   Operation::BCS)),
-        0xF0 => Ok(Instruction(OperandMode::Immediate,   Operation::BEQ)),
-        0x24 => Ok(Instruction(OperandMode::ZeroPage,    Operation::BIT)),
-        0x2C => Ok(Instruction(OperandMode::Absolute,    Operation::BIT)),
-        0x30 => Ok(Instruction(OperandMode::Immediate,   Operation::BMI)),
-        0xD0 => Ok(Instruction(OperandMode::Immediate,   Operation::BNE)),
-        0x10 => Ok(Instruction(OperandMode::Immediate,   Operation::BPL)),
-        0x00 => Ok(Instruction(OperandMode::Implied,     Operation::BRK)),
-        0x50 => Ok(Instruction(OperandMode::Immediate,   Operation::BVC)),
-        0x70 => Ok(Instruction(OperandMode::Immediate,   Operation::BVS)),
-        0x18 => Ok(Instruction(OperandMode::Implied,     Operation::CLC)),
-        0xD8 => Ok(Instruction(OperandMode::Implied,     Operation::CLD)),
-        0x58 => Ok(Instruction(OperandMode::Implied,     Operation::CLI)),
-        0xB8 => Ok(Instruction(OperandMode::Implied,     Operation::CLV)),
-        0xC9 => Ok(Instruction(OperandMode::Immediate,   Operation::CMP)),
-        0xC5 => Ok(Instruction(OperandMode::ZeroPage,    Operation::CMP)),
-        0xD5 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::CMP)),
-        0xCD => Ok(Instruction(OperandMode::Absolute,    Operation::CMP)),
-        0xDD => Ok(Instruction(OperandMode::AbsoluteX,   Operation::CMP)),
-        0xD9 => Ok(Instruction(OperandMode::AbsoluteY,   Operation::CMP)),
-        0xC1 => Ok(Instruction(OperandMode::IndirectX,   Operation::CMP)),
-        0xD1 => Ok(Instruction(OperandMode::IndirectY,   Operation::CMP)),
-        0xE0 => Ok(Instruction(OperandMode::Immediate,   Operation::CPX)),
-        0xE4 => Ok(Instruction(OperandMode::ZeroPage,    Operation::CPX)),
-        0xEC => Ok(Instruction(OperandMode::Absolute,    Operation::CPX)),
-        0xC0 => Ok(Instruction(OperandMode::Immediate,   Operation::CPY)),
-        0xC4 => Ok(Instruction(OperandMode::ZeroPage,    Operation::CPY)),
-        0xCC => Ok(Instruction(OperandMode::Absolute,    Operation::CPY)),
-        0xC6 => Ok(Instruction(OperandMode::ZeroPage,    Operation::DEC)),
-        0xD6 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::DEC)),
-        0xCE => Ok(Instruction(OperandMode::Absolute,    Operation::DEC)),
-        0xDE => Ok(Instruction(OperandMode::AbsoluteX,   Operation::DEC)),
-        0xCA => Ok(Instruction(OperandMode::Implied,     Operation::DEX)),
-        0x88 => Ok(Instruction(OperandMode::Implied,     Operation::DEY)),
-        0x49 => Ok(Instruction(OperandMode::Immediate,   Operation::EOR)),
-        0x45 => Ok(Instruction(OperandMode::ZeroPage,    Operation::EOR)),
-        0x55 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::EOR)),
-        0x4D => Ok(Instruction(OperandMode::Absolute,    Operation::EOR)),
-        0x5D => Ok(Instruction(OperandMode::AbsoluteX,   Operation::EOR)),
-        0x59 => Ok(Instruction(OperandMode::AbsoluteY,   Operation::EOR)),
-        0x41 => Ok(Instruction(OperandMode::IndirectX,   Operation::EOR)),
-        0x51 => Ok(Instruction(OperandMode::IndirectY,   Operation::EOR)),
-        0xE6 => Ok(Instruction(OperandMode::ZeroPage,    Operation::INC)),
-        0xF6 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::INC)),
-        0xEE => Ok(Instruction(OperandMode::Absolute,    Operation::INC)),
-        0xFE => Ok(Instruction(OperandMode::AbsoluteX,   Operation::INC)),
-        0xE8 => Ok(Instruction(OperandMode::Implied,     Operation::INX)),
-        0xC8 => Ok(Instruction(OperandMode::Implied,     Operation::INY)),
-        0x4C => Ok(Instruction(OperandMode::Absolute,    Operation::JMP)),
-        0x6C => Ok(Instruction(OperandMode::Indirect,    Operation::JMP)),
-        0x20 => Ok(Instruction(OperandMode::Absolute,    Operation::JSR)),
-        0xA9 => Ok(Instruction(OperandMode::Immediate,   Operation::LDA)),
-        0xA5 => Ok(Instruction(OperandMode::ZeroPage,    Operation::LDA)),
-        0xB5 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::LDA)),
-        0xAD => Ok(Instruction(OperandMode::Absolute,    Operation::LDA)),
-        0xBD => Ok(Instruction(OperandMode::AbsoluteX,   Operation::LDA)),
-        0xB9 => Ok(Instruction(OperandMode::AbsoluteY,   Operation::LDA)),
-        0xA1 => Ok(Instruction(OperandMode::IndirectX,   Operation::LDA)),
-        0xB1 => Ok(Instruction(OperandMode::IndirectY,   Operation::LDA)),
-        0xA2 => Ok(Instruction(OperandMode::Immediate,   Operation::LDX)),
-        0xA6 => Ok(Instruction(OperandMode::ZeroPage,    Operation::LDX)),
-        0xB6 => Ok(Instruction(OperandMode::ZeroPageY,   Operation::LDX)),
-        0xAE => Ok(Instruction(OperandMode::Absolute,    Operation::LDX)),
-        0xBE => Ok(Instruction(OperandMode::AbsoluteY,   Operation::LDX)),
-        0xA0 => Ok(Instruction(OperandMode::Immediate,   Operation::LDY)),
-        0xA4 => Ok(Instruction(OperandMode::ZeroPage,    Operation::LDY)),
-        0xB4 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::LDY)),
-        0xAC => Ok(Instruction(OperandMode::Absolute,    Operation::LDY)),
-        0xBC => Ok(Instruction(OperandMode::AbsoluteX,   Operation::LDY)),
-        0x4A => Ok(Instruction(OperandMode::Accumulator, Operation::LSR)),
-        0x46 => Ok(Instruction(OperandMode::ZeroPage,    Operation::LSR)),
-        0x56 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::LSR)),
-        0x4E => Ok(Instruction(OperandMode::Absolute,    Operation::LSR)),
-        0x5E => Ok(Instruction(OperandMode::AbsoluteX,   Operation::LSR)),
-        0xEA => Ok(Instruction(OperandMode::Implied,     Operation::NOP)),
-        0x09 => Ok(Instruction(OperandMode::Immediate,   Operation::ORA)),
-        0x05 => Ok(Instruction(OperandMode::ZeroPage,    Operation::ORA)),
-        0x15 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::ORA)),
-        0x0D => Ok(Instruction(OperandMode::Absolute,    Operation::ORA)),
-        0x1D => Ok(Instruction(OperandMode::AbsoluteX,   Operation::ORA)),
-        0x19 => Ok(Instruction(OperandMode::AbsoluteY,   Operation::ORA)),
-        0x01 => Ok(Instruction(OperandMode::IndirectX,   Operation::ORA)),
-        0x11 => Ok(Instruction(OperandMode::IndirectY,   Operation::ORA)),
-        0x48 => Ok(Instruction(OperandMode::Implied,     Operation::PHA)),
-        0x08 => Ok(Instruction(OperandMode::Implied,     Operation::PHP)),
-        0x68 => Ok(Instruction(OperandMode::Implied,     Operation::PLA)),
-        0x28 => Ok(Instruction(OperandMode::Implied,     Operation::PLP)),
-        0x2A => Ok(Instruction(OperandMode::Accumulator, Operation::ROL)),
-        0x26 => Ok(Instruction(OperandMode::ZeroPage,    Operation::ROL)),
-        0x36 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::ROL)),
-        0x2E => Ok(Instruction(OperandMode::Absolute,    Operation::ROL)),
-        0x3E => Ok(Instruction(OperandMode::AbsoluteX,   Operation::ROL)),
-        0x6A => Ok(Instruction(OperandMode::Accumulator, Operation::ROR)),
-        0x66 => Ok(Instruction(OperandMode::ZeroPage,    Operation::ROR)),
-        0x76 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::ROR)),
-        0x6E => Ok(Instruction(OperandMode::Absolute,    Operation::ROR)),
-        0x7E => Ok(Instruction(OperandMode::AbsoluteX,   Operation::ROR)),
-        0x40 => Ok(Instruction(OperandMode::Implied,     Operation::RTI)),
-        0x60 => Ok(Instruction(OperandMode::Implied,     Operation::RTS)),
-        0xE9 => Ok(Instruction(OperandMode::Immediate,   Operation::SBC)),
-        0xE5 => Ok(Instruction(OperandMode::ZeroPage,    Operation::SBC)),
-        0xF5 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::SBC)),
-        0xED => Ok(Instruction(OperandMode::Absolute,    Operation::SBC)),
-        0xFD => Ok(Instruction(OperandMode::AbsoluteX,   Operation::SBC)),
-        0xF9 => Ok(Instruction(OperandMode::AbsoluteY,   Operation::SBC)),
-        0xE1 => Ok(Instruction(OperandMode::IndirectX,   Operation::SBC)),
-        0xF1 => Ok(Instruction(OperandMode::IndirectY,   Operation::SBC)),
-        0x38 => Ok(Instruction(OperandMode::Implied,     Operation::SEC)),
-        0xF8 => Ok(Instruction(OperandMode::Implied,     Operation::SED)),
-        0x78 => Ok(Instruction(OperandMode::Implied,     Operation::SEI)),
-        0x85 => Ok(Instruction(OperandMode::ZeroPage,    Operation::STA)),
-        0x95 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::STA)),
-        0x8D => Ok(Instruction(OperandMode::Absolute,    Operation::STA)),
-        0x9D => Ok(Instruction(OperandMode::AbsoluteX,   Operation::STA)),
-        0x99 => Ok(Instruction(OperandMode::AbsoluteY,   Operation::STA)),
-        0x81 => Ok(Instruction(OperandMode::IndirectX,   Operation::STA)),
-        0x91 => Ok(Instruction(OperandMode::IndirectY,   Operation::STA)),
-        0x86 => Ok(Instruction(OperandMode::ZeroPage,    Operation::STX)),
-        0x96 => Ok(Instruction(OperandMode::ZeroPageY,   Operation::STX)),
-        0x8E => Ok(Instruction(OperandMode::Absolute,    Operation::STX)),
-        0x84 => Ok(Instruction(OperandMode::ZeroPage,    Operation::STY)),
-        0x94 => Ok(Instruction(OperandMode::ZeroPageX,   Operation::STY)),
-        0x8C => Ok(Instruction(OperandMode::Absolute,    Operation::STY)),
-        0xAA => Ok(Instruction(OperandMode::Implied,     Operation::TAX)),
-        0xA8 => Ok(Instruction(OperandMode::Implied,     Operation::TAY)),
-        0xBA => Ok(Instruction(OperandMode::Implied,     Operation::TSX)),
-        0x8A => Ok(Instruction(OperandMode::Implied,     Operation::TXA)),
-        0x9A => Ok(Instruction(OperandMode::Implied,     Operation::TXS)),
-        0x98 => Ok(Instruction(OperandMode::Implied,     Operation::TYA)),
-        _    => Err("Can't find instruction")
+        0x69 => Some(Instruction(OperandMode::Immediate,   Operation::ADC)),
+        0x65 => Some(Instruction(OperandMode::ZeroPage,    Operation::ADC)),
+        0x75 => Some(Instruction(OperandMode::ZeroPageX,   Operation::ADC)),
+        0x6D => Some(Instruction(OperandMode::Absolute,    Operation::ADC)),
+        0x7D => Some(Instruction(OperandMode::AbsoluteX,   Operation::ADC)),
+        0x79 => Some(Instruction(OperandMode::AbsoluteY,   Operation::ADC)),
+        0x61 => Some(Instruction(OperandMode::IndirectX,   Operation::ADC)),
+        0x71 => Some(Instruction(OperandMode::IndirectY,   Operation::ADC)),
+        0x29 => Some(Instruction(OperandMode::Immediate,   Operation::AND)),
+        0x25 => Some(Instruction(OperandMode::ZeroPage,    Operation::AND)),
+        0x35 => Some(Instruction(OperandMode::ZeroPageX,   Operation::AND)),
+        0x2D => Some(Instruction(OperandMode::Absolute,    Operation::AND)),
+        0x3D => Some(Instruction(OperandMode::AbsoluteX,   Operation::AND)),
+        0x39 => Some(Instruction(OperandMode::AbsoluteY,   Operation::AND)),
+        0x21 => Some(Instruction(OperandMode::IndirectX,   Operation::AND)),
+        0x31 => Some(Instruction(OperandMode::IndirectY,   Operation::AND)),
+        0x0A => Some(Instruction(OperandMode::Accumulator, Operation::ASL)),
+        0x06 => Some(Instruction(OperandMode::ZeroPage,    Operation::ASL)),
+        0x16 => Some(Instruction(OperandMode::ZeroPageX,   Operation::ASL)),
+        0x0E => Some(Instruction(OperandMode::Absolute,    Operation::ASL)),
+        0x1E => Some(Instruction(OperandMode::AbsoluteX,   Operation::ASL)),
+        0x90 => Some(Instruction(OperandMode::Immediate,   Operation::BCC)),
+        0xB0 => Some(Instruction(OperandMode::Immediate,   Operation::BCS)),
+        0xF0 => Some(Instruction(OperandMode::Immediate,   Operation::BEQ)),
+        0x24 => Some(Instruction(OperandMode::ZeroPage,    Operation::BIT)),
+        0x2C => Some(Instruction(OperandMode::Absolute,    Operation::BIT)),
+        0x30 => Some(Instruction(OperandMode::Immediate,   Operation::BMI)),
+        0xD0 => Some(Instruction(OperandMode::Immediate,   Operation::BNE)),
+        0x10 => Some(Instruction(OperandMode::Immediate,   Operation::BPL)),
+        0x00 => Some(Instruction(OperandMode::Implied,     Operation::BRK)),
+        0x50 => Some(Instruction(OperandMode::Immediate,   Operation::BVC)),
+        0x70 => Some(Instruction(OperandMode::Immediate,   Operation::BVS)),
+        0x18 => Some(Instruction(OperandMode::Implied,     Operation::CLC)),
+        0xD8 => Some(Instruction(OperandMode::Implied,     Operation::CLD)),
+        0x58 => Some(Instruction(OperandMode::Implied,     Operation::CLI)),
+        0xB8 => Some(Instruction(OperandMode::Implied,     Operation::CLV)),
+        0xC9 => Some(Instruction(OperandMode::Immediate,   Operation::CMP)),
+        0xC5 => Some(Instruction(OperandMode::ZeroPage,    Operation::CMP)),
+        0xD5 => Some(Instruction(OperandMode::ZeroPageX,   Operation::CMP)),
+        0xCD => Some(Instruction(OperandMode::Absolute,    Operation::CMP)),
+        0xDD => Some(Instruction(OperandMode::AbsoluteX,   Operation::CMP)),
+        0xD9 => Some(Instruction(OperandMode::AbsoluteY,   Operation::CMP)),
+        0xC1 => Some(Instruction(OperandMode::IndirectX,   Operation::CMP)),
+        0xD1 => Some(Instruction(OperandMode::IndirectY,   Operation::CMP)),
+        0xE0 => Some(Instruction(OperandMode::Immediate,   Operation::CPX)),
+        0xE4 => Some(Instruction(OperandMode::ZeroPage,    Operation::CPX)),
+        0xEC => Some(Instruction(OperandMode::Absolute,    Operation::CPX)),
+        0xC0 => Some(Instruction(OperandMode::Immediate,   Operation::CPY)),
+        0xC4 => Some(Instruction(OperandMode::ZeroPage,    Operation::CPY)),
+        0xCC => Some(Instruction(OperandMode::Absolute,    Operation::CPY)),
+        0xC6 => Some(Instruction(OperandMode::ZeroPage,    Operation::DEC)),
+        0xD6 => Some(Instruction(OperandMode::ZeroPageX,   Operation::DEC)),
+        0xCE => Some(Instruction(OperandMode::Absolute,    Operation::DEC)),
+        0xDE => Some(Instruction(OperandMode::AbsoluteX,   Operation::DEC)),
+        0xCA => Some(Instruction(OperandMode::Implied,     Operation::DEX)),
+        0x88 => Some(Instruction(OperandMode::Implied,     Operation::DEY)),
+        0x49 => Some(Instruction(OperandMode::Immediate,   Operation::EOR)),
+        0x45 => Some(Instruction(OperandMode::ZeroPage,    Operation::EOR)),
+        0x55 => Some(Instruction(OperandMode::ZeroPageX,   Operation::EOR)),
+        0x4D => Some(Instruction(OperandMode::Absolute,    Operation::EOR)),
+        0x5D => Some(Instruction(OperandMode::AbsoluteX,   Operation::EOR)),
+        0x59 => Some(Instruction(OperandMode::AbsoluteY,   Operation::EOR)),
+        0x41 => Some(Instruction(OperandMode::IndirectX,   Operation::EOR)),
+        0x51 => Some(Instruction(OperandMode::IndirectY,   Operation::EOR)),
+        0xE6 => Some(Instruction(OperandMode::ZeroPage,    Operation::INC)),
+        0xF6 => Some(Instruction(OperandMode::ZeroPageX,   Operation::INC)),
+        0xEE => Some(Instruction(OperandMode::Absolute,    Operation::INC)),
+        0xFE => Some(Instruction(OperandMode::AbsoluteX,   Operation::INC)),
+        0xE8 => Some(Instruction(OperandMode::Implied,     Operation::INX)),
+        0xC8 => Some(Instruction(OperandMode::Implied,     Operation::INY)),
+        0x4C => Some(Instruction(OperandMode::Absolute,    Operation::JMP)),
+        0x6C => Some(Instruction(OperandMode::Indirect,    Operation::JMP)),
+        0x20 => Some(Instruction(OperandMode::Absolute,    Operation::JSR)),
+        0xA9 => Some(Instruction(OperandMode::Immediate,   Operation::LDA)),
+        0xA5 => Some(Instruction(OperandMode::ZeroPage,    Operation::LDA)),
+        0xB5 => Some(Instruction(OperandMode::ZeroPageX,   Operation::LDA)),
+        0xAD => Some(Instruction(OperandMode::Absolute,    Operation::LDA)),
+        0xBD => Some(Instruction(OperandMode::AbsoluteX,   Operation::LDA)),
+        0xB9 => Some(Instruction(OperandMode::AbsoluteY,   Operation::LDA)),
+        0xA1 => Some(Instruction(OperandMode::IndirectX,   Operation::LDA)),
+        0xB1 => Some(Instruction(OperandMode::IndirectY,   Operation::LDA)),
+        0xA2 => Some(Instruction(OperandMode::Immediate,   Operation::LDX)),
+        0xA6 => Some(Instruction(OperandMode::ZeroPage,    Operation::LDX)),
+        0xB6 => Some(Instruction(OperandMode::ZeroPageY,   Operation::LDX)),
+        0xAE => Some(Instruction(OperandMode::Absolute,    Operation::LDX)),
+        0xBE => Some(Instruction(OperandMode::AbsoluteY,   Operation::LDX)),
+        0xA0 => Some(Instruction(OperandMode::Immediate,   Operation::LDY)),
+        0xA4 => Some(Instruction(OperandMode::ZeroPage,    Operation::LDY)),
+        0xB4 => Some(Instruction(OperandMode::ZeroPageX,   Operation::LDY)),
+        0xAC => Some(Instruction(OperandMode::Absolute,    Operation::LDY)),
+        0xBC => Some(Instruction(OperandMode::AbsoluteX,   Operation::LDY)),
+        0x4A => Some(Instruction(OperandMode::Accumulator, Operation::LSR)),
+        0x46 => Some(Instruction(OperandMode::ZeroPage,    Operation::LSR)),
+        0x56 => Some(Instruction(OperandMode::ZeroPageX,   Operation::LSR)),
+        0x4E => Some(Instruction(OperandMode::Absolute,    Operation::LSR)),
+        0x5E => Some(Instruction(OperandMode::AbsoluteX,   Operation::LSR)),
+        0xEA => Some(Instruction(OperandMode::Implied,     Operation::NOP)),
+        0x09 => Some(Instruction(OperandMode::Immediate,   Operation::ORA)),
+        0x05 => Some(Instruction(OperandMode::ZeroPage,    Operation::ORA)),
+        0x15 => Some(Instruction(OperandMode::ZeroPageX,   Operation::ORA)),
+        0x0D => Some(Instruction(OperandMode::Absolute,    Operation::ORA)),
+        0x1D => Some(Instruction(OperandMode::AbsoluteX,   Operation::ORA)),
+        0x19 => Some(Instruction(OperandMode::AbsoluteY,   Operation::ORA)),
+        0x01 => Some(Instruction(OperandMode::IndirectX,   Operation::ORA)),
+        0x11 => Some(Instruction(OperandMode::IndirectY,   Operation::ORA)),
+        0x48 => Some(Instruction(OperandMode::Implied,     Operation::PHA)),
+        0x08 => Some(Instruction(OperandMode::Implied,     Operation::PHP)),
+        0x68 => Some(Instruction(OperandMode::Implied,     Operation::PLA)),
+        0x28 => Some(Instruction(OperandMode::Implied,     Operation::PLP)),
+        0x2A => Some(Instruction(OperandMode::Accumulator, Operation::ROL)),
+        0x26 => Some(Instruction(OperandMode::ZeroPage,    Operation::ROL)),
+        0x36 => Some(Instruction(OperandMode::ZeroPageX,   Operation::ROL)),
+        0x2E => Some(Instruction(OperandMode::Absolute,    Operation::ROL)),
+        0x3E => Some(Instruction(OperandMode::AbsoluteX,   Operation::ROL)),
+        0x6A => Some(Instruction(OperandMode::Accumulator, Operation::ROR)),
+        0x66 => Some(Instruction(OperandMode::ZeroPage,    Operation::ROR)),
+        0x76 => Some(Instruction(OperandMode::ZeroPageX,   Operation::ROR)),
+        0x6E => Some(Instruction(OperandMode::Absolute,    Operation::ROR)),
+        0x7E => Some(Instruction(OperandMode::AbsoluteX,   Operation::ROR)),
+        0x40 => Some(Instruction(OperandMode::Implied,     Operation::RTI)),
+        0x60 => Some(Instruction(OperandMode::Implied,     Operation::RTS)),
+        0xE9 => Some(Instruction(OperandMode::Immediate,   Operation::SBC)),
+        0xE5 => Some(Instruction(OperandMode::ZeroPage,    Operation::SBC)),
+        0xF5 => Some(Instruction(OperandMode::ZeroPageX,   Operation::SBC)),
+        0xED => Some(Instruction(OperandMode::Absolute,    Operation::SBC)),
+        0xFD => Some(Instruction(OperandMode::AbsoluteX,   Operation::SBC)),
+        0xF9 => Some(Instruction(OperandMode::AbsoluteY,   Operation::SBC)),
+        0xE1 => Some(Instruction(OperandMode::IndirectX,   Operation::SBC)),
+        0xF1 => Some(Instruction(OperandMode::IndirectY,   Operation::SBC)),
+        0x38 => Some(Instruction(OperandMode::Implied,     Operation::SEC)),
+        0xF8 => Some(Instruction(OperandMode::Implied,     Operation::SED)),
+        0x78 => Some(Instruction(OperandMode::Implied,     Operation::SEI)),
+        0x85 => Some(Instruction(OperandMode::ZeroPage,    Operation::STA)),
+        0x95 => Some(Instruction(OperandMode::ZeroPageX,   Operation::STA)),
+        0x8D => Some(Instruction(OperandMode::Absolute,    Operation::STA)),
+        0x9D => Some(Instruction(OperandMode::AbsoluteX,   Operation::STA)),
+        0x99 => Some(Instruction(OperandMode::AbsoluteY,   Operation::STA)),
+        0x81 => Some(Instruction(OperandMode::IndirectX,   Operation::STA)),
+        0x91 => Some(Instruction(OperandMode::IndirectY,   Operation::STA)),
+        0x86 => Some(Instruction(OperandMode::ZeroPage,    Operation::STX)),
+        0x96 => Some(Instruction(OperandMode::ZeroPageY,   Operation::STX)),
+        0x8E => Some(Instruction(OperandMode::Absolute,    Operation::STX)),
+        0x84 => Some(Instruction(OperandMode::ZeroPage,    Operation::STY)),
+        0x94 => Some(Instruction(OperandMode::ZeroPageX,   Operation::STY)),
+        0x8C => Some(Instruction(OperandMode::Absolute,    Operation::STY)),
+        0xAA => Some(Instruction(OperandMode::Implied,     Operation::TAX)),
+        0xA8 => Some(Instruction(OperandMode::Implied,     Operation::TAY)),
+        0xBA => Some(Instruction(OperandMode::Implied,     Operation::TSX)),
+        0x8A => Some(Instruction(OperandMode::Implied,     Operation::TXA)),
+        0x9A => Some(Instruction(OperandMode::Implied,     Operation::TXS)),
+        0x98 => Some(Instruction(OperandMode::Implied,     Operation::TYA)),
+        // Undocumented NMOS instructions
+        0x4B => Some(Instruction(OperandMode::Immediate,   Operation::ALR)),
+        0x0B => Some(Instruction(OperandMode::Immediate,   Operation::ANC)),
+        0x2B => Some(Instruction(OperandMode::Immediate,   Operation::ANC)),
+        0x6B => Some(Instruction(OperandMode::Immediate,   Operation::ARR)),
+        0xCB => Some(Instruction(OperandMode::Immediate,   Operation::AXS)),
+        0xC7 => Some(Instruction(OperandMode::ZeroPage,    Operation::DCP)),
+        0xD7 => Some(Instruction(OperandMode::ZeroPageX,   Operation::DCP)),
+        0xCF => Some(Instruction(OperandMode::Absolute,    Operation::DCP)),
+        0xDF => Some(Instruction(OperandMode::AbsoluteX,   Operation::DCP)),
+        0xDB => Some(Instruction(OperandMode::AbsoluteY,   Operation::DCP)),
+        0xC3 => Some(Instruction(OperandMode::IndirectX,   Operation::DCP)),
+        0xD3 => Some(Instruction(OperandMode::IndirectY,   Operation::DCP)),
+        0xE7 => Some(Instruction(OperandMode::ZeroPage,    Operation::ISC)),
+        0xF7 => Some(Instruction(OperandMode::ZeroPageX,   Operation::ISC)),
+        0xEF => Some(Instruction(OperandMode::Absolute,    Operation::ISC)),
+        0xFF => Some(Instruction(OperandMode::AbsoluteX,   Operation::ISC)),
+        0xFB => Some(Instruction(OperandMode::AbsoluteY,   Operation::ISC)),
+        0xE3 => Some(Instruction(OperandMode::IndirectX,   Operation::ISC)),
+        0xF3 => Some(Instruction(OperandMode::IndirectY,   Operation::ISC)),
+        0xA7 => Some(Instruction(OperandMode::ZeroPage,    Operation::LAX)),
+        0xB7 => Some(Instruction(OperandMode::ZeroPageY,   Operation::LAX)),
+        0xAF => Some(Instruction(OperandMode::Absolute,    Operation::LAX)),
+        0xBF => Some(Instruction(OperandMode::AbsoluteY,   Operation::LAX)),
+        0xA3 => Some(Instruction(OperandMode::IndirectX,   Operation::LAX)),
+        0xB3 => Some(Instruction(OperandMode::IndirectY,   Operation::LAX)),
+        0x1A => Some(Instruction(OperandMode::Implied,     Operation::NOP)),
+        0x3A => Some(Instruction(OperandMode::Implied,     Operation::NOP)),
+        0x5A => Some(Instruction(OperandMode::Implied,     Operation::NOP)),
+        0x7A => Some(Instruction(OperandMode::Implied,     Operation::NOP)),
+        0xDA => Some(Instruction(OperandMode::Implied,     Operation::NOP)),
+        0xFA => Some(Instruction(OperandMode::Implied,     Operation::NOP)),
+        0x80 => Some(Instruction(OperandMode::Immediate,   Operation::NOP)),
+        0x82 => Some(Instruction(OperandMode::Immediate,   Operation::NOP)),
+        0x89 => Some(Instruction(OperandMode::Immediate,   Operation::NOP)),
+        0xC2 => Some(Instruction(OperandMode::Immediate,   Operation::NOP)),
+        0xE2 => Some(Instruction(OperandMode::Immediate,   Operation::NOP)),
+        0x04 => Some(Instruction(OperandMode::ZeroPage,    Operation::NOP)),
+        0x44 => Some(Instruction(OperandMode::ZeroPage,    Operation::NOP)),
+        0x64 => Some(Instruction(OperandMode::ZeroPage,    Operation::NOP)),
+        0x14 => Some(Instruction(OperandMode::ZeroPageX,   Operation::NOP)),
+        0x34 => Some(Instruction(OperandMode::ZeroPageX,   Operation::NOP)),
+        0x54 => Some(Instruction(OperandMode::ZeroPageX,   Operation::NOP)),
+        0x74 => Some(Instruction(OperandMode::ZeroPageX,   Operation::NOP)),
+        0xD4 => Some(Instruction(OperandMode::ZeroPageX,   Operation::NOP)),
+        0xF4 => Some(Instruction(OperandMode::ZeroPageX,   Operation::NOP)),
+        0x0C => Some(Instruction(OperandMode::Absolute,    Operation::NOP)),
+        0x1C => Some(Instruction(OperandMode::AbsoluteX,   Operation::NOP)),
+        0x3C => Some(Instruction(OperandMode::AbsoluteX,   Operation::NOP)),
+        0x5C => Some(Instruction(OperandMode::AbsoluteX,   Operation::NOP)),
+        0x7C => Some(Instruction(OperandMode::AbsoluteX,   Operation::NOP)),
+        0xDC => Some(Instruction(OperandMode::AbsoluteX,   Operation::NOP)),
+        0xFC => Some(Instruction(OperandMode::AbsoluteX,   Operation::NOP)),
+        0x27 => Some(Instruction(OperandMode::ZeroPage,    Operation::RLA)),
+        0x37 => Some(Instruction(OperandMode::ZeroPageX,   Operation::RLA)),
+        0x2F => Some(Instruction(OperandMode::Absolute,    Operation::RLA)),
+        0x3F => Some(Instruction(OperandMode::AbsoluteX,   Operation::RLA)),
+        0x3B => Some(Instruction(OperandMode::AbsoluteY,   Operation::RLA)),
+        0x23 => Some(Instruction(OperandMode::IndirectX,   Operation::RLA)),
+        0x33 => Some(Instruction(OperandMode::IndirectY,   Operation::RLA)),
+        0x67 => Some(Instruction(OperandMode::ZeroPage,    Operation::RRA)),
+        0x77 => Some(Instruction(OperandMode::ZeroPageX,   Operation::RRA)),
+        0x6F => Some(Instruction(OperandMode::Absolute,    Operation::RRA)),
+        0x7F => Some(Instruction(OperandMode::AbsoluteX,   Operation::RRA)),
+        0x7B => Some(Instruction(OperandMode::AbsoluteY,   Operation::RRA)),
+        0x63 => Some(Instruction(OperandMode::IndirectX,   Operation::RRA)),
+        0x73 => Some(Instruction(OperandMode::IndirectY,   Operation::RRA)),
+        0x87 => Some(Instruction(OperandMode::ZeroPage,    Operation::SAX)),
+        0x97 => Some(Instruction(OperandMode::ZeroPageY,   Operation::SAX)),
+        0x8F => Some(Instruction(OperandMode::Absolute,    Operation::SAX)),
+        0x83 => Some(Instruction(OperandMode::IndirectX,   Operation::SAX)),
+        0xEB => Some(Instruction(OperandMode::Immediate,   Operation::SBC)),
+        0x07 => Some(Instruction(OperandMode::ZeroPage,    Operation::SLO)),
+        0x17 => Some(Instruction(OperandMode::ZeroPageX,   Operation::SLO)),
+        0x0F => Some(Instruction(OperandMode::Absolute,    Operation::SLO)),
+        0x1F => Some(Instruction(OperandMode::AbsoluteX,   Operation::SLO)),
+        0x1B => Some(Instruction(OperandMode::AbsoluteY,   Operation::SLO)),
+        0x03 => Some(Instruction(OperandMode::IndirectX,   Operation::SLO)),
+        0x13 => Some(Instruction(OperandMode::IndirectY,   Operation::SLO)),
+        0x47 => Some(Instruction(OperandMode::ZeroPage,    Operation::SRE)),
+        0x57 => Some(Instruction(OperandMode::ZeroPageX,   Operation::SRE)),
+        0x4F => Some(Instruction(OperandMode::Absolute,    Operation::SRE)),
+        0x5F => Some(Instruction(OperandMode::AbsoluteX,   Operation::SRE)),
+        0x5B => Some(Instruction(OperandMode::AbsoluteY,   Operation::SRE)),
+        0x43 => Some(Instruction(OperandMode::IndirectX,   Operation::SRE)),
+        0x53 => Some(Instruction(OperandMode::IndirectY,   Operation::SRE)),
+        // Unstable undocumented NMOS instructions, see execute_operation for how they're emulated
+        0x9F => Some(Instruction(OperandMode::AbsoluteY,   Operation::AHX)),
+        0x93 => Some(Instruction(OperandMode::IndirectY,   Operation::AHX)),
+        0xBB => Some(Instruction(OperandMode::AbsoluteY,   Operation::LAS)),
+        0xAB => Some(Instruction(OperandMode::Immediate,   Operation::LXA)),
+        0x9E => Some(Instruction(OperandMode::AbsoluteY,   Operation::SHX)),
+        0x9C => Some(Instruction(OperandMode::AbsoluteX,   Operation::SHY)),
+        0x9B => Some(Instruction(OperandMode::AbsoluteY,   Operation::TAS)),
+        0x8B => Some(Instruction(OperandMode::Immediate,   Operation::XAA)),
+        _    => None
+    }
+}
+
+/// The 65C02 adds its own instructions in the NMOS undocumented opcode slots, the remaining ones
+/// are NOPs of varying length
+const fn decode_cmos_instruction(
+    instruction: u8,
+    variant: CpuVariant,
+) -> Option<Instruction> {
+    let is_wdc_extension = instruction & 0x07 == 0x07 || instruction == 0xCB || instruction == 0xDB;
+    if is_wdc_extension && !variant.has_wdc_extensions() {
+        return Some(Instruction(OperandMode::OneCycleImplied, Operation::NOP));
+    }
+
+    match instruction {
+        0x72 => Some(Instruction(OperandMode::ZeroPageIndirect,  Operation::ADC)),
+        0x32 => Some(Instruction(OperandMode::ZeroPageIndirect,  Operation::AND)),
+        0x0F => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBR0)),
+        0x1F => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBR1)),
+        0x2F => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBR2)),
+        0x3F => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBR3)),
+        0x4F => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBR4)),
+        0x5F => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBR5)),
+        0x6F => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBR6)),
+        0x7F => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBR7)),
+        0x8F => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBS0)),
+        0x9F => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBS1)),
+        0xAF => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBS2)),
+        0xBF => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBS3)),
+        0xCF => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBS4)),
+        0xDF => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBS5)),
+        0xEF => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBS6)),
+        0xFF => Some(Instruction(OperandMode::ZeroPageRelative,  Operation::BBS7)),
+        0x89 => Some(Instruction(OperandMode::Immediate,         Operation::BIT)),
+        0x34 => Some(Instruction(OperandMode::ZeroPageX,         Operation::BIT)),
+        0x3C => Some(Instruction(OperandMode::AbsoluteX,         Operation::BIT)),
+        0x80 => Some(Instruction(OperandMode::Immediate,         Operation::BRA)),
+        0xD2 => Some(Instruction(OperandMode::ZeroPageIndirect,  Operation::CMP)),
+        0x3A => Some(Instruction(OperandMode::Accumulator,       Operation::DEC)),
+        0x52 => Some(Instruction(OperandMode::ZeroPageIndirect,  Operation::EOR)),
+        0x1A => Some(Instruction(OperandMode::Accumulator,       Operation::INC)),
+        0x7C => Some(Instruction(OperandMode::AbsoluteIndirectX, Operation::JMP)),
+        0xB2 => Some(Instruction(OperandMode::ZeroPageIndirect,  Operation::LDA)),
+        0x12 => Some(Instruction(OperandMode::ZeroPageIndirect,  Operation::ORA)),
+        0xDA => Some(Instruction(OperandMode::Implied,           Operation::PHX)),
+        0x5A => Some(Instruction(OperandMode::Implied,           Operation::PHY)),
+        0xFA => Some(Instruction(OperandMode::Implied,           Operation::PLX)),
+        0x7A => Some(Instruction(OperandMode::Implied,           Operation::PLY)),
+        0x07 => Some(Instruction(OperandMode::ZeroPage,          Operation::RMB0)),
+        0x17 => Some(Instruction(OperandMode::ZeroPage,          Operation::RMB1)),
+        0x27 => Some(Instruction(OperandMode::ZeroPage,          Operation::RMB2)),
+        0x37 => Some(Instruction(OperandMode::ZeroPage,          Operation::RMB3)),
+        0x47 => Some(Instruction(OperandMode::ZeroPage,          Operation::RMB4)),
+        0x57 => Some(Instruction(OperandMode::ZeroPage,          Operation::RMB5)),
+        0x67 => Some(Instruction(OperandMode::ZeroPage,          Operation::RMB6)),
+        0x77 => Some(Instruction(OperandMode::ZeroPage,          Operation::RMB7)),
+        0xF2 => Some(Instruction(OperandMode::ZeroPageIndirect,  Operation::SBC)),
+        0x87 => Some(Instruction(OperandMode::ZeroPage,          Operation::SMB0)),
+        0x97 => Some(Instruction(OperandMode::ZeroPage,          Operation::SMB1)),
+        0xA7 => Some(Instruction(OperandMode::ZeroPage,          Operation::SMB2)),
+        0xB7 => Some(Instruction(OperandMode::ZeroPage,          Operation::SMB3)),
+        0xC7 => Some(Instruction(OperandMode::ZeroPage,          Operation::SMB4)),
+        0xD7 => Some(Instruction(OperandMode::ZeroPage,          Operation::SMB5)),
+        0xE7 => Some(Instruction(OperandMode::ZeroPage,          Operation::SMB6)),
+        0xF7 => Some(Instruction(OperandMode::ZeroPage,          Operation::SMB7)),
+        0x92 => Some(Instruction(OperandMode::ZeroPageIndirect,  Operation::STA)),
+        0xDB => Some(Instruction(OperandMode::Implied,           Operation::STP)),
+        0x64 => Some(Instruction(OperandMode::ZeroPage,          Operation::STZ)),
+        0x74 => Some(Instruction(OperandMode::ZeroPageX,         Operation::STZ)),
+        0x9C => Some(Instruction(OperandMode::Absolute,          Operation::STZ)),
+        0x9E => Some(Instruction(OperandMode::AbsoluteX,         Operation::STZ)),
+        0x14 => Some(Instruction(OperandMode::ZeroPage,          Operation::TRB)),
+        0x1C => Some(Instruction(OperandMode::Absolute,          Operation::TRB)),
+        0x04 => Some(Instruction(OperandMode::ZeroPage,          Operation::TSB)),
+        0x0C => Some(Instruction(OperandMode::Absolute,          Operation::TSB)),
+        0xCB => Some(Instruction(OperandMode::Implied,           Operation::WAI)),
+        // Reserved opcodes
+        0x02 => Some(Instruction(OperandMode::Immediate,         Operation::NOP)),
+        0x22 => Some(Instruction(OperandMode::Immediate,         Operation::NOP)),
+        0x42 => Some(Instruction(OperandMode::Immediate,         Operation::NOP)),
+        0x62 => Some(Instruction(OperandMode::Immediate,         Operation::NOP)),
+        0x82 => Some(Instruction(OperandMode::Immediate,         Operation::NOP)),
+        0xC2 => Some(Instruction(OperandMode::Immediate,         Operation::NOP)),
+        0xE2 => Some(Instruction(OperandMode::Immediate,         Operation::NOP)),
+        0x44 => Some(Instruction(OperandMode::ZeroPage,          Operation::NOP)),
+        0x54 => Some(Instruction(OperandMode::ZeroPageX,         Operation::NOP)),
+        0xD4 => Some(Instruction(OperandMode::ZeroPageX,         Operation::NOP)),
+        0xF4 => Some(Instruction(OperandMode::ZeroPageX,         Operation::NOP)),
+        0x5C => Some(Instruction(OperandMode::EightCycleAbsolute, Operation::NOP)),
+        0xDC => Some(Instruction(OperandMode::Absolute,          Operation::NOP)),
+        0xFC => Some(Instruction(OperandMode::Absolute,          Operation::NOP)),
+        // The x3 and xB columns
+        _ if instruction & 0x07 == 0x03 => {
+            Some(Instruction(OperandMode::OneCycleImplied, Operation::NOP))
+        }
+        _    => decode_nmos_instruction(instruction)
+    }
+}
+
+pub const fn calculate_cycles(
+    instr: &Instruction,
+    variant: CpuVariant,
+) -> Option<CycleCount> {
+    if variant.is_cmos() {
+        calculate_cmos_cycles(instr)
+    } else {
+        calculate_nmos_cycles(instr)
     }
 }
 
 // These are from https://www.nesdev.org/wiki/6502_cycle_times
 // and http://6502.org/tutorials/6502opcodes.html
-pub fn calculate_cycles(instr: &Instruction) -> Result<CycleCount, &'static str> {
+const fn calculate_nmos_cycles(instr: &Instruction) -> Option<CycleCount> {
     match instr {
         // Most common instruction latency set, includes most arithmetic, logical and memory operations
         Instruction(opmode,   Operation::ADC | Operation::AND | Operation::BIT |
                               Operation::CMP | Operation::CPX | Operation::CPY |
                               Operation::EOR | Operation::LDA | Operation::LDX |
                               Operation::LDY | Operation::ORA | Operation::SBC |
-                              Operation::STX | Operation::STY) =>
+                              Operation::STX | Operation::STY | Operation::LAS |
+                              Operation::LAX | Operation::SAX) =>
             match opmode {
-                OperandMode::Immediate => Ok(cycles(2)),
-                OperandMode::ZeroPage  => Ok(cycles(3)),
-                OperandMode::ZeroPageX => Ok(cycles(4)),
-                OperandMode::ZeroPageY => Ok(cycles(4)),
-                OperandMode::Absolute  => Ok(cycles(4)),
-                OperandMode::AbsoluteX => Ok(cycles_with_extra_cost(4)),
-                OperandMode::AbsoluteY => Ok(cycles_with_extra_cost(4)),
-                OperandMode::IndirectX => Ok(cycles(6)),
-                OperandMode::IndirectY => Ok(cycles_with_extra_cost(5)),   
-                _                      => Err("Instruction timings not found")
+                OperandMode::Immediate => Some(cycles(2)),
+                OperandMode::ZeroPage  => Some(cycles(3)),
+                OperandMode::ZeroPageX => Some(cycles(4)),
+                OperandMode::ZeroPageY => Some(cycles(4)),
+                OperandMode::Absolute  => Some(cycles(4)),
+                OperandMode::AbsoluteX => Some(cycles_with_extra_cost(4)),
+                OperandMode::AbsoluteY => Some(cycles_with_extra_cost(4)),
+                OperandMode::IndirectX => Some(cycles(6)),
+                OperandMode::IndirectY => Some(cycles_with_extra_cost(5)),   
+                _                      => None
             }
 
         // Memory increment/decrement operations and rotates, shifts
         Instruction(opmode,   Operation::ASL | Operation::DEC | Operation::INC |
                               Operation::LSR | Operation::ROL | Operation::ROR) =>
             match opmode {
-                OperandMode::Accumulator => Ok(cycles(2)),
-                OperandMode::ZeroPage    => Ok(cycles(5)),
-                OperandMode::ZeroPageX   => Ok(cycles(6)),
-                OperandMode::Absolute    => Ok(cycles(6)),
-                OperandMode::AbsoluteX   => Ok(cycles(7)),
-                _                      => Err("Instruction timings not found")
+                OperandMode::Accumulator => Some(cycles(2)),
+                OperandMode::ZeroPage    => Some(cycles(5)),
+                OperandMode::ZeroPageX   => Some(cycles(6)),
+                OperandMode::Absolute    => Some(cycles(6)),
+                OperandMode::AbsoluteX   => Some(cycles(7)),
+                _                      => None
             }
 
-        // Branches (TODO: Take and extra cycle if branch is taken)
+        // Undocumented combined read-modify-write and accumulator operations
+        Instruction(opmode,   Operation::DCP | Operation::ISC | Operation::RLA |
+                              Operation::RRA | Operation::SLO | Operation::SRE) =>
+            match opmode {
+                OperandMode::ZeroPage  => Some(cycles(5)),
+                OperandMode::ZeroPageX => Some(cycles(6)),
+                OperandMode::Absolute  => Some(cycles(6)),
+                OperandMode::AbsoluteX => Some(cycles(7)),
+                OperandMode::AbsoluteY => Some(cycles(7)),
+                OperandMode::IndirectX => Some(cycles(8)),
+                OperandMode::IndirectY => Some(cycles(8)),
+                _                      => None
+            }
+
+        // Undocumented immediate-only operations
+        Instruction(OperandMode::Immediate, Operation::ALR | Operation::ANC | Operation::ARR |
+                                            Operation::AXS | Operation::LXA | Operation::XAA) =>
+                                                Some(cycles(2)),
+
+        // Undocumented NOPs read their operand like a load does
+        Instruction(opmode,   Operation::NOP) =>
+            match opmode {
+                OperandMode::Implied   => Some(cycles(2)),
+                OperandMode::Immediate => Some(cycles(2)),
+                OperandMode::ZeroPage  => Some(cycles(3)),
+                OperandMode::ZeroPageX => Some(cycles(4)),
+                OperandMode::Absolute  => Some(cycles(4)),
+                OperandMode::AbsoluteX => Some(cycles_with_extra_cost(4)),
+                _                      => None
+            }
+
+        // Branches, taking an extra cycle when taken and another when the target is on another
+        // page, both charged when the branch executes
         Instruction(OperandMode::Immediate, Operation::BCC | Operation::BCS | Operation::BEQ |
                                             Operation::BMI | Operation::BNE | Operation::BPL |
                                             Operation::BVC | Operation::BVS) =>
-                                                Ok(cycles_with_extra_cost(2)),
+                                                Some(cycles(2)),
 
-        Instruction(OperandMode::Implied,     Operation::BRK) => Ok(cycles(7)),
+        Instruction(OperandMode::Implied,     Operation::BRK) => Some(cycles(7)),
 
         // Short, implied-operand instructions; set and clear flags, register transfer and
         // increment/decrement operations
         Instruction(OperandMode::Implied,     Operation::CLC | Operation::CLD | Operation::CLI |
                                               Operation::CLV | Operation::DEX | Operation::DEY |
-                                              Operation::INX | Operation::INY | Operation::NOP |
+                                              Operation::INX | Operation::INY |
                                               Operation::SEC | Operation::SED | Operation::SEI |
                                               Operation::TAX | Operation::TAY | Operation::TSX |
-                                              Operation::TXA | Operation::TXS | Operation::TYA) => Ok(cycles(2)),
+                                              Operation::TXA | Operation::TXS | Operation::TYA) => Some(cycles(2)),
 
-        Instruction(OperandMode::Absolute,    Operation::JMP) => Ok(cycles(3)),
-        Instruction(OperandMode::Indirect,    Operation::JMP) => Ok(cycles(5)),
+        Instruction(OperandMode::Absolute,    Operation::JMP) => Some(cycles(3)),
+        Instruction(OperandMode::Indirect,    Operation::JMP) => Some(cycles(5)),
 
         // Interrupt / subroutine instructions
-        Instruction(OperandMode::Absolute,    Operation::JSR) => Ok(cycles(6)),
-        Instruction(OperandMode::Implied,     Operation::RTI | Operation::RTS) => Ok(cycles(6)),
+        Instruction(OperandMode::Absolute,    Operation::JSR) => Some(cycles(6)),
+        Instruction(OperandMode::Implied,     Operation::RTI | Operation::RTS) => Some(cycles(6)),
+
+        Instruction(OperandMode::Implied,     Operation::PHA | Operation::PHP) => Some(cycles(3)),
+        Instruction(OperandMode::Implied,     Operation::PLA | Operation::PLP) => Some(cycles(4)),
 
-        Instruction(OperandMode::Implied,     Operation::PHA | Operation::PHP) => Ok(cycles(3)),
-        Instruction(OperandMode::Implied,     Operation::PLA | Operation::PLP) => Ok(cycles(4)),
+        Instruction(OperandMode::ZeroPage,    Operation::STA) => Some(cycles(3)),
+        Instruction(OperandMode::ZeroPageX,   Operation::STA) => Some(cycles(4)),
+        Instruction(OperandMode::Absolute,    Operation::STA) => Some(cycles(4)),
+        Instruction(OperandMode::AbsoluteX,   Operation::STA) => Some(cycles(5)),
+        Instruction(OperandMode::AbsoluteY,   Operation::STA) => Some(cycles(5)),
+        Instruction(OperandMode::IndirectX,   Operation::STA) => Some(cycles(6)),
+        Instruction(OperandMode::IndirectY,   Operation::STA) => Some(cycles(6)),
 
-        Instruction(OperandMode::ZeroPage,    Operation::STA) => Ok(cycles(3)),
-        Instruction(OperandMode::ZeroPageX,   Operation::STA) => Ok(cycles(4)),
-        Instruction(OperandMode::Absolute,    Operation::STA) => Ok(cycles(4)),
-        Instruction(OperandMode::AbsoluteX,   Operation::STA) => Ok(cycles(5)),
-        Instruction(OperandMode::AbsoluteY,   Operation::STA) => Ok(cycles(5)),
-        Instruction(OperandMode::IndirectX,   Operation::STA) => Ok(cycles(6)),
-        Instruction(OperandMode::IndirectY,   Operation::STA) => Ok(cycles(6)),
+        // Unstable undocumented stores, timed like STA
+        Instruction(OperandMode::AbsoluteX,   Operation::SHY) => Some(cycles(5)),
+        Instruction(OperandMode::AbsoluteY,   Operation::AHX | Operation::SHX |
+                                              Operation::TAS) => Some(cycles(5)),
+        Instruction(OperandMode::IndirectY,   Operation::AHX) => Some(cycles(6)),
 
-        _                                                     => Err("Instruction timings not found")
+        _                                                     => None
     }
 }
 
-fn cycles(cycles: u8) -> CycleCount {
+// These are from the WDC W65C02S datasheet, anything not listed is timed like on the NMOS 6502
+const fn calculate_cmos_cycles(instr: &Instruction) -> Option<CycleCount> {
+    match instr {
+        Instruction(OperandMode::ZeroPageIndirect,  _) => Some(cycles(5)),
+        Instruction(OperandMode::AbsoluteIndirectX, Operation::JMP) => Some(cycles(6)),
+        Instruction(OperandMode::Indirect,          Operation::JMP) => Some(cycles(6)),
+
+        // Shifts and rotates only take the extra cycle when indexing crosses a page
+        Instruction(OperandMode::AbsoluteX, Operation::ASL | Operation::LSR |
+                                            Operation::ROL | Operation::ROR) =>
+                                                Some(cycles_with_extra_cost(6)),
+
+        Instruction(OperandMode::Immediate, Operation::BRA) => Some(cycles(2)),
+
+        Instruction(OperandMode::Implied,   Operation::PHX | Operation::PHY) => Some(cycles(3)),
+        Instruction(OperandMode::Implied,   Operation::PLX | Operation::PLY) => Some(cycles(4)),
+
+        Instruction(OperandMode::ZeroPage,  Operation::STZ) => Some(cycles(3)),
+        Instruction(OperandMode::ZeroPageX, Operation::STZ) => Some(cycles(4)),
+        Instruction(OperandMode::Absolute,  Operation::STZ) => Some(cycles(4)),
+        Instruction(OperandMode::AbsoluteX, Operation::STZ) => Some(cycles(5)),
+
+        Instruction(OperandMode::ZeroPage,  Operation::TRB | Operation::TSB) => Some(cycles(5)),
+        Instruction(OperandMode::Absolute,  Operation::TRB | Operation::TSB) => Some(cycles(6)),
+
+        Instruction(OperandMode::ZeroPage,  Operation::RMB0 | Operation::RMB1 | Operation::RMB2 |
+                                            Operation::RMB3 | Operation::RMB4 | Operation::RMB5 |
+                                            Operation::RMB6 | Operation::RMB7 | Operation::SMB0 |
+                                            Operation::SMB1 | Operation::SMB2 | Operation::SMB3 |
+                                            Operation::SMB4 | Operation::SMB5 | Operation::SMB6 |
+                                            Operation::SMB7) => Some(cycles(5)),
+
+        Instruction(OperandMode::ZeroPageRelative, _) => Some(cycles(5)),
+
+        Instruction(OperandMode::Implied,   Operation::WAI | Operation::STP) => Some(cycles(3)),
+
+        // Reserved opcodes
+        Instruction(OperandMode::OneCycleImplied,    Operation::NOP) => Some(cycles(1)),
+        Instruction(OperandMode::EightCycleAbsolute, Operation::NOP) => Some(cycles(8)),
+
+        _ => calculate_nmos_cycles(instr)
+    }
+}
+
+const fn cycles(cycles: u8) -> CycleCount {
     CycleCount { cycles, page_boundary_costs_extra: false }
 }
 
-fn cycles_with_extra_cost(cycles: u8) -> CycleCount {
+const fn cycles_with_extra_cost(cycles: u8) -> CycleCount {
     CycleCount { cycles, page_boundary_costs_extra: true }
 }
 
@@ -258,8 +558,80 @@ mod unit_tests {
         use super::*;
 
         #[test]
-        fn it_errors_when_instruction_not_found() {
-            assert!(decode_instruction(0x80).is_err());
+        fn it_returns_none_when_instruction_not_found() {
+            assert!(decode_instruction(0x02, CpuVariant::Nmos6502).is_none());
+        }
+
+        #[test]
+        fn it_decodes_all_but_the_jam_opcodes() {
+            let jams = [0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2];
+            for opcode in 0..=255u8 {
+                let decoded = decode_instruction(opcode, CpuVariant::Nmos6502);
+                assert_eq!(decoded.is_none(), jams.contains(&opcode), "opcode {:02X}", opcode);
+                if let Some(instruction) = decoded {
+                    let timing = calculate_cycles(&instruction, CpuVariant::Nmos6502);
+                    assert!(timing.is_some(), "opcode {:02X}", opcode);
+                }
+            }
+        }
+
+        #[test]
+        fn it_decodes_all_cmos_opcodes() {
+            for opcode in 0..=255u8 {
+                let instruction = decode_instruction(opcode, CpuVariant::Wdc65C02).unwrap();
+                let timing = calculate_cycles(&instruction, CpuVariant::Wdc65C02);
+                assert!(timing.is_some(), "opcode {:02X}", opcode);
+            }
+        }
+
+        #[test]
+        fn it_decodes_wdc_extensions_as_nops_on_the_65sc02() {
+            for opcode in [0x07, 0x8F, 0xCB, 0xDB] {
+                let decoded = decode_instruction(opcode, CpuVariant::Wdc65SC02);
+                let Instruction(mode, op) = decoded.unwrap();
+                assert_eq!((mode, op), (OperandMode::OneCycleImplied, Operation::NOP));
+            }
+            let Instruction(mode, op) = decode_instruction(0x64, CpuVariant::Wdc65SC02).unwrap();
+            assert_eq!((mode, op), (OperandMode::ZeroPage, Operation::STZ));
+        }
+
+        #[test]
+        fn it_decodes_undocumented_instructions() {
+            let Instruction(mode, op) = decode_instruction(0xB3, CpuVariant::Nmos6502).unwrap();
+            assert_eq!((mode, op), (OperandMode::IndirectY, Operation::LAX));
+            let Instruction(mode, op) = decode_instruction(0xEB, CpuVariant::Nmos6502).unwrap();
+            assert_eq!((mode, op), (OperandMode::Immediate, Operation::SBC));
+            let Instruction(mode, op) = decode_instruction(0x1C, CpuVariant::Nmos6502).unwrap();
+            assert_eq!((mode, op), (OperandMode::AbsoluteX, Operation::NOP));
+        }
+
+        #[test]
+        fn it_times_the_cmos_reserved_opcodes() {
+            let timing = |opcode| {
+                let instruction = decode_instruction(opcode, CpuVariant::Wdc65C02).unwrap();
+                calculate_cycles(&instruction, CpuVariant::Wdc65C02).unwrap().cycles
+            };
+            assert_eq!(timing(0x03), 1);
+            assert_eq!(timing(0xFB), 1);
+            assert_eq!(timing(0x02), 2);
+            assert_eq!(timing(0x44), 3);
+            assert_eq!(timing(0xD4), 4);
+            assert_eq!(timing(0xDC), 4);
+            assert_eq!(timing(0x5C), 8);
+        }
+
+        #[test]
+        fn it_decodes_cmos_instructions_in_undocumented_slots() {
+            let Instruction(mode, op) = decode_instruction(0xB2, CpuVariant::Wdc65C02).unwrap();
+            assert_eq!((mode, op), (OperandMode::ZeroPageIndirect, Operation::LDA));
+            let Instruction(mode, op) = decode_instruction(0x7C, CpuVariant::Wdc65C02).unwrap();
+            assert_eq!((mode, op), (OperandMode::AbsoluteIndirectX, Operation::JMP));
+            let Instruction(mode, op) = decode_instruction(0xBF, CpuVariant::Wdc65C02).unwrap();
+            assert_eq!((mode, op), (OperandMode::ZeroPageRelative, Operation::BBS3));
+            let Instruction(mode, op) = decode_instruction(0xA3, CpuVariant::Wdc65C02).unwrap();
+            assert_eq!((mode, op), (OperandMode::OneCycleImplied, Operation::NOP));
+            let Instruction(mode, op) = decode_instruction(0xA9, CpuVariant::Wdc65C02).unwrap();
+            assert_eq!((mode, op), (OperandMode::Immediate, Operation::LDA));
         }
     }
 }
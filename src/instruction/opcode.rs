@@ -0,0 +1,168 @@
+use super::operand_mode::OperandMode;
+use super::operation::Operation;
+use super::{calculate_cycles, decode_instruction, Instruction};
+use crate::variant::CpuVariant;
+
+/// Everything known about an opcode without executing it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub opcode: u8,
+    pub operation: Operation,
+    pub mode: OperandMode,
+    /// Bytes including the opcode
+    pub length: u8,
+    /// Cycles when no page is crossed and no branch is taken
+    pub cycles: u8,
+    /// Takes an extra cycle when indexing crosses a page
+    pub page_cross_penalty: bool,
+    /// Takes an extra cycle when the branch is taken, and another when the target is on another
+    /// page
+    pub branch_penalty: bool,
+    pub documented: bool,
+}
+
+impl OpcodeInfo {
+    pub const fn instruction(&self) -> Instruction {
+        Instruction(self.mode, self.operation)
+    }
+}
+
+/// The NMOS 6502 opcodes, shared by the 2A03, 6507 and 6510, with None for the JAM opcodes
+pub const NMOS_6502_OPCODES: [Option<OpcodeInfo>; 256] = build_table(CpuVariant::Nmos6502);
+pub const WDC_65C02_OPCODES: [Option<OpcodeInfo>; 256] = build_table(CpuVariant::Wdc65C02);
+pub const WDC_65SC02_OPCODES: [Option<OpcodeInfo>; 256] = build_table(CpuVariant::Wdc65SC02);
+
+/// The opcode table for the variant's instruction set
+pub const fn opcode_table(variant: CpuVariant) -> &'static [Option<OpcodeInfo>; 256] {
+    match variant {
+        CpuVariant::Wdc65C02 => &WDC_65C02_OPCODES,
+        CpuVariant::Wdc65SC02 => &WDC_65SC02_OPCODES,
+        _ => &NMOS_6502_OPCODES,
+    }
+}
+
+pub const fn opcode_info(opcode: u8, variant: CpuVariant) -> Option<OpcodeInfo> {
+    opcode_table(variant)[opcode as usize]
+}
+
+/// The opcode for the instruction, preferring the documented one where several decode the same,
+/// e.g. $E9 rather than $EB for SBC #
+pub fn encode_instruction(instruction: Instruction, variant: CpuVariant) -> Option<u8> {
+    let Instruction(mode, operation) = instruction;
+    let mut matching = opcode_table(variant)
+        .iter()
+        .flatten()
+        .filter(|info| info.mode == mode && info.operation == operation);
+    let first = matching.next()?;
+    if first.documented {
+        return Some(first.opcode);
+    }
+    Some(matching.find(|info| info.documented).unwrap_or(first).opcode)
+}
+
+const fn build_table(variant: CpuVariant) -> [Option<OpcodeInfo>; 256] {
+    let mut table = [None; 256];
+    let mut opcode = 0;
+    while opcode < 256 {
+        table[opcode] = build_info(opcode as u8, variant);
+        opcode += 1;
+    }
+    table
+}
+
+const fn build_info(opcode: u8, variant: CpuVariant) -> Option<OpcodeInfo> {
+    let instruction = match decode_instruction(opcode, variant) {
+        Some(instruction) => instruction,
+        None => return None,
+    };
+    let timing = match calculate_cycles(&instruction, variant) {
+        Some(timing) => timing,
+        None => panic!("Decoded opcode without cycle timings"),
+    };
+    let Instruction(mode, operation) = instruction;
+    Some(OpcodeInfo {
+        opcode,
+        operation,
+        mode,
+        length: 1 + mode.operand_length(),
+        cycles: timing.cycles,
+        page_cross_penalty: timing.page_boundary_costs_extra,
+        branch_penalty: operation.is_branch(),
+        documented: is_documented(opcode, operation),
+    })
+}
+
+/// Only $EA is an official NOP, the other NOPs and the SBC at $EB are undocumented duplicates
+const fn is_documented(opcode: u8, operation: Operation) -> bool {
+    match operation {
+        Operation::NOP => opcode == 0xEA,
+        Operation::SBC => opcode != 0xEB,
+        _ => !operation.is_undocumented(),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    mod describe_opcode_table {
+        use super::*;
+
+        #[test]
+        fn it_describes_opcodes() {
+            let info = opcode_info(0xBD, CpuVariant::Nmos6502).unwrap();
+            assert_eq!(info.instruction(), Instruction(OperandMode::AbsoluteX, Operation::LDA));
+            assert_eq!((info.length, info.cycles), (3, 4));
+            assert!(info.page_cross_penalty && !info.branch_penalty && info.documented);
+
+            let info = opcode_info(0xD0, CpuVariant::Ricoh2A03).unwrap();
+            assert_eq!((info.length, info.cycles), (2, 2));
+            assert!(info.branch_penalty);
+
+            let info = opcode_info(0x0F, CpuVariant::Wdc65C02).unwrap();
+            assert_eq!(info.operation, Operation::BBR0);
+            assert_eq!((info.length, info.cycles), (3, 5));
+
+            assert!(!opcode_info(0xA7, CpuVariant::Nmos6502).unwrap().documented);
+            assert!(!opcode_info(0x07, CpuVariant::Wdc65SC02).unwrap().documented);
+            assert_eq!(opcode_info(0x02, CpuVariant::Nmos6502), None);
+        }
+
+        #[test]
+        fn it_counts_the_documented_opcodes() {
+            let documented = |variant| {
+                opcode_table(variant)
+                    .iter()
+                    .flatten()
+                    .filter(|info| info.documented)
+                    .count()
+            };
+            assert_eq!(documented(CpuVariant::Nmos6502), 151);
+            assert_eq!(documented(CpuVariant::Wdc65C02), 212);
+            assert_eq!(documented(CpuVariant::Wdc65SC02), 178);
+        }
+
+        #[test]
+        fn it_encodes_instructions() {
+            let sbc = Instruction(OperandMode::Immediate, Operation::SBC);
+            assert_eq!(encode_instruction(sbc, CpuVariant::Nmos6502), Some(0xE9));
+            let nop = Instruction(OperandMode::Implied, Operation::NOP);
+            assert_eq!(encode_instruction(nop, CpuVariant::Wdc65C02), Some(0xEA));
+            let lax = Instruction(OperandMode::IndirectY, Operation::LAX);
+            assert_eq!(encode_instruction(lax, CpuVariant::Nmos6502), Some(0xB3));
+            assert_eq!(encode_instruction(lax, CpuVariant::Wdc65C02), None);
+            let stz = Instruction(OperandMode::Absolute, Operation::STZ);
+            assert_eq!(encode_instruction(stz, CpuVariant::Nmos6502), None);
+        }
+
+        #[test]
+        fn it_round_trips_documented_opcodes() {
+            for variant in [CpuVariant::Nmos6502, CpuVariant::Wdc65C02, CpuVariant::Wdc65SC02] {
+                for info in opcode_table(variant).iter().flatten().filter(|i| i.documented) {
+                    let encoded = encode_instruction(info.instruction(), variant);
+                    assert_eq!(encoded, Some(info.opcode), "{:?} {:02X}", variant, info.opcode);
+                }
+            }
+        }
+    }
+}
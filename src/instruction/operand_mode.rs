@@ -1,6 +1,7 @@
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum OperandMode {
     Absolute,
+    AbsoluteIndirectX,
     AbsoluteX,
     AbsoluteY,
     Accumulator,
@@ -10,6 +11,35 @@ pub enum OperandMode {
     IndirectX,
     IndirectY,
     ZeroPage,
+    ZeroPageIndirect,
+    ZeroPageRelative,
     ZeroPageX,
     ZeroPageY,
+    /// The 65C02's one byte reserved opcodes, which are done in the cycle that fetches them
+    OneCycleImplied,
+    /// The 65C02's reserved opcode $5C, which reads an absolute address and takes eight cycles
+    EightCycleAbsolute,
+}
+
+impl OperandMode {
+    /// Number of bytes after the opcode
+    pub const fn operand_length(self) -> u8 {
+        match self {
+            OperandMode::Accumulator | OperandMode::Implied | OperandMode::OneCycleImplied => 0,
+            OperandMode::Immediate
+            | OperandMode::IndirectX
+            | OperandMode::IndirectY
+            | OperandMode::ZeroPage
+            | OperandMode::ZeroPageIndirect
+            | OperandMode::ZeroPageX
+            | OperandMode::ZeroPageY => 1,
+            OperandMode::Absolute
+            | OperandMode::AbsoluteIndirectX
+            | OperandMode::AbsoluteX
+            | OperandMode::AbsoluteY
+            | OperandMode::Indirect
+            | OperandMode::ZeroPageRelative
+            | OperandMode::EightCycleAbsolute => 2,
+        }
+    }
 }
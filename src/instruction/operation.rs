@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Operation {
     ADC, AND, ASL, BCC, BCS, BEQ, BIT, BMI,
     BNE, BPL, BRK, BVC, BVS, CLC, CLD, CLI,
@@ -6,5 +6,46 @@ pub enum Operation {
     INC, INX, INY, JMP, JSR, LDA, LDX, LDY,
     LSR, NOP, ORA, PHA, PHP, PLA, PLP, ROL,
     ROR, RTI, RTS, SBC, SEC, SED, SEI, STA,
-    STX, STY, TAX, TAY, TSX, TXA, TXS, TYA
+    STX, STY, TAX, TAY, TSX, TXA, TXS, TYA,
+    // Undocumented NMOS instructions
+    AHX, ALR, ANC, ARR, AXS, DCP, ISC, LAS,
+    LAX, LXA, RLA, RRA, SAX, SHX, SHY, SLO,
+    SRE, TAS, XAA,
+    // 65C02 instructions
+    BRA, PHX, PHY, PLX, PLY, STP, STZ, TRB,
+    TSB, WAI,
+    // Rockwell bit instructions, as found on the WDC 65C02
+    BBR0, BBR1, BBR2, BBR3, BBR4, BBR5, BBR6, BBR7,
+    BBS0, BBS1, BBS2, BBS3, BBS4, BBS5, BBS6, BBS7,
+    RMB0, RMB1, RMB2, RMB3, RMB4, RMB5, RMB6, RMB7,
+    SMB0, SMB1, SMB2, SMB3, SMB4, SMB5, SMB6, SMB7,
+}
+
+impl Operation {
+    /// True for the relative branches, whose last operand byte is a signed offset from the next
+    /// instruction
+    pub const fn is_branch(self) -> bool {
+        matches!(
+            self,
+            Operation::BCC | Operation::BCS | Operation::BEQ | Operation::BMI |
+            Operation::BNE | Operation::BPL | Operation::BVC | Operation::BVS |
+            Operation::BRA |
+            Operation::BBR0 | Operation::BBR1 | Operation::BBR2 | Operation::BBR3 |
+            Operation::BBR4 | Operation::BBR5 | Operation::BBR6 | Operation::BBR7 |
+            Operation::BBS0 | Operation::BBS1 | Operation::BBS2 | Operation::BBS3 |
+            Operation::BBS4 | Operation::BBS5 | Operation::BBS6 | Operation::BBS7
+        )
+    }
+
+    /// True for the NMOS instructions outside the official instruction set
+    pub const fn is_undocumented(self) -> bool {
+        matches!(
+            self,
+            Operation::AHX | Operation::ALR | Operation::ANC | Operation::ARR |
+            Operation::AXS | Operation::DCP | Operation::ISC | Operation::LAS |
+            Operation::LAX | Operation::LXA | Operation::RLA | Operation::RRA |
+            Operation::SAX | Operation::SHX | Operation::SHY | Operation::SLO |
+            Operation::SRE | Operation::TAS | Operation::XAA
+        )
+    }
 }
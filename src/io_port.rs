@@ -0,0 +1,63 @@
+use crate::snapshot::{Reader, SnapshotError};
+
+/// The 6510 on-chip I/O port
+/// $0000 is the data direction register, bits set to 1 are outputs; $0001 reads the output latch
+/// for output bits and the external input for the others
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IoPort {
+    direction: u8,
+    output: u8,
+    input: u8,
+}
+
+impl Default for IoPort {
+    /// All bits are inputs at reset, and unconnected inputs float high
+    fn default() -> IoPort {
+        IoPort {
+            direction: 0x00,
+            output: 0x00,
+            input: 0xFF,
+        }
+    }
+}
+
+impl IoPort {
+    pub fn is_port_address(address: u16) -> bool {
+        address < 2
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0 => self.direction,
+            _ => self.pins(),
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0 => self.direction = value,
+            _ => self.output = value,
+        }
+    }
+
+    /// The levels on the port pins, as seen by the rest of the machine
+    pub fn pins(&self) -> u8 {
+        (self.output & self.direction) | (self.input & !self.direction)
+    }
+
+    pub fn set_input(&mut self, value: u8) {
+        self.input = value;
+    }
+
+    pub fn save_snapshot(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[self.direction, self.output, self.input]);
+    }
+
+    pub fn restore_snapshot(reader: &mut Reader) -> Result<IoPort, SnapshotError> {
+        Ok(IoPort {
+            direction: reader.u8()?,
+            output: reader.u8()?,
+            input: reader.u8()?,
+        })
+    }
+}
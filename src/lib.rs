@@ -1,11 +1,48 @@
 use std::vec::Vec;
 
-mod instruction;
+mod assembler;
+mod bus;
+mod disassembler;
+mod error;
+mod export;
+mod history;
+pub mod instruction;
+mod io_port;
+mod loader;
+mod memory_map;
+mod provenance;
+mod run;
+mod snapshot;
+mod tick;
 mod util;
-
-use instruction::operand_mode::OperandMode;
-use instruction::operation::Operation;
+mod variant;
+
+#[doc(hidden)]
+pub use assembler::assemble_tokens;
+pub use assembler::{
+    assemble, Assembler, AssemblerError, Assembly, BlockCycles, Listing, ListingLine,
+};
+pub use bus::Bus;
+pub use disassembler::DisassembledLine;
+pub use error::CpuError;
+pub use history::HistoryPoint;
+pub use instruction::operand_mode::OperandMode;
+pub use instruction::operation::Operation;
+pub use instruction::Instruction;
+pub use loader::{
+    ImageFormat, LoadError, LoadedImage, O65Export, O65Layout, O65Module, Segment,
+};
+pub use memory_map::{MemoryMap, MemoryMapBuilder, MemoryMapError, OpenBus};
+pub use provenance::WriteRecord;
+pub use run::{StopConditions, StopReason};
+pub use snapshot::{Snapshot, SnapshotError, SNAPSHOT_VERSION};
+pub use variant::CpuVariant;
+
+use history::History;
 use instruction::{calculate_cycles, decode_instruction};
+use io_port::IoPort;
+use provenance::WriteTracker;
+use tick::Sequence;
 use util::is_negative;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
@@ -18,6 +55,75 @@ pub struct RegisterFile {
     program_counter: u16,
 }
 
+impl RegisterFile {
+    pub fn accumulator(&self) -> u8 {
+        self.accumulator
+    }
+
+    pub fn set_accumulator(&mut self, value: u8) {
+        self.accumulator = value;
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn set_x(&mut self, value: u8) {
+        self.x = value;
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    pub fn set_y(&mut self, value: u8) {
+        self.y = value;
+    }
+
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    pub fn set_stack_pointer(&mut self, value: u8) {
+        self.stack_pointer = value;
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, value: u16) {
+        self.program_counter = value;
+    }
+
+    /// The P register as the CPU holds it
+    /// Bit 5 isn't wired to anything and always reads 1, and BREAK only exists in the copies PHP
+    /// and BRK push, so it always reads 0
+    pub fn status(&self) -> u8 {
+        (self.status & !(1 << StatusFlag::BREAK as u8)) | (1 << StatusFlag::RESERVED as u8)
+    }
+
+    /// Sets the P register, ignoring bits 4 and 5 like PLP and RTI do
+    pub fn set_status(&mut self, value: u8) {
+        self.status = value;
+        self.status = self.status();
+    }
+
+    #[allow(clippy::needless_return)]
+    pub fn flag(&self, status_flag: StatusFlag) -> bool {
+        let index = status_flag as u8;
+        let flag = self.status >> index;
+
+        return (flag & 0x1) != 0;
+    }
+
+    pub fn set_flag(&mut self, status_flag: StatusFlag, new_flag: bool) {
+        let index = status_flag as u8;
+        self.status &= !(1 << index);
+        self.status |= (new_flag as u8) << index;
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum StatusFlag {
     CARRY = 0,
@@ -30,11 +136,53 @@ pub enum StatusFlag {
     NEGATIVE = 7,
 }
 
+const NMI_VECTOR: usize = 0xfffa;
+const RESET_VECTOR: usize = 0xfffc;
+const IRQ_VECTOR: usize = 0xfffe;
+
+const INTERRUPT_CYCLES: u32 = 7;
+
+/// Value ORed into the accumulator by the unstable XAA and LXA instructions, which varies between
+/// chips and with temperature on real hardware
+const UNSTABLE_MAGIC_CONSTANT: u8 = 0xEE;
+
+/// The CPU and everything it's attached to, through a Bus which defaults to 64K of flat RAM
 #[derive(Clone, PartialEq, Eq)]
-pub struct ComputerState {
-    pub memory: Vec<u8>,
+pub struct ComputerState<B: Bus = Vec<u8>> {
+    pub memory: B,
     pub registers: RegisterFile,
     pub cycles: u32,
+    variant: CpuVariant,
+    io_port: Option<IoPort>,
+    irq_line: bool,
+    nmi_line: bool,
+    nmi_pending: bool,
+    reset_pending: bool,
+    waiting_for_interrupt: bool,
+    stopped: bool,
+    branch_taken: bool,
+    sequence: Option<Sequence>,
+    history: Option<History>,
+    write_tracker: Option<WriteTracker>,
+}
+
+/// What a call to step() did
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StepResult {
+    /// None when an interrupt sequence ran or the CPU idled after WAI or STP
+    pub instruction: Option<Instruction>,
+    pub cycles: u32,
+    pub branch_taken: bool,
+}
+
+impl StepResult {
+    fn without_instruction(cycles: u32) -> StepResult {
+        StepResult {
+            instruction: None,
+            cycles,
+            branch_taken: false,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -43,47 +191,184 @@ enum Operand {
     Address(u16),
     Immediate(u8),
     Implied,
+    /// An indexed address and the high byte of the address it was indexed from
+    Indexed(u16, u8),
+    /// An address whose value was already read from the bus in an earlier cycle
+    Latched(u16, u8),
+    ZeroPageRelative(u16, u8),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Interrupt {
+    Reset,
+    Nmi,
+    Irq,
 }
 
 impl ComputerState {
     pub fn initialize() -> ComputerState {
-        ComputerState {
-            memory: vec![0; 2usize.pow(16)],
-            registers: RegisterFile {
-                ..Default::default()
-            },
-            cycles: 0,
-        }
+        ComputerState::initialize_with_variant(CpuVariant::default())
     }
 
     pub fn initialize_from_image(memory: Vec<u8>) -> ComputerState {
+        ComputerState::initialize_from_image_with_variant(memory, CpuVariant::default())
+    }
+
+    pub fn initialize_with_variant(variant: CpuVariant) -> ComputerState {
+        ComputerState::initialize_from_image_with_variant(vec![0; 2usize.pow(16)], variant)
+    }
+
+    pub fn initialize_from_image_with_variant(
+        memory: Vec<u8>,
+        variant: CpuVariant,
+    ) -> ComputerState {
+        ComputerState::initialize_with_bus(memory, variant)
+    }
+
+    /// Builds a state from the image and runs the power-on reset sequence, so execution starts
+    /// at the address in the reset vector ($FFFC/$FFFD) like it would on hardware
+    pub fn boot_from_image(memory: Vec<u8>) -> ComputerState {
+        let mut state = ComputerState::initialize_from_image(memory);
+        state.reset();
+        state
+    }
+
+    /// A 64K machine with the program at the address and about to run it, with an empty stack
+    #[cfg(test)]
+    pub(crate) fn with_program(
+        variant: CpuVariant,
+        address: u16,
+        program: &[u8],
+    ) -> ComputerState {
+        let mut memory = vec![0; 0x10000];
+        let address = address as usize;
+        memory[address..address + program.len()].copy_from_slice(program);
+        let mut state = ComputerState::initialize_from_image_with_variant(memory, variant);
+        state.registers.set_program_counter(address as u16);
+        state.registers.set_stack_pointer(0xFF);
+        state
+    }
+}
+
+impl<B: Bus> ComputerState<B> {
+    pub fn initialize_with_bus(memory: B, variant: CpuVariant) -> ComputerState<B> {
+        ComputerState::initialize_with_registers(memory, variant, RegisterFile::default())
+    }
+
+    /// Builds a state with the registers already set up, e.g. to call a routine directly
+    pub fn initialize_with_registers(
+        memory: B,
+        variant: CpuVariant,
+        registers: RegisterFile,
+    ) -> ComputerState<B> {
         ComputerState {
             memory,
-            registers: RegisterFile {
-                ..Default::default()
-            },
+            registers,
             cycles: 0,
+            variant,
+            io_port: if variant.has_io_port() {
+                Some(IoPort::default())
+            } else {
+                None
+            },
+            irq_line: false,
+            nmi_line: false,
+            nmi_pending: false,
+            reset_pending: false,
+            waiting_for_interrupt: false,
+            stopped: false,
+            branch_taken: false,
+            sequence: None,
+            history: None,
+            write_tracker: None,
         }
     }
 
-    pub fn get_byte_from_memory(&self, index: usize) -> u8 {
-        self.memory[index]
+    pub fn variant(&self) -> CpuVariant {
+        self.variant
+    }
+
+    /// Levels on the 6510 I/O port pins, or None on chips without the port
+    pub fn io_port_pins(&self) -> Option<u8> {
+        self.io_port.map(|port| port.pins())
+    }
+
+    /// Drives the 6510 I/O port pins configured as inputs, ignored on chips without the port
+    pub fn set_io_port_input(&mut self, value: u8) {
+        if let Some(port) = &mut self.io_port {
+            port.set_input(value);
+        }
     }
 
-    pub fn get_word_from_memory(&self, index: usize) -> u16 {
-        let low = self.memory[index];
-        let high = self.memory[index + 1];
+    /// Runs the reset sequence immediately: loads the program counter from the reset vector,
+    /// decrements the stack pointer by 3 (from $00 to $FD at power-on), sets the INTERRUPT and
+    /// RESERVED flags and charges the 7 reset cycles
+    pub fn reset(&mut self) {
+        self.acknowledge_reset();
+        self.execute_reset();
+        self.cycles += INTERRUPT_CYCLES;
+    }
+
+    /// Reset abandons everything in flight, including an instruction part way through in tick mode
+    fn acknowledge_reset(&mut self) {
+        self.reset_pending = false;
+        self.nmi_pending = false;
+        self.waiting_for_interrupt = false;
+        self.stopped = false;
+        self.sequence = None;
+    }
+
+    /// Reads a byte the way the CPU does, with any side effects the bus has on reads
+    pub fn get_byte_from_memory(&mut self, index: usize) -> u8 {
+        let address = index as u16 & self.variant.address_mask();
+        match self.io_port {
+            Some(port) if IoPort::is_port_address(address) => port.read(address),
+            _ => self.memory.read(address),
+        }
+    }
+
+    pub fn get_word_from_memory(&mut self, index: usize) -> u16 {
+        let low = self.get_byte_from_memory(index);
+        let high = self.get_byte_from_memory(index + 1);
+        u16::from_le_bytes([low, high])
+    }
+
+    /// Inspects a byte without side effects, as seen by the CPU
+    pub fn peek_byte_from_memory(&self, index: usize) -> u8 {
+        let address = index as u16 & self.variant.address_mask();
+        match self.io_port {
+            Some(port) if IoPort::is_port_address(address) => port.read(address),
+            _ => self.memory.peek(address),
+        }
+    }
+
+    pub fn peek_word_from_memory(&self, index: usize) -> u16 {
+        let low = self.peek_byte_from_memory(index);
+        let high = self.peek_byte_from_memory(index + 1);
         u16::from_le_bytes([low, high])
     }
 
+    /// Writes to the 6510 I/O port also reach the RAM underneath it
     pub fn write_byte_to_memory(&mut self, index: usize, value: u8) {
-        self.memory[index] = value;
+        let address = index as u16 & self.variant.address_mask();
+        if let Some(port) = &mut self.io_port {
+            if IoPort::is_port_address(address) {
+                port.write(address, value);
+            }
+        }
+        if let Some(history) = &mut self.history {
+            history.record_write(address, self.memory.peek(address));
+        }
+        if let Some(tracker) = &mut self.write_tracker {
+            tracker.record(address, value, self.registers.program_counter, self.cycles);
+        }
+        self.memory.write(address, value);
     }
 
     pub fn write_word_to_memory(&mut self, index: usize, value: u16) {
         let bytes = value.to_le_bytes();
-        self.memory[index] = bytes[0];
-        self.memory[index + 1] = bytes[1];
+        self.write_byte_to_memory(index, bytes[0]);
+        self.write_byte_to_memory(index + 1, bytes[1]);
     }
 
     pub fn pull_byte_from_stack(&mut self) -> u8 {
@@ -110,34 +395,240 @@ impl ComputerState {
         self.push_byte_to_stack(bytes[0]);
     }
 
-    pub fn step(mut self) -> Result<Self, &'static str> {
-        let instruction = self.memory[self.registers.program_counter as usize];
-        self.registers.program_counter += 1;
+    /// Asserts or deasserts the level-triggered IRQ line
+    /// While asserted, an interrupt is serviced before every instruction for which the INTERRUPT
+    /// flag is clear
+    pub fn set_irq_line(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
 
-        let decoded_instruction = decode_instruction(instruction)?;
+    /// Drives the edge-triggered NMI line
+    /// An interrupt is latched when the line goes from deasserted to asserted, and serviced
+    /// before the next instruction regardless of the INTERRUPT flag
+    pub fn set_nmi_line(&mut self, asserted: bool) {
+        if asserted && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = asserted;
+    }
 
-        let (operand, page_boundary_crossed) = self.fetch_operand(&decoded_instruction.0);
+    /// Pulses the NMI line, latching a single non-maskable interrupt
+    pub fn trigger_nmi(&mut self) {
+        self.set_nmi_line(true);
+        self.set_nmi_line(false);
+    }
+
+    /// Pulses the RESET line; the reset sequence runs before the next instruction
+    pub fn pulse_reset(&mut self) {
+        self.reset_pending = true;
+    }
+
+    /// Executes a whole instruction, or interrupt sequence, at once
+    /// An instruction started with tick() is run to completion instead. On error the registers
+    /// and cycle count are left as they were before the faulting instruction.
+    pub fn step(&mut self) -> Result<StepResult, CpuError> {
+        self.record_step();
+        let registers = self.registers;
+        let cycles = self.cycles;
+        // Until an opcode is fetched, any writes come from an interrupt sequence
+        if self.sequence.is_none() {
+            self.set_write_source(registers.program_counter, None, cycles);
+        }
+        let result = self.execute_step();
+        self.clear_write_source();
+        if result.is_err() {
+            self.registers = registers;
+            self.cycles = cycles;
+            self.discard_failed_step();
+        }
+        result
+    }
+
+    /// Steps until the given number of instructions have run, or one fails
+    pub fn multiple_steps(&mut self, steps: u32) -> Result<(), CpuError> {
+        for _ in 0..steps {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    fn execute_step(&mut self) -> Result<StepResult, CpuError> {
+        let start_cycles = self.cycles;
+        if let Some(sequence) = self.sequence {
+            while self.sequence.is_some() {
+                self.tick()?;
+            }
+            return Ok(StepResult {
+                instruction: sequence.instruction(),
+                cycles: self.cycles - start_cycles,
+                branch_taken: sequence.instruction().is_some() && self.branch_taken,
+            });
+        }
 
-        let cycle_cost = calculate_cycles(&decoded_instruction)?;
+        if self.service_interrupts() {
+            return Ok(StepResult::without_instruction(self.cycles - start_cycles));
+        }
+
+        // A CPU halted by WAI or STP idles, a cycle per step
+        if self.waiting_for_interrupt || self.stopped {
+            self.cycles += 1;
+            return Ok(StepResult::without_instruction(self.cycles - start_cycles));
+        }
+
+        let program_counter = self.registers.program_counter;
+        let opcode = self.get_byte_from_memory(program_counter as usize);
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
+
+        let decoded_instruction =
+            decode_instruction(opcode, self.variant).ok_or(CpuError::UnknownOpcode {
+                opcode,
+                program_counter,
+                cycles: start_cycles,
+            })?;
+        self.set_write_source(program_counter, Some(opcode), start_cycles);
+
+        let (operand, page_boundary_crossed) = self.fetch_operand(&decoded_instruction.0);
+        let next_instruction = self.registers.program_counter;
+        self.branch_taken = false;
+
+        let cycle_cost = calculate_cycles(&decoded_instruction, self.variant).ok_or(
+            CpuError::MissingTimings {
+                opcode,
+                instruction: decoded_instruction,
+                program_counter,
+                cycles: start_cycles,
+            },
+        )?;
         self.cycles += cycle_cost.cycles as u32;
         if cycle_cost.page_boundary_costs_extra && page_boundary_crossed {
             self.cycles += 1
         }
-        self.execute_operation(decoded_instruction.1, operand)?;
+        // The 65C02 takes an extra cycle to produce valid flags in decimal mode
+        if self.variant.is_cmos()
+            && self.decimal_mode_active()
+            && matches!(decoded_instruction.1, Operation::ADC | Operation::SBC)
+        {
+            self.cycles += 1
+        }
+        self.execute_operation(decoded_instruction.1, operand)
+            .map_err(|reason| CpuError::InvalidOperand {
+                opcode,
+                instruction: decoded_instruction,
+                program_counter,
+                cycles: start_cycles,
+                reason,
+            })?;
+
+        // Taken branches take an extra cycle, and another one if the target is on another page
+        if self.branch_taken {
+            self.cycles += 1;
+            if (next_instruction ^ self.registers.program_counter) & 0xFF00 != 0 {
+                self.cycles += 1;
+            }
+        }
+
+        Ok(StepResult {
+            instruction: Some(decoded_instruction),
+            cycles: self.cycles - start_cycles,
+            branch_taken: self.branch_taken,
+        })
+    }
+
+
+    /// Runs the highest priority pending interrupt sequence, if any
+    /// Returns true if an interrupt was serviced in place of the next instruction
+    fn service_interrupts(&mut self) -> bool {
+        match self.take_pending_interrupt() {
+            Some(Interrupt::Reset) => self.reset(),
+            Some(Interrupt::Nmi) => {
+                self.execute_interrupt(NMI_VECTOR);
+                self.cycles += INTERRUPT_CYCLES;
+            }
+            Some(Interrupt::Irq) => {
+                self.execute_interrupt(IRQ_VECTOR);
+                self.cycles += INTERRUPT_CYCLES;
+            }
+            None => return false,
+        }
+        true
+    }
+
+    /// Picks the highest priority pending interrupt, latched NMIs are cleared as they're taken
+    fn take_pending_interrupt(&mut self) -> Option<Interrupt> {
+        if self.reset_pending {
+            return Some(Interrupt::Reset);
+        }
 
-        Ok(self)
+        if self.stopped {
+            return None;
+        }
+
+        // WAI resumes on IRQ even when it's masked, continuing with the next instruction
+        if self.irq_line {
+            self.waiting_for_interrupt = false;
+        }
+
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            Some(Interrupt::Nmi)
+        } else if self.irq_line && !self.get_status_flag(StatusFlag::INTERRUPT) {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        }
+    }
+
+    /// Pushes the return address and status (with BREAK cleared) and jumps through the vector
+    fn execute_interrupt(&mut self, vector: usize) {
+        self.push_word_to_stack(self.registers.program_counter);
+        self.push_byte_to_stack(self.interrupt_status());
+
+        self.enter_interrupt();
+        self.registers.program_counter = self.get_word_from_memory(vector);
+    }
+
+    /// IRQ and NMI push the status with BREAK clear, while PHP and BRK set it
+    fn interrupt_status(&self) -> u8 {
+        self.registers.status()
+    }
+
+    fn pushed_status(&self) -> u8 {
+        self.registers.status() | (1 << StatusFlag::BREAK as u8)
+    }
+
+    fn enter_interrupt(&mut self) {
+        self.waiting_for_interrupt = false;
+        self.set_status_flag(StatusFlag::INTERRUPT, true);
+        if self.variant.is_cmos() {
+            self.set_status_flag(StatusFlag::DECIMAL, false);
+        }
     }
 
-    pub fn multiple_steps(self, steps: u32) -> Result<Self, &'static str> {
-        (0..steps).try_fold(self, |state, _| state.step())
+    /// The reset sequence goes through the motions of an interrupt with the bus in read mode,
+    /// so the stack pointer is decremented without anything being written
+    fn execute_reset(&mut self) {
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(3);
+        self.enter_reset();
+        self.registers.program_counter = self.get_word_from_memory(RESET_VECTOR);
+    }
+
+    fn enter_reset(&mut self) {
+        self.set_status_flag(StatusFlag::INTERRUPT, true);
+        self.set_status_flag(StatusFlag::RESERVED, true);
+        if self.variant.is_cmos() {
+            self.set_status_flag(StatusFlag::DECIMAL, false);
+        }
     }
 
-    fn get_operand_value(&self, operand: Operand) -> Result<u8, &'static str> {
+    fn get_operand_value(&mut self, operand: Operand) -> Result<u8, &'static str> {
         match operand {
             Operand::Accumulator => Ok(self.registers.accumulator),
             Operand::Address(addr) => Ok(self.get_byte_from_memory(addr as usize)),
             Operand::Immediate(value) => Ok(value),
             Operand::Implied => Err("Cannot get implied operand value"),
+            Operand::Indexed(addr, _) => Ok(self.get_byte_from_memory(addr as usize)),
+            Operand::Latched(_, value) => Ok(value),
+            Operand::ZeroPageRelative(addr, _) => Ok(self.get_byte_from_memory(addr as usize)),
         }
     }
 
@@ -147,21 +638,24 @@ impl ComputerState {
             Operand::Address(addr) => self.write_byte_to_memory(addr as usize, value),
             Operand::Immediate(_) => return Err("Cannot set immediate operand value"),
             Operand::Implied => return Err("Cannot set implied operand value"),
+            Operand::Indexed(addr, _) => self.write_byte_to_memory(addr as usize, value),
+            Operand::Latched(addr, _) => self.write_byte_to_memory(addr as usize, value),
+            Operand::ZeroPageRelative(addr, _) => self.write_byte_to_memory(addr as usize, value),
         }
         Ok(())
     }
 
-    fn get_status_flag(&self, status_flag: StatusFlag) -> bool {
-        let index = status_flag as u8;
-        let flag = self.registers.status >> index;
+    pub fn get_status_flag(&self, status_flag: StatusFlag) -> bool {
+        self.registers.flag(status_flag)
+    }
 
-        return (flag & 0x1) != 0;
+    pub fn set_status_flag(&mut self, status_flag: StatusFlag, new_flag: bool) {
+        self.registers.set_flag(status_flag, new_flag)
     }
 
-    fn set_status_flag(&mut self, status_flag: StatusFlag, new_flag: bool) {
-        let index = status_flag as u8;
-        self.registers.status &= !(1 << index);
-        self.registers.status |= (new_flag as u8) << index;
+    /// The DECIMAL flag can always be set, but the 2A03 has no decimal mode to act on it
+    fn decimal_mode_active(&self) -> bool {
+        self.variant.has_decimal_mode() && self.get_status_flag(StatusFlag::DECIMAL)
     }
 
     fn set_zero_and_negative_flags(&mut self, value: u8) {
@@ -169,11 +663,12 @@ impl ComputerState {
         self.set_status_flag(StatusFlag::NEGATIVE, value & (1 << 7) != 0);
     }
 
+    #[allow(clippy::unit_arg)]
     fn execute_operation(&mut self, op: Operation, operand: Operand) -> Result<(), &'static str> {
         match op {
             Operation::ADC => Ok(self.execute_add_with_carry(operand)?),
             Operation::AND => Ok(self.execute_and(operand)?),
-            Operation::ASL => Ok(self.execute_left_shift(operand)?),
+            Operation::ASL => Ok(self.execute_read_modify_write(operand, Self::shift_left)?),
             Operation::BCC => Ok(self.execute_branch_if(operand, StatusFlag::CARRY, false)?),
             Operation::BCS => Ok(self.execute_branch_if(operand, StatusFlag::CARRY, true)?),
             Operation::BEQ => Ok(self.execute_branch_if(operand, StatusFlag::ZERO, true)?),
@@ -191,11 +686,11 @@ impl ComputerState {
             Operation::CMP => Ok(self.execute_compare(operand, self.registers.accumulator)?),
             Operation::CPX => Ok(self.execute_compare(operand, self.registers.x)?),
             Operation::CPY => Ok(self.execute_compare(operand, self.registers.y)?),
-            Operation::DEC => Ok(self.execute_increment(operand, true)?),
+            Operation::DEC => Ok(self.execute_read_modify_write(operand, Self::decrement)?),
             Operation::DEX => Ok(self.execute_increment_x(true)?),
             Operation::DEY => Ok(self.execute_increment_y(true)?),
             Operation::EOR => Ok(self.execute_exclusive_or(operand)?),
-            Operation::INC => Ok(self.execute_increment(operand, false)?),
+            Operation::INC => Ok(self.execute_read_modify_write(operand, Self::increment)?),
             Operation::INX => Ok(self.execute_increment_x(false)?),
             Operation::INY => Ok(self.execute_increment_y(false)?),
             Operation::JMP => Ok(self.execute_jump(operand, false)?),
@@ -203,15 +698,15 @@ impl ComputerState {
             Operation::LDA => Ok(self.execute_load_accumulator(operand)?),
             Operation::LDX => Ok(self.execute_load_x(operand)?),
             Operation::LDY => Ok(self.execute_load_y(operand)?),
-            Operation::LSR => Ok(self.execute_right_shift(operand)?),
+            Operation::LSR => Ok(self.execute_read_modify_write(operand, Self::shift_right)?),
             Operation::NOP => Ok(()),
             Operation::ORA => Ok(self.execute_inclusive_or(operand)?),
             Operation::PHA => Ok(self.push_byte_to_stack(self.registers.accumulator)),
-            Operation::PHP => Ok(self.push_byte_to_stack(self.registers.status)),
+            Operation::PHP => Ok(self.push_byte_to_stack(self.pushed_status())),
             Operation::PLA => Ok(self.execute_pull_accumulator()?),
             Operation::PLP => Ok(self.execute_pull_status()?),
-            Operation::ROL => Ok(self.execute_rotate_left(operand)?),
-            Operation::ROR => Ok(self.execute_rotate_right(operand)?),
+            Operation::ROL => Ok(self.execute_read_modify_write(operand, Self::rotate_left)?),
+            Operation::ROR => Ok(self.execute_read_modify_write(operand, Self::rotate_right)?),
             Operation::RTI => Ok(self.execute_return_from_interrupt()?),
             Operation::RTS => Ok(self.execute_return_from_subroutine()?),
             Operation::SBC => Ok(self.execute_substract_with_carry(operand)?),
@@ -227,87 +722,376 @@ impl ComputerState {
             Operation::TXA => Ok(self.execute_transfer_to_accumulator(self.registers.x)),
             Operation::TXS => Ok(self.execute_transfer_to_stack_pointer(self.registers.x)),
             Operation::TYA => Ok(self.execute_transfer_to_accumulator(self.registers.y)),
+
+            // Undocumented NMOS instructions
+            Operation::ALR => Ok(self.execute_and_then(operand, Operation::LSR)?),
+            Operation::ANC => Ok(self.execute_and_copy_negative_to_carry(operand)?),
+            Operation::ARR => Ok(self.execute_and_rotate_right(operand)?),
+            Operation::AXS => Ok(self.execute_and_x_substract(operand)?),
+            Operation::DCP => Ok(self.execute_combined(operand, Self::decrement, Operation::CMP)?),
+            Operation::ISC => Ok(self.execute_combined(operand, Self::increment, Operation::SBC)?),
+            Operation::LAX => Ok(self.execute_load_accumulator_and_x(operand)?),
+            Operation::RLA => {
+                Ok(self.execute_combined(operand, Self::rotate_left, Operation::AND)?)
+            }
+            Operation::RRA => {
+                Ok(self.execute_combined(operand, Self::rotate_right, Operation::ADC)?)
+            }
+            Operation::SAX => Ok(self.execute_store_accumulator_and_x(operand)?),
+            Operation::SLO => Ok(self.execute_combined(operand, Self::shift_left, Operation::ORA)?),
+            Operation::SRE => {
+                Ok(self.execute_combined(operand, Self::shift_right, Operation::EOR)?)
+            }
+
+            // The unstable instructions are emulated with their most commonly observed behaviour:
+            // XAA and LXA use UNSTABLE_MAGIC_CONSTANT, and the stores AND their value with the
+            // base address high byte plus one, and write to an address whose high byte is that
+            // result when indexing crosses a page
+            Operation::AHX => Ok(self.execute_unstable_store_accumulator_and_x(operand)?),
+            Operation::LAS => Ok(self.execute_load_accumulator_x_and_stack_pointer(operand)?),
+            Operation::LXA => Ok(self.execute_unstable_load_accumulator_and_x(operand)?),
+            Operation::SHX => Ok(self.execute_unstable_store(operand, self.registers.x)?),
+            Operation::SHY => Ok(self.execute_unstable_store(operand, self.registers.y)?),
+            Operation::TAS => Ok(self.execute_transfer_and_store(operand)?),
+            Operation::XAA => Ok(self.execute_unstable_transfer_x_and(operand)?),
+
+            // 65C02 instructions
+            Operation::BRA => Ok(self.execute_branch(operand)?),
+            Operation::PHX => {
+                self.push_byte_to_stack(self.registers.x);
+                Ok(())
+            }
+            Operation::PHY => {
+                self.push_byte_to_stack(self.registers.y);
+                Ok(())
+            }
+            Operation::PLX => Ok(self.execute_pull_x()?),
+            Operation::PLY => Ok(self.execute_pull_y()?),
+            Operation::STP => {
+                self.stopped = true;
+                Ok(())
+            }
+            Operation::STZ => Ok(self.set_operand_value(operand, 0)?),
+            Operation::TRB => Ok(self.execute_test_and_reset_bits(operand)?),
+            Operation::TSB => Ok(self.execute_test_and_set_bits(operand)?),
+            Operation::WAI => {
+                self.waiting_for_interrupt = true;
+                Ok(())
+            }
+            Operation::BBR0 => Ok(self.execute_branch_on_bit(operand, 0, false)?),
+            Operation::BBR1 => Ok(self.execute_branch_on_bit(operand, 1, false)?),
+            Operation::BBR2 => Ok(self.execute_branch_on_bit(operand, 2, false)?),
+            Operation::BBR3 => Ok(self.execute_branch_on_bit(operand, 3, false)?),
+            Operation::BBR4 => Ok(self.execute_branch_on_bit(operand, 4, false)?),
+            Operation::BBR5 => Ok(self.execute_branch_on_bit(operand, 5, false)?),
+            Operation::BBR6 => Ok(self.execute_branch_on_bit(operand, 6, false)?),
+            Operation::BBR7 => Ok(self.execute_branch_on_bit(operand, 7, false)?),
+            Operation::BBS0 => Ok(self.execute_branch_on_bit(operand, 0, true)?),
+            Operation::BBS1 => Ok(self.execute_branch_on_bit(operand, 1, true)?),
+            Operation::BBS2 => Ok(self.execute_branch_on_bit(operand, 2, true)?),
+            Operation::BBS3 => Ok(self.execute_branch_on_bit(operand, 3, true)?),
+            Operation::BBS4 => Ok(self.execute_branch_on_bit(operand, 4, true)?),
+            Operation::BBS5 => Ok(self.execute_branch_on_bit(operand, 5, true)?),
+            Operation::BBS6 => Ok(self.execute_branch_on_bit(operand, 6, true)?),
+            Operation::BBS7 => Ok(self.execute_branch_on_bit(operand, 7, true)?),
+            Operation::RMB0 => Ok(self.execute_set_bit(operand, 0, false)?),
+            Operation::RMB1 => Ok(self.execute_set_bit(operand, 1, false)?),
+            Operation::RMB2 => Ok(self.execute_set_bit(operand, 2, false)?),
+            Operation::RMB3 => Ok(self.execute_set_bit(operand, 3, false)?),
+            Operation::RMB4 => Ok(self.execute_set_bit(operand, 4, false)?),
+            Operation::RMB5 => Ok(self.execute_set_bit(operand, 5, false)?),
+            Operation::RMB6 => Ok(self.execute_set_bit(operand, 6, false)?),
+            Operation::RMB7 => Ok(self.execute_set_bit(operand, 7, false)?),
+            Operation::SMB0 => Ok(self.execute_set_bit(operand, 0, true)?),
+            Operation::SMB1 => Ok(self.execute_set_bit(operand, 1, true)?),
+            Operation::SMB2 => Ok(self.execute_set_bit(operand, 2, true)?),
+            Operation::SMB3 => Ok(self.execute_set_bit(operand, 3, true)?),
+            Operation::SMB4 => Ok(self.execute_set_bit(operand, 4, true)?),
+            Operation::SMB5 => Ok(self.execute_set_bit(operand, 5, true)?),
+            Operation::SMB6 => Ok(self.execute_set_bit(operand, 6, true)?),
+            Operation::SMB7 => Ok(self.execute_set_bit(operand, 7, true)?),
         }
     }
 
+    /// Reads the operand once, modifies it and writes the result back
+    fn execute_read_modify_write(
+        &mut self,
+        operand: Operand,
+        modify: fn(&mut Self, u8) -> u8,
+    ) -> Result<(), &'static str> {
+        let operand_value = self.get_operand_value(operand)?;
+        let result = modify(self, operand_value);
+        self.set_operand_value(operand, result)
+    }
+
+    /// Runs a read-modify-write operation on the operand, then an accumulator operation on its
+    /// result, as the undocumented combined instructions do
+    fn execute_combined(
+        &mut self,
+        operand: Operand,
+        modify: fn(&mut Self, u8) -> u8,
+        then: Operation,
+    ) -> Result<(), &'static str> {
+        let operand_value = self.get_operand_value(operand)?;
+        let result = modify(self, operand_value);
+        self.set_operand_value(operand, result)?;
+        self.execute_operation(then, Operand::Immediate(result))
+    }
+
+    /// ANDs the operand into the accumulator, then runs an accumulator operation on the result
+    fn execute_and_then(&mut self, operand: Operand, then: Operation) -> Result<(), &'static str> {
+        self.execute_and(operand)?;
+        self.execute_operation(then, Operand::Accumulator)
+    }
+
+    fn execute_and_copy_negative_to_carry(&mut self, operand: Operand) -> Result<(), &'static str> {
+        self.execute_and(operand)?;
+        self.set_status_flag(StatusFlag::CARRY, is_negative(self.registers.accumulator));
+        Ok(())
+    }
+
+    /// ARR, following http://www.oxyron.de/html/opcodes02.html
+    /// C and V come from bits 6 and 5 of the result in binary mode, and the NMOS decimal adjust
+    /// is applied to both nibbles in decimal mode
+    fn execute_and_rotate_right(&mut self, operand: Operand) -> Result<(), &'static str> {
+        let and_result = self.registers.accumulator & self.get_operand_value(operand)?;
+        let carry = self.get_status_flag(StatusFlag::CARRY);
+        let mut result = (and_result >> 1) | ((carry as u8) << 7);
+
+        if !self.decimal_mode_active() {
+            self.set_zero_and_negative_flags(result);
+            self.set_status_flag(StatusFlag::CARRY, result & (1 << 6) != 0);
+            self.set_status_flag(StatusFlag::OVERFLOW, ((result >> 6) ^ (result >> 5)) & 1 != 0);
+            self.registers.accumulator = result;
+            return Ok(());
+        }
+
+        self.set_status_flag(StatusFlag::NEGATIVE, carry);
+        self.set_status_flag(StatusFlag::ZERO, result == 0);
+        self.set_status_flag(StatusFlag::OVERFLOW, (and_result ^ result) & (1 << 6) != 0);
+        if (and_result & 0x0F) + (and_result & 0x01) > 0x05 {
+            result = (result & 0xF0) | (result.wrapping_add(0x06) & 0x0F);
+        }
+        let high_adjust = (and_result & 0xF0) as u16 + (and_result & 0x10) as u16 > 0x50;
+        if high_adjust {
+            result = result.wrapping_add(0x60);
+        }
+        self.set_status_flag(StatusFlag::CARRY, high_adjust);
+        self.registers.accumulator = result;
+
+        Ok(())
+    }
+
+    fn execute_and_x_substract(&mut self, operand: Operand) -> Result<(), &'static str> {
+        let operand_value = self.get_operand_value(operand)?;
+        let and_result = self.registers.accumulator & self.registers.x;
+        let result = and_result.wrapping_sub(operand_value);
+
+        self.set_status_flag(StatusFlag::CARRY, and_result >= operand_value);
+        self.set_zero_and_negative_flags(result);
+        self.registers.x = result;
+
+        Ok(())
+    }
+
+    fn execute_load_accumulator_and_x(&mut self, operand: Operand) -> Result<(), &'static str> {
+        self.execute_load_accumulator(operand)?;
+        self.registers.x = self.registers.accumulator;
+        Ok(())
+    }
+
+    fn execute_unstable_load_accumulator_and_x(
+        &mut self,
+        operand: Operand,
+    ) -> Result<(), &'static str> {
+        let operand_value = self.get_operand_value(operand)?;
+        let result = (self.registers.accumulator | UNSTABLE_MAGIC_CONSTANT) & operand_value;
+        self.execute_load_accumulator_and_x(Operand::Immediate(result))
+    }
+
+    fn execute_unstable_transfer_x_and(&mut self, operand: Operand) -> Result<(), &'static str> {
+        let operand_value = self.get_operand_value(operand)?;
+        let result = (self.registers.accumulator | UNSTABLE_MAGIC_CONSTANT)
+            & self.registers.x
+            & operand_value;
+        self.execute_transfer_to_accumulator(result);
+        Ok(())
+    }
+
+    fn execute_load_accumulator_x_and_stack_pointer(
+        &mut self,
+        operand: Operand,
+    ) -> Result<(), &'static str> {
+        let result = self.get_operand_value(operand)? & self.registers.stack_pointer;
+        self.execute_load_accumulator_and_x(Operand::Immediate(result))?;
+        self.registers.stack_pointer = result;
+        Ok(())
+    }
+
+    fn execute_store_accumulator_and_x(&mut self, operand: Operand) -> Result<(), &'static str> {
+        self.set_operand_value(operand, self.registers.accumulator & self.registers.x)
+    }
+
+    fn execute_unstable_store_accumulator_and_x(
+        &mut self,
+        operand: Operand,
+    ) -> Result<(), &'static str> {
+        self.execute_unstable_store(operand, self.registers.accumulator & self.registers.x)
+    }
+
+    fn execute_transfer_and_store(&mut self, operand: Operand) -> Result<(), &'static str> {
+        self.registers.stack_pointer = self.registers.accumulator & self.registers.x;
+        self.execute_unstable_store(operand, self.registers.stack_pointer)
+    }
+
+    /// Stores the value ANDed with the high byte of the base address plus one, and when indexing
+    /// crosses a page the high byte of the address written to is replaced with the result too
+    fn execute_unstable_store(&mut self, operand: Operand, value: u8) -> Result<(), &'static str> {
+        let (address, base_high_byte) = match operand {
+            Operand::Indexed(address, base_high_byte) => (address, base_high_byte),
+            _ => return Err("Unstable store must have Indexed-type operand"),
+        };
+        let result = value & base_high_byte.wrapping_add(1);
+        let address = if (address >> 8) as u8 != base_high_byte {
+            (result as u16) << 8 | (address & 0x00FF)
+        } else {
+            address
+        };
+        self.write_byte_to_memory(address as usize, result);
+        Ok(())
+    }
+
     /// Fetches and returns the value of the operand
     /// Also, returns if the page boundary was crossed for AbsoluteX, AbsoluteY, and IndirectY
     /// OperandModes
     fn fetch_operand(&mut self, mode: &OperandMode) -> (Operand, bool) {
         match mode {
-            OperandMode::Absolute => self.get_absolute_operand(0),
-            OperandMode::AbsoluteX => self.get_absolute_operand(self.registers.x),
-            OperandMode::AbsoluteY => self.get_absolute_operand(self.registers.y),
+            OperandMode::Absolute | OperandMode::EightCycleAbsolute => self.get_absolute_operand(),
+            OperandMode::AbsoluteIndirectX => self.get_absolute_indirect_x_operand(),
+            OperandMode::AbsoluteX => self.get_absolute_indexed_operand(self.registers.x),
+            OperandMode::AbsoluteY => self.get_absolute_indexed_operand(self.registers.y),
             OperandMode::Accumulator => (Operand::Accumulator, false),
             OperandMode::Immediate => self.get_immediate_operand(),
-            OperandMode::Implied => (Operand::Implied, false),
+            OperandMode::Implied | OperandMode::OneCycleImplied => (Operand::Implied, false),
             OperandMode::Indirect => self.get_indirect_operand(),
             OperandMode::IndirectX => self.get_indirect_x_operand(),
             OperandMode::IndirectY => self.get_indirect_y_operand(),
             OperandMode::ZeroPage => self.get_zero_page_operand(0),
+            OperandMode::ZeroPageIndirect => self.get_zero_page_indirect_operand(),
+            OperandMode::ZeroPageRelative => self.get_zero_page_relative_operand(),
             OperandMode::ZeroPageX => self.get_zero_page_operand(self.registers.x),
             OperandMode::ZeroPageY => self.get_zero_page_operand(self.registers.y),
         }
     }
 
+    fn get_absolute_operand(&mut self) -> (Operand, bool) {
+        let address = self.get_word_from_memory(self.registers.program_counter as usize);
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(2);
+        (Operand::Address(address), false)
+    }
+
     /// Fetches absolute operand, adding given offset
     /// Also returns true if page boundary crossed
-    fn get_absolute_operand(&mut self, offset: u8) -> (Operand, bool) {
+    #[allow(clippy::unnecessary_cast)]
+    fn get_absolute_indexed_operand(&mut self, offset: u8) -> (Operand, bool) {
         let address = self.get_word_from_memory(self.registers.program_counter as usize) as u16;
-        let operand_value = address + offset as u16;
-        let operand = Operand::Address(operand_value);
-        let page_boundary_crossed = (address & 0xFF00) == (operand_value & 0xFF00);
+        let operand_value = address.wrapping_add(offset as u16);
+        let operand = Operand::Indexed(operand_value, (address >> 8) as u8);
+        let page_boundary_crossed = (address & 0xFF00) != (operand_value & 0xFF00);
 
-        self.registers.program_counter += 2;
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(2);
         (operand, page_boundary_crossed)
     }
 
+    fn get_absolute_indirect_x_operand(&mut self) -> (Operand, bool) {
+        let address = self.get_word_from_memory(self.registers.program_counter as usize);
+        let pointer_address = address.wrapping_add(self.registers.x as u16);
+        let pointer = self.get_word_from_memory(pointer_address as usize);
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(2);
+        (Operand::Address(pointer), false)
+    }
+
     fn get_immediate_operand(&mut self) -> (Operand, bool) {
         let address = self.registers.program_counter as usize;
-        self.registers.program_counter += 1;
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
         (Operand::Immediate(self.get_byte_from_memory(address)), false)
     }
 
+    /// The NMOS 6502 doesn't carry into the high byte of the pointer address, so a pointer at
+    /// $xxFF has its high byte read from $xx00, the CMOS chips read it from the next page
     fn get_indirect_operand(&mut self) -> (Operand, bool) {
-        let pointer_address =
-        self.get_word_from_memory(self.registers.program_counter as usize) as usize;
-        let pointer = self.get_word_from_memory(pointer_address) as u16;
-        self.registers.program_counter += 2;
+        let pointer_address = self.get_word_from_memory(self.registers.program_counter as usize);
+        let high_byte_address = if self.variant.is_cmos() {
+            pointer_address.wrapping_add(1)
+        } else {
+            (pointer_address & 0xFF00) | (pointer_address as u8).wrapping_add(1) as u16
+        };
+        let low = self.get_byte_from_memory(pointer_address as usize);
+        let high = self.get_byte_from_memory(high_byte_address as usize);
+        let pointer = u16::from_le_bytes([low, high]);
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(2);
         (Operand::Address(pointer), false)
     }
 
     fn get_indirect_x_operand(&mut self) -> (Operand, bool) {
-        let address = self.get_byte_from_memory(self.registers.program_counter as usize) as usize;
-        let offset = self.registers.x as usize;
-        let pointer_address = (address + offset) & 0xff;
-        let pointer = self.get_word_from_memory(pointer_address);
-        self.registers.program_counter += 1;
+        let address = self.get_byte_from_memory(self.registers.program_counter as usize);
+        let pointer_address = address.wrapping_add(self.registers.x);
+        let pointer = self.get_zero_page_word(pointer_address);
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
         (Operand::Address(pointer), false)
     }
 
     fn get_indirect_y_operand(&mut self) -> (Operand, bool) {
-        let address = self.get_byte_from_memory(self.registers.program_counter as usize) as usize;
-        let pointer = self.get_word_from_memory(address);
-        let offset = self.registers.x as u16;
-        let operand_value = pointer + offset;
-        let page_boundary_crossed = (operand_value & 0xFF00) == (pointer & 0xFF);
+        let address = self.get_byte_from_memory(self.registers.program_counter as usize);
+        let pointer = self.get_zero_page_word(address);
+        let offset = self.registers.y as u16;
+        let operand_value = pointer.wrapping_add(offset);
+        let page_boundary_crossed = (operand_value & 0xFF00) != (pointer & 0xFF00);
+
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
+        (Operand::Indexed(operand_value, (pointer >> 8) as u8), page_boundary_crossed)
+    }
+
+    /// Reads a pointer from the zero page, wrapping around within it
+    fn get_zero_page_word(&mut self, address: u8) -> u16 {
+        let low = self.get_byte_from_memory(address as usize);
+        let high = self.get_byte_from_memory(address.wrapping_add(1) as usize);
+        u16::from_le_bytes([low, high])
+    }
 
-        self.registers.program_counter += 1;
-        (Operand::Address(operand_value), page_boundary_crossed)
+    fn get_zero_page_indirect_operand(&mut self) -> (Operand, bool) {
+        let address = self.get_byte_from_memory(self.registers.program_counter as usize);
+        let pointer = self.get_zero_page_word(address);
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
+        (Operand::Address(pointer), false)
+    }
+
+    fn get_zero_page_relative_operand(&mut self) -> (Operand, bool) {
+        let address = self.get_byte_from_memory(self.registers.program_counter as usize);
+        let offset = self.get_byte_from_memory(self.registers.program_counter as usize + 1);
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(2);
+        (Operand::ZeroPageRelative(address as u16, offset), false)
     }
 
     fn get_zero_page_operand(&mut self, offset: u8) -> (Operand, bool) {
         let address = self.get_byte_from_memory(self.registers.program_counter as usize);
-        let final_address = (address + offset) & 0xff;
-        self.registers.program_counter += 1;
+        let final_address = address.wrapping_add(offset);
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
         (Operand::Address(final_address as u16), false)
     }
 
     fn execute_add_with_carry(&mut self, operand: Operand) -> Result<(), &'static str> {
-        if self.get_status_flag(StatusFlag::DECIMAL) {
-            unimplemented!("No decimal mode support.");
-        }
         let operand_value = self.get_operand_value(operand)?;
+        if self.decimal_mode_active() {
+            self.add_decimal(operand_value);
+        } else {
+            self.add_binary(operand_value);
+        }
+
+        Ok(())
+    }
+
+    fn add_binary(&mut self, operand_value: u8) {
         let carry: u16 = self.get_status_flag(StatusFlag::CARRY) as u16;
         let accumulator: u16 = self.registers.accumulator as u16;
 
@@ -327,8 +1111,40 @@ impl ComputerState {
 
         self.set_zero_and_negative_flags(sum as u8);
         self.registers.accumulator = sum as u8;
+    }
 
-        Ok(())
+    /// Decimal mode addition, following http://www.6502.org/tutorials/decimal_mode.html
+    /// (appendix A)
+    /// On the NMOS 6502 Z is taken from the binary sum, N and V from the sum before the high nibble
+    /// is adjusted, which gives the documented-undefined flags for invalid BCD operands. The 65C02
+    /// sets N and Z from the result.
+    fn add_decimal(&mut self, operand_value: u8) {
+        let accumulator = self.registers.accumulator;
+        let carry = self.get_status_flag(StatusFlag::CARRY) as u16;
+
+        let binary_sum = (accumulator as u16 + operand_value as u16 + carry) as u8;
+
+        let mut low = (accumulator & 0x0F) as u16 + (operand_value & 0x0F) as u16 + carry;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+        let mut sum = (accumulator & 0xF0) as u16 + (operand_value & 0xF0) as u16 + low;
+
+        let signed_sum =
+            (accumulator & 0xF0) as i8 as i16 + (operand_value & 0xF0) as i8 as i16 + low as i16;
+        self.set_status_flag(StatusFlag::OVERFLOW, !(-128..=127).contains(&signed_sum));
+        self.set_status_flag(StatusFlag::NEGATIVE, is_negative(sum as u8));
+        self.set_status_flag(StatusFlag::ZERO, binary_sum == 0);
+
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        self.set_status_flag(StatusFlag::CARRY, sum >= 0x100);
+        self.registers.accumulator = sum as u8;
+
+        if self.variant.is_cmos() {
+            self.set_zero_and_negative_flags(sum as u8);
+        }
     }
 
     fn execute_and(&mut self, operand: Operand) -> Result<(), &'static str> {
@@ -341,16 +1157,13 @@ impl ComputerState {
         Ok(())
     }
 
-    fn execute_left_shift(&mut self, operand: Operand) -> Result<(), &'static str> {
-        let operand_value = self.get_operand_value(operand)?;
+    fn shift_left(&mut self, operand_value: u8) -> u8 {
         let high_bit = operand_value & (1 << 7) != 0;
         let result = operand_value << 1;
 
         self.set_status_flag(StatusFlag::CARRY, high_bit);
         self.set_zero_and_negative_flags(result);
-        self.set_operand_value(operand, result)?;
-
-        Ok(())
+        result
     }
 
     fn execute_branch_if(
@@ -360,40 +1173,96 @@ impl ComputerState {
         value: bool,
     ) -> Result<(), &'static str> {
         if self.get_status_flag(flag) == value {
-            let mut operand_value = self.get_operand_value(operand)? as u16;
-            if operand_value > 127 {
-                // Signed eight-bit operand, decrement unsigned value with 2^8
-                operand_value = operand_value.wrapping_sub(256);
-            }
-            // Operand is advanced by 2 from fetching opcode and operand
-            self.registers.program_counter = self
-                .registers
-                .program_counter
-                .wrapping_add(operand_value.wrapping_sub(2));
+            self.execute_branch(operand)?;
         }
         Ok(())
     }
 
+    /// Branches by the signed offset in the operand, relative to the next instruction
+    fn execute_branch(&mut self, operand: Operand) -> Result<(), &'static str> {
+        let offset = match operand {
+            Operand::Immediate(offset) | Operand::ZeroPageRelative(_, offset) => offset,
+            _ => return Err("Branch must have Immediate or ZeroPageRelative-type operand"),
+        };
+        self.branch_taken = true;
+        // Sign extend the eight-bit offset
+        self.registers.program_counter = self
+            .registers
+            .program_counter
+            .wrapping_add(offset as i8 as u16);
+        Ok(())
+    }
+
+    fn execute_branch_on_bit(
+        &mut self,
+        operand: Operand,
+        bit: u8,
+        value: bool,
+    ) -> Result<(), &'static str> {
+        let operand_value = self.get_operand_value(operand)?;
+        if (operand_value & (1 << bit) != 0) == value {
+            self.execute_branch(operand)?;
+        }
+        Ok(())
+    }
+
+    fn execute_set_bit(
+        &mut self,
+        operand: Operand,
+        bit: u8,
+        value: bool,
+    ) -> Result<(), &'static str> {
+        let operand_value = self.get_operand_value(operand)?;
+        let result = (operand_value & !(1 << bit)) | ((value as u8) << bit);
+        self.set_operand_value(operand, result)
+    }
+
     fn execute_bit_test(&mut self, operand: Operand) -> Result<(), &'static str> {
         let operand_value = self.get_operand_value(operand)?;
         let bit_7 = operand_value & (1 << 7) != 0;
         let bit_6 = operand_value & (1 << 6) != 0;
         let and_result = self.registers.accumulator & operand_value;
 
-        self.set_status_flag(StatusFlag::NEGATIVE, bit_7);
-        self.set_status_flag(StatusFlag::OVERFLOW, bit_6);
+        // The 65C02 immediate mode only affects ZERO
+        if !(self.variant.is_cmos() && matches!(operand, Operand::Immediate(_))) {
+            self.set_status_flag(StatusFlag::NEGATIVE, bit_7);
+            self.set_status_flag(StatusFlag::OVERFLOW, bit_6);
+        }
         self.set_status_flag(StatusFlag::ZERO, and_result == 0);
         Ok(())
     }
 
+    fn execute_test_and_reset_bits(&mut self, operand: Operand) -> Result<(), &'static str> {
+        let operand_value = self.get_operand_value(operand)?;
+
+        self.set_status_flag(StatusFlag::ZERO, self.registers.accumulator & operand_value == 0);
+        self.set_operand_value(operand, operand_value & !self.registers.accumulator)
+    }
+
+    fn execute_test_and_set_bits(&mut self, operand: Operand) -> Result<(), &'static str> {
+        let operand_value = self.get_operand_value(operand)?;
+
+        self.set_status_flag(StatusFlag::ZERO, self.registers.accumulator & operand_value == 0);
+        self.set_operand_value(operand, operand_value | self.registers.accumulator)
+    }
+
+    /// BRK skips the byte after the opcode, so the return address is two past it
     fn execute_break(&mut self) -> Result<(), &'static str> {
-        self.push_word_to_stack(self.registers.program_counter);
-        self.push_byte_to_stack(self.registers.status);
+        self.push_word_to_stack(self.registers.program_counter.wrapping_add(1));
+        self.push_byte_to_stack(self.pushed_status());
 
-        self.registers.program_counter = self.get_word_from_memory(0xfffe);
+        self.enter_break();
+        self.registers.program_counter = self.get_word_from_memory(IRQ_VECTOR);
         Ok(())
     }
 
+    fn enter_break(&mut self) {
+        self.set_status_flag(StatusFlag::INTERRUPT, true);
+        if self.variant.is_cmos() {
+            self.set_status_flag(StatusFlag::DECIMAL, false);
+        }
+    }
+
     fn execute_compare(&mut self, operand: Operand, register: u8) -> Result<(), &'static str> {
         let operand_value = self.get_operand_value(operand)?;
         let substract_value = register.wrapping_sub(operand_value);
@@ -404,21 +1273,19 @@ impl ComputerState {
         Ok(())
     }
 
-    fn execute_increment(&mut self, operand: Operand, negate: bool) -> Result<(), &'static str> {
-        let operand_value = self.get_operand_value(operand)?;
-        let result;
-        if negate {
-            result = operand_value.wrapping_sub(1);
-        } else {
-            result = operand_value.wrapping_add(1);
-        }
-
+    fn increment(&mut self, operand_value: u8) -> u8 {
+        let result = operand_value.wrapping_add(1);
         self.set_zero_and_negative_flags(result);
-        self.set_operand_value(operand, result)?;
+        result
+    }
 
-        Ok(())
+    fn decrement(&mut self, operand_value: u8) -> u8 {
+        let result = operand_value.wrapping_sub(1);
+        self.set_zero_and_negative_flags(result);
+        result
     }
 
+    #[allow(clippy::needless_late_init)]
     fn execute_increment_x(&mut self, negate: bool) -> Result<(), &'static str> {
         let result;
         if negate {
@@ -433,6 +1300,7 @@ impl ComputerState {
         Ok(())
     }
 
+    #[allow(clippy::needless_late_init)]
     fn execute_increment_y(&mut self, negate: bool) -> Result<(), &'static str> {
         let result;
         if negate {
@@ -459,7 +1327,7 @@ impl ComputerState {
 
     fn execute_jump(&mut self, operand: Operand, save_ra: bool) -> Result<(), &'static str> {
         if save_ra {
-            self.push_word_to_stack(self.registers.program_counter - 1);
+            self.push_word_to_stack(self.registers.program_counter.wrapping_sub(1));
         }
 
         let jump_address = match operand {
@@ -498,16 +1366,13 @@ impl ComputerState {
         Ok(())
     }
 
-    fn execute_right_shift(&mut self, operand: Operand) -> Result<(), &'static str> {
-        let operand_value = self.get_operand_value(operand)?;
+    fn shift_right(&mut self, operand_value: u8) -> u8 {
         let low_bit = operand_value & 1 != 0;
         let result = operand_value >> 1;
 
         self.set_status_flag(StatusFlag::CARRY, low_bit);
         self.set_zero_and_negative_flags(result);
-        self.set_operand_value(operand, result)?;
-
-        Ok(())
+        result
     }
 
     fn execute_inclusive_or(&mut self, operand: Operand) -> Result<(), &'static str> {
@@ -529,53 +1394,75 @@ impl ComputerState {
         Ok(())
     }
 
+    fn execute_pull_x(&mut self) -> Result<(), &'static str> {
+        let new_x = self.pull_byte_from_stack();
+
+        self.set_zero_and_negative_flags(new_x);
+        self.registers.x = new_x;
+
+        Ok(())
+    }
+
+    fn execute_pull_y(&mut self) -> Result<(), &'static str> {
+        let new_y = self.pull_byte_from_stack();
+
+        self.set_zero_and_negative_flags(new_y);
+        self.registers.y = new_y;
+
+        Ok(())
+    }
+
     fn execute_pull_status(&mut self) -> Result<(), &'static str> {
-        self.registers.status = self.pull_byte_from_stack();
+        let status = self.pull_byte_from_stack();
+        self.registers.set_status(status);
         Ok(())
     }
 
-    fn execute_rotate_right(&mut self, operand: Operand) -> Result<(), &'static str> {
-        let operand_value = self.get_operand_value(operand)?;
+    fn rotate_right(&mut self, operand_value: u8) -> u8 {
         let low_bit = operand_value & 1 != 0;
         let new_high_bit = (self.get_status_flag(StatusFlag::CARRY) as u8) << 7;
         let result = (operand_value >> 1) | new_high_bit;
 
         self.set_zero_and_negative_flags(result);
         self.set_status_flag(StatusFlag::CARRY, low_bit);
-        self.set_operand_value(operand, result)?;
-
-        Ok(())
+        result
     }
 
-    fn execute_rotate_left(&mut self, operand: Operand) -> Result<(), &'static str> {
-        let operand_value = self.get_operand_value(operand)?;
+    fn rotate_left(&mut self, operand_value: u8) -> u8 {
         let high_bit = operand_value & (1 << 7) != 0;
         let new_low_bit = self.get_status_flag(StatusFlag::CARRY) as u8;
         let result = (operand_value << 1) | new_low_bit;
 
         self.set_zero_and_negative_flags(result);
         self.set_status_flag(StatusFlag::CARRY, high_bit);
-        self.set_operand_value(operand, result)?;
-
-        Ok(())
+        result
     }
 
     fn execute_return_from_interrupt(&mut self) -> Result<(), &'static str> {
-        self.registers.status = self.pull_byte_from_stack();
+        let status = self.pull_byte_from_stack();
+        self.registers.set_status(status);
         self.registers.program_counter = self.pull_word_from_stack();
         Ok(())
     }
 
     fn execute_return_from_subroutine(&mut self) -> Result<(), &'static str> {
-        self.registers.program_counter = self.pull_word_from_stack() + 1;
+        self.registers.program_counter = self.pull_word_from_stack().wrapping_add(1);
         Ok(())
     }
 
     fn execute_substract_with_carry(&mut self, operand: Operand) -> Result<(), &'static str> {
-        if self.get_status_flag(StatusFlag::DECIMAL) {
-            unimplemented!("No decimal mode support.");
-        }
         let operand_value = self.get_operand_value(operand)?;
+        if self.decimal_mode_active() {
+            self.substract_decimal(operand_value);
+        } else {
+            self.substract_binary(operand_value);
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn substract_binary(&mut self, operand_value: u8) {
         let accumulator = self.registers.accumulator;
         let carry = 1 - (self.get_status_flag(StatusFlag::CARRY) as u8);
 
@@ -594,8 +1481,43 @@ impl ComputerState {
 
         self.set_zero_and_negative_flags(result);
         self.registers.accumulator = result;
+    }
 
-        Ok(())
+    /// Decimal mode substraction, following http://www.6502.org/tutorials/decimal_mode.html
+    /// (appendix A)
+    /// On the NMOS 6502 all flags are set as in binary mode, only the accumulator differs. The
+    /// 65C02 adjusts the accumulator differently for invalid BCD operands and sets N and Z from
+    /// the result.
+    fn substract_decimal(&mut self, operand_value: u8) {
+        let accumulator = self.registers.accumulator;
+        let carry = self.get_status_flag(StatusFlag::CARRY) as i16;
+
+        let mut low = (accumulator & 0x0F) as i16 - (operand_value & 0x0F) as i16 + carry - 1;
+        let mut result;
+        if self.variant.is_cmos() {
+            result = accumulator as i16 - operand_value as i16 + carry - 1;
+            if result < 0 {
+                result -= 0x60;
+            }
+            if low < 0 {
+                result -= 0x06;
+            }
+        } else {
+            if low < 0 {
+                low = ((low - 0x06) & 0x0F) - 0x10;
+            }
+            result = (accumulator & 0xF0) as i16 - (operand_value & 0xF0) as i16 + low;
+            if result < 0 {
+                result -= 0x60;
+            }
+        }
+
+        self.substract_binary(operand_value);
+        self.registers.accumulator = result as u8;
+
+        if self.variant.is_cmos() {
+            self.set_zero_and_negative_flags(result as u8);
+        }
     }
 
     fn execute_transfer_to_accumulator(&mut self, value: u8) {
@@ -622,11 +1544,13 @@ impl ComputerState {
 #[cfg(test)]
 mod unit_tests {
     use super::*;
+    use nestegg_macros::asm6502;
 
     mod describe_computer_state {
         use super::*;
 
         #[test]
+        #[allow(clippy::needless_range_loop)]
         fn it_fetches_bytes() {
             const NUM_VALUES: usize = 1024;
             let mut initial_memory = Vec::new();
@@ -636,7 +1560,7 @@ mod unit_tests {
                 initial_memory.push(inverted);
             }
 
-            let state = ComputerState::initialize_from_image(initial_memory.clone());
+            let mut state = ComputerState::initialize_from_image(initial_memory.clone());
 
             for i in 0..NUM_VALUES {
                 assert_eq!(state.get_byte_from_memory(i), initial_memory[i]);
@@ -644,6 +1568,7 @@ mod unit_tests {
         }
 
         #[test]
+        #[allow(clippy::needless_range_loop)]
         fn it_fetches_words() {
             const NUM_VALUES: usize = 1024;
             let mut initial_memory = Vec::new();
@@ -658,7 +1583,7 @@ mod unit_tests {
                 expected_memory.push(inverted as u16);
             }
 
-            let state = ComputerState::initialize_from_image(initial_memory.clone());
+            let mut state = ComputerState::initialize_from_image(initial_memory.clone());
 
             for i in 0..NUM_VALUES {
                 assert_eq!(state.get_word_from_memory(i * 2), expected_memory[i]);
@@ -677,6 +1602,7 @@ mod unit_tests {
             assert_eq!(state_initial_registers, state.registers);
         }
 
+        #[allow(clippy::needless_borrow, clippy::single_match)]
         fn check_accumulator_op(
             state: &mut ComputerState,
             operation: Operation,
@@ -700,6 +1626,7 @@ mod unit_tests {
             check_status_flags(&state, &expected_flags);
         }
 
+        #[allow(clippy::ptr_arg, clippy::useless_vec)]
         fn check_status_flags(state: &ComputerState, expected_flags: &Vec<StatusFlag>) {
             let all_flags = vec![
                 StatusFlag::CARRY,
@@ -772,14 +1699,139 @@ mod unit_tests {
             check_accumulator_op(
                 &mut state,
                 Operation::ADC,
-                Some(0x80),
-                Some(0x80),
-                0x01,
-                vec![StatusFlag::CARRY, StatusFlag::OVERFLOW],
+                Some(0x80),
+                Some(0x80),
+                0x01,
+                vec![StatusFlag::CARRY, StatusFlag::OVERFLOW],
+            );
+        }
+
+        #[test]
+        fn it_fetches_absolute_indexed_operands_across_pages() {
+            let mut state = ComputerState::initialize();
+            state.memory[0x0200..0x0206].copy_from_slice(&[0xF5, 0x12, 0xF5, 0x12, 0xF0, 0xFF]);
+            state.registers.program_counter = 0x0200;
+
+            state.registers.x = 0x05;
+            let operand = state.fetch_operand(&OperandMode::AbsoluteX);
+            assert_eq!(operand, (Operand::Indexed(0x12FA, 0x12), false));
+            state.registers.x = 0x20;
+            let operand = state.fetch_operand(&OperandMode::AbsoluteX);
+            assert_eq!(operand, (Operand::Indexed(0x1315, 0x12), true));
+            let operand = state.fetch_operand(&OperandMode::AbsoluteX);
+            assert_eq!(operand, (Operand::Indexed(0x0010, 0xFF), true));
+        }
+
+        #[test]
+        fn it_fetches_indirect_y_operands_indexed_by_y() {
+            let mut state = ComputerState::initialize();
+            state.memory[0x0200] = 0x40;
+            state.memory[0x40..0x42].copy_from_slice(&[0xF8, 0x12]);
+            state.registers.program_counter = 0x0200;
+            state.registers.x = 0x55;
+
+            state.registers.y = 0x04;
+            assert_eq!(
+                state.fetch_operand(&OperandMode::IndirectY),
+                (Operand::Indexed(0x12FC, 0x12), false)
+            );
+            state.registers.program_counter = 0x0200;
+            state.registers.y = 0x10;
+            assert_eq!(
+                state.fetch_operand(&OperandMode::IndirectY),
+                (Operand::Indexed(0x1308, 0x12), true)
+            );
+        }
+
+        #[test]
+        fn it_wraps_indirect_pointers_within_the_zero_page() {
+            let mut state = ComputerState::initialize();
+            state.memory[0x0200..0x0202].copy_from_slice(&[0xFE, 0xFF]);
+            state.memory[0xFF] = 0x34;
+            state.memory[0x00] = 0x12;
+            state.memory[0x0100] = 0x99;
+            state.registers.program_counter = 0x0200;
+
+            state.registers.x = 0x01;
+            assert_eq!(
+                state.fetch_operand(&OperandMode::IndirectX),
+                (Operand::Address(0x1234), false)
+            );
+            state.registers.y = 0x00;
+            assert_eq!(
+                state.fetch_operand(&OperandMode::IndirectY),
+                (Operand::Indexed(0x1234, 0x12), false)
+            );
+        }
+
+        #[test]
+        fn it_wraps_zero_page_indexed_operands() {
+            let mut state = ComputerState::initialize();
+            state.memory[0x0200] = 0xF0;
+            state.registers.program_counter = 0x0200;
+            state.registers.x = 0x20;
+            assert_eq!(
+                state.fetch_operand(&OperandMode::ZeroPageX),
+                (Operand::Address(0x0010), false)
+            );
+        }
+
+        #[test]
+        fn it_executes_adc_in_decimal_mode() {
+            let mut state = ComputerState::initialize();
+            state.set_status_flag(StatusFlag::DECIMAL, true);
+
+            check_accumulator_op(
+                &mut state,
+                Operation::ADC,
+                Some(0x12),
+                Some(0x34),
+                0x46,
+                vec![StatusFlag::DECIMAL],
+            );
+            check_accumulator_op(
+                &mut state,
+                Operation::ADC,
+                Some(0x58),
+                Some(0x46),
+                0x04,
+                vec![
+                    StatusFlag::DECIMAL,
+                    StatusFlag::CARRY,
+                    StatusFlag::NEGATIVE,
+                    StatusFlag::OVERFLOW,
+                ],
+            );
+            check_accumulator_op(
+                &mut state,
+                Operation::ADC,
+                None,
+                Some(0x05),
+                0x10,
+                vec![StatusFlag::DECIMAL],
+            );
+            // Z follows the binary result and N the unadjusted high nibble on the NMOS 6502
+            check_accumulator_op(
+                &mut state,
+                Operation::ADC,
+                Some(0x99),
+                Some(0x01),
+                0x00,
+                vec![StatusFlag::DECIMAL, StatusFlag::CARRY, StatusFlag::NEGATIVE],
+            );
+            state.set_status_flag(StatusFlag::CARRY, false);
+            check_accumulator_op(
+                &mut state,
+                Operation::ADC,
+                Some(0x81),
+                Some(0x92),
+                0x73,
+                vec![StatusFlag::DECIMAL, StatusFlag::CARRY, StatusFlag::OVERFLOW],
             );
         }
 
         #[test]
+        #[allow(clippy::identity_op)]
         fn it_executes_and() {
             let mut state = ComputerState::initialize();
 
@@ -869,58 +1921,58 @@ mod unit_tests {
             state
                 .execute_operation(Operation::BVC, Operand::Immediate(0x55))
                 .expect("Couldn't execute BVC");
-            assert_eq!(state.registers.program_counter, 0x53);
+            assert_eq!(state.registers.program_counter, 0x55);
 
             state.set_status_flag(StatusFlag::OVERFLOW, true);
             state
                 .execute_operation(Operation::BVS, Operand::Immediate(0x55))
                 .expect("Couldn't execute BVS");
-            assert_eq!(state.registers.program_counter, 0xa6);
+            assert_eq!(state.registers.program_counter, 0xaa);
 
             state.set_status_flag(StatusFlag::CARRY, true);
             state
                 .execute_operation(Operation::BCS, Operand::Immediate(0xa0))
                 .expect("Couldn't execute BCS");
-            assert_eq!(state.registers.program_counter, 0x44);
+            assert_eq!(state.registers.program_counter, 0x4a);
 
             state.set_status_flag(StatusFlag::CARRY, false);
             state
                 .execute_operation(Operation::BCS, Operand::Immediate(0xa0))
                 .expect("Couldn't execute BCS");
-            assert_eq!(state.registers.program_counter, 0x44);
+            assert_eq!(state.registers.program_counter, 0x4a);
 
             state
                 .execute_operation(Operation::BCC, Operand::Immediate(0xf0))
                 .expect("Couldn't execute BCC");
-            assert_eq!(state.registers.program_counter, 0x32);
+            assert_eq!(state.registers.program_counter, 0x3a);
 
             state.registers.status = 0x00;
             state.set_status_flag(StatusFlag::ZERO, true);
             state
                 .execute_operation(Operation::BEQ, Operand::Immediate(0xf0))
                 .expect("Couldn't execute BEQ");
-            assert_eq!(state.registers.program_counter, 0x20);
+            assert_eq!(state.registers.program_counter, 0x2a);
 
             state.registers.status = 0xff;
             state.set_status_flag(StatusFlag::ZERO, false);
             state
                 .execute_operation(Operation::BNE, Operand::Immediate(0x55))
                 .expect("Couldn't execute BNE");
-            assert_eq!(state.registers.program_counter, 0x73);
+            assert_eq!(state.registers.program_counter, 0x7f);
 
             state.registers.status = 0x00;
             state.set_status_flag(StatusFlag::NEGATIVE, true);
             state
                 .execute_operation(Operation::BMI, Operand::Immediate(0xf0))
                 .expect("Couldn't execute BMI");
-            assert_eq!(state.registers.program_counter, 0x61);
+            assert_eq!(state.registers.program_counter, 0x6f);
 
             state.registers.status = 0xff;
             state.set_status_flag(StatusFlag::NEGATIVE, false);
             state
                 .execute_operation(Operation::BPL, Operand::Immediate(0xf0))
                 .expect("Couldn't execute BPL");
-            assert_eq!(state.registers.program_counter, 0x4f);
+            assert_eq!(state.registers.program_counter, 0x5f);
         }
 
         #[test]
@@ -1422,7 +2474,8 @@ mod unit_tests {
             state
                 .execute_operation(Operation::PHP, Operand::Implied)
                 .unwrap();
-            assert_eq!(state.get_byte_from_memory(0x1fe), 0x55);
+            // PHP pushes BREAK and bit 5 set
+            assert_eq!(state.get_byte_from_memory(0x1fe), 0x75);
             assert_eq!(state.registers.stack_pointer, 0xfd);
 
             state.registers.accumulator = 0x43;
@@ -1443,7 +2496,7 @@ mod unit_tests {
             state
                 .execute_operation(Operation::PLP, Operand::Implied)
                 .unwrap();
-            assert_eq!(state.registers.status, 0x55);
+            assert_eq!(state.registers.status, 0x65);
 
             state
                 .execute_operation(Operation::PLA, Operand::Implied)
@@ -1534,25 +2587,27 @@ mod unit_tests {
                 .execute_operation(Operation::RTI, Operand::Implied)
                 .unwrap();
             assert_eq!(state.registers.program_counter, 0xdead);
-            assert_eq!(state.registers.status, 0x57);
+            assert_eq!(state.registers.status, 0x67);
 
             state.write_word_to_memory(0xfffe, 0x1234);
 
-            state.registers.status = 0x47;
+            // The program counter is already past the opcode, and BRK skips one more byte
+            state.registers.status = 0x43;
             state.registers.program_counter = 0xcafe;
             state
                 .execute_operation(Operation::BRK, Operand::Implied)
                 .unwrap();
             assert_eq!(state.registers.program_counter, 0x1234);
-            assert_eq!(state.get_word_from_memory(0x1fe), 0xcafe);
-            assert_eq!(state.get_byte_from_memory(0x1fd), 0x47);
+            assert_eq!(state.get_word_from_memory(0x1fe), 0xcaff);
+            assert_eq!(state.get_byte_from_memory(0x1fd), 0x73);
+            assert!(state.get_status_flag(StatusFlag::INTERRUPT));
             state.registers.status = 0;
             state.registers.program_counter = 0;
             state
                 .execute_operation(Operation::RTI, Operand::Implied)
                 .unwrap();
-            assert_eq!(state.registers.program_counter, 0xcafe);
-            assert_eq!(state.registers.status, 0x47);
+            assert_eq!(state.registers.program_counter, 0xcaff);
+            assert_eq!(state.registers.status, 0x63);
         }
 
         #[test]
@@ -1610,6 +2665,226 @@ mod unit_tests {
             );
         }
 
+        #[test]
+        fn it_executes_sbc_in_decimal_mode() {
+            let mut state = ComputerState::initialize();
+            state.set_status_flag(StatusFlag::DECIMAL, true);
+            state.set_status_flag(StatusFlag::CARRY, true);
+
+            check_accumulator_op(
+                &mut state,
+                Operation::SBC,
+                Some(0x46),
+                Some(0x12),
+                0x34,
+                vec![StatusFlag::DECIMAL, StatusFlag::CARRY],
+            );
+            check_accumulator_op(
+                &mut state,
+                Operation::SBC,
+                None,
+                Some(0x35),
+                0x99,
+                vec![StatusFlag::DECIMAL, StatusFlag::NEGATIVE],
+            );
+            check_accumulator_op(
+                &mut state,
+                Operation::SBC,
+                Some(0x32),
+                Some(0x02),
+                0x29,
+                vec![StatusFlag::DECIMAL, StatusFlag::CARRY],
+            );
+            check_accumulator_op(
+                &mut state,
+                Operation::SBC,
+                None,
+                Some(0x29),
+                0x00,
+                vec![StatusFlag::DECIMAL, StatusFlag::CARRY, StatusFlag::ZERO],
+            );
+        }
+
+        #[test]
+        fn it_executes_undocumented_read_modify_write_combinations() {
+            let mut state =
+                ComputerState::initialize_from_image(vec![0x81, 0x03, 0x02, 0x05, 0xFF]);
+
+            state.registers.accumulator = 0x10;
+            state
+                .execute_operation(Operation::SLO, Operand::Address(0))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(0), 0x02);
+            assert_eq!(state.registers.accumulator, 0x12);
+            check_status_flags(&state, &vec![StatusFlag::CARRY]);
+
+            state.registers.accumulator = 0x0F;
+            state
+                .execute_operation(Operation::RLA, Operand::Address(0))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(0), 0x05);
+            assert_eq!(state.registers.accumulator, 0x05);
+            check_status_flags(&state, &vec![]);
+
+            state.registers.accumulator = 0xFF;
+            state
+                .execute_operation(Operation::SRE, Operand::Address(1))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(1), 0x01);
+            assert_eq!(state.registers.accumulator, 0xFE);
+            check_status_flags(&state, &vec![StatusFlag::CARRY, StatusFlag::NEGATIVE]);
+
+            state.registers.accumulator = 0x01;
+            state
+                .execute_operation(Operation::RRA, Operand::Address(2))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(2), 0x81);
+            assert_eq!(state.registers.accumulator, 0x82);
+            check_status_flags(&state, &vec![StatusFlag::NEGATIVE]);
+
+            state.registers.accumulator = 0x04;
+            state
+                .execute_operation(Operation::DCP, Operand::Address(3))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(3), 0x04);
+            check_status_flags(&state, &vec![StatusFlag::CARRY, StatusFlag::ZERO]);
+
+            state.registers.accumulator = 0x10;
+            state
+                .execute_operation(Operation::ISC, Operand::Address(4))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(4), 0x00);
+            assert_eq!(state.registers.accumulator, 0x10);
+            check_status_flags(&state, &vec![StatusFlag::CARRY]);
+        }
+
+        /// Flat RAM that counts the CPU's reads of each address
+        struct CountingBus {
+            ram: Vec<u8>,
+            reads: Vec<u32>,
+        }
+
+        impl Bus for CountingBus {
+            fn read(&mut self, address: u16) -> u8 {
+                self.reads[address as usize] += 1;
+                self.ram[address as usize]
+            }
+
+            fn write(&mut self, address: u16, value: u8) {
+                self.ram[address as usize] = value;
+            }
+
+            fn peek(&self, address: u16) -> u8 {
+                self.ram[address as usize]
+            }
+        }
+
+        #[test]
+        fn it_reads_read_modify_write_operands_once() {
+            let bus = CountingBus {
+                ram: vec![0x80; 0x10000],
+                reads: vec![0; 0x10000],
+            };
+            let mut state = ComputerState::initialize_with_bus(bus, CpuVariant::Nmos6502);
+
+            for op in [Operation::INC, Operation::ROL, Operation::SLO, Operation::ISC] {
+                state.execute_operation(op, Operand::Address(0x0200)).unwrap();
+            }
+            assert_eq!(state.memory.reads[0x0200], 4);
+
+            assert_eq!(state.peek_byte_from_memory(0x0200), 0x05);
+            assert_eq!(state.peek_word_from_memory(0x01FF), 0x0580);
+            assert_eq!(state.memory.reads[0x0200], 4);
+        }
+
+        #[test]
+        fn it_executes_undocumented_loads_and_stores() {
+            let mut state = ComputerState::initialize();
+            state.write_byte_to_memory(0x20, 0xF0);
+
+            state
+                .execute_operation(Operation::LAX, Operand::Address(0x20))
+                .unwrap();
+            assert_eq!(state.registers.accumulator, 0xF0);
+            assert_eq!(state.registers.x, 0xF0);
+            check_status_flags(&state, &vec![StatusFlag::NEGATIVE]);
+
+            state.registers.accumulator = 0x3C;
+            state
+                .execute_operation(Operation::SAX, Operand::Address(0x21))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(0x21), 0x30);
+
+            state.registers.stack_pointer = 0x3F;
+            state
+                .execute_operation(Operation::LAS, Operand::Address(0x20))
+                .unwrap();
+            assert_eq!(state.registers.accumulator, 0x30);
+            assert_eq!(state.registers.x, 0x30);
+            assert_eq!(state.registers.stack_pointer, 0x30);
+
+            state.registers.x = 0xFF;
+            state
+                .execute_operation(Operation::SHX, Operand::Indexed(0x1230, 0x12))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(0x1230), 0x13);
+
+            // Indexed from $11F0, so ANDed with $12, and crossing the page it writes to $0230
+            state.registers.x = 0x0F;
+            state
+                .execute_operation(Operation::SHX, Operand::Indexed(0x1230, 0x11))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(0x0230), 0x02);
+            assert_eq!(state.get_byte_from_memory(0x1230), 0x13);
+        }
+
+        #[test]
+        fn it_executes_undocumented_immediate_operations() {
+            let mut state = ComputerState::initialize();
+
+            check_accumulator_op(
+                &mut state,
+                Operation::ANC,
+                Some(0xF0),
+                Some(0x81),
+                0x80,
+                vec![StatusFlag::NEGATIVE, StatusFlag::CARRY],
+            );
+            check_accumulator_op(
+                &mut state,
+                Operation::ALR,
+                Some(0xFF),
+                Some(0x03),
+                0x01,
+                vec![StatusFlag::CARRY],
+            );
+            check_accumulator_op(
+                &mut state,
+                Operation::ARR,
+                Some(0xFF),
+                Some(0xC0),
+                0xE0,
+                vec![StatusFlag::NEGATIVE, StatusFlag::CARRY],
+            );
+            state.registers.x = 0x7F;
+            check_accumulator_op(
+                &mut state,
+                Operation::XAA,
+                Some(0x00),
+                Some(0xFF),
+                0x6E,
+                vec![StatusFlag::CARRY],
+            );
+
+            state.registers.accumulator = 0x0F;
+            state.registers.x = 0x07;
+            state
+                .execute_operation(Operation::AXS, Operand::Immediate(0x02))
+                .unwrap();
+            assert_eq!(state.registers.x, 0x05);
+            check_status_flags(&state, &vec![StatusFlag::CARRY]);
+        }
+
         #[test]
         fn it_executes_stores() {
             let mut state = ComputerState::initialize();
@@ -1686,6 +2961,7 @@ mod unit_tests {
         }
 
         #[test]
+        #[allow(clippy::bool_assert_comparison)]
         fn it_gets_status_flags() {
             let mut state = ComputerState::initialize();
             state.registers.status = 0b00110011;
@@ -1694,21 +2970,434 @@ mod unit_tests {
             assert_eq!(state.get_status_flag(StatusFlag::NEGATIVE), false);
         }
 
+        #[test]
+        fn it_packs_the_status_register_like_hardware() {
+            let mut registers = RegisterFile::default();
+            assert_eq!(registers.status(), 0x20);
+
+            registers.set_status(0xFF);
+            assert_eq!(registers.status(), 0xEF);
+            assert!(!registers.flag(StatusFlag::BREAK));
+
+            registers.set_flag(StatusFlag::NEGATIVE, false);
+            registers.set_flag(StatusFlag::CARRY, false);
+            assert_eq!(registers.status(), 0x6E);
+        }
+
+        #[test]
+        fn it_services_irq_only_when_enabled() {
+            let mut state = ComputerState::initialize_from_image(vec![0xEA; 0x10000]);
+            state.write_word_to_memory(IRQ_VECTOR, 0x4000);
+            state.registers.stack_pointer = 0xff;
+            state.registers.program_counter = 0x1234;
+            state.registers.status = 0x14;
+
+            state.set_irq_line(true);
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 0x1235);
+            assert_eq!(state.cycles, 2);
+
+            state.registers.program_counter = 0x1234;
+            state.set_status_flag(StatusFlag::INTERRUPT, false);
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 0x4000);
+            assert_eq!(state.get_word_from_memory(0x1fe), 0x1234);
+            assert_eq!(state.get_byte_from_memory(0x1fd), 0x20);
+            assert!(state.get_status_flag(StatusFlag::INTERRUPT));
+            assert_eq!(state.cycles, 2 + 7);
+
+            state.set_irq_line(false);
+            state.set_status_flag(StatusFlag::INTERRUPT, false);
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 0x4001);
+        }
+
+        #[test]
+        fn it_services_nmi_on_edge() {
+            let mut state = ComputerState::initialize_from_image(vec![0xEA; 0x10000]);
+            state.write_word_to_memory(NMI_VECTOR, 0x5000);
+            state.registers.stack_pointer = 0xff;
+            state.set_status_flag(StatusFlag::INTERRUPT, true);
+
+            state.set_nmi_line(true);
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 0x5000);
+            assert_eq!(state.registers.stack_pointer, 0xfc);
+            assert_eq!(state.cycles, 7);
+
+            // Holding the line doesn't retrigger
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 0x5001);
+
+            state.set_nmi_line(false);
+            state.trigger_nmi();
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 0x5000);
+            assert_eq!(state.cycles, 7 + 2 + 7);
+        }
+
+        #[test]
+        fn it_services_reset_before_other_interrupts() {
+            let mut state = ComputerState::initialize_from_image(vec![0xEA; 0x10000]);
+            state.write_word_to_memory(RESET_VECTOR, 0x6000);
+            state.write_word_to_memory(NMI_VECTOR, 0x5000);
+
+            state.trigger_nmi();
+            state.set_irq_line(true);
+            state.pulse_reset();
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 0x6000);
+            assert_eq!(state.registers.stack_pointer, 0xfd);
+            assert!(state.get_status_flag(StatusFlag::INTERRUPT));
+            assert_eq!(state.cycles, 7);
+
+            // The NMI latched before the reset is discarded and IRQ is masked
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 0x6001);
+        }
+
+        #[test]
+        fn it_boots_from_reset_vector() {
+            let mut image = vec![0; 0x10000];
+            image[0xfffc] = 0x00;
+            image[0xfffd] = 0x80;
+
+            let state = ComputerState::boot_from_image(image);
+
+            assert_eq!(state.registers.program_counter, 0x8000);
+            assert_eq!(state.registers.stack_pointer, 0xfd);
+            check_status_flags(&state, &vec![StatusFlag::INTERRUPT, StatusFlag::RESERVED]);
+            assert_eq!(state.cycles, 7);
+        }
+
+        #[test]
+        fn it_executes_cmos_stack_and_store_operations() {
+            let mut state = ComputerState::initialize_with_variant(CpuVariant::Wdc65C02);
+            state.registers.stack_pointer = 0xff;
+            state.write_byte_to_memory(0x20, 0x55);
+
+            state
+                .execute_operation(Operation::STZ, Operand::Address(0x20))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(0x20), 0x00);
+
+            state.registers.x = 0x80;
+            state
+                .execute_operation(Operation::PHX, Operand::Implied)
+                .unwrap();
+            state
+                .execute_operation(Operation::PLY, Operand::Implied)
+                .unwrap();
+            assert_eq!(state.registers.y, 0x80);
+            assert_eq!(state.registers.stack_pointer, 0xff);
+            check_status_flags(&state, &vec![StatusFlag::NEGATIVE]);
+
+            state
+                .execute_operation(Operation::INC, Operand::Accumulator)
+                .unwrap();
+            assert_eq!(state.registers.accumulator, 0x01);
+        }
+
+        #[test]
+        fn it_executes_cmos_bit_operations() {
+            let mut state = ComputerState::initialize_with_variant(CpuVariant::Wdc65C02);
+            state.write_byte_to_memory(0x20, 0b1010_0101);
+
+            state.registers.accumulator = 0b0000_0110;
+            state
+                .execute_operation(Operation::TRB, Operand::Address(0x20))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(0x20), 0b1010_0001);
+            assert!(!state.get_status_flag(StatusFlag::ZERO));
+
+            state
+                .execute_operation(Operation::TSB, Operand::Address(0x20))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(0x20), 0b1010_0111);
+            assert!(state.get_status_flag(StatusFlag::ZERO));
+
+            state
+                .execute_operation(Operation::RMB7, Operand::Address(0x20))
+                .unwrap();
+            state
+                .execute_operation(Operation::SMB3, Operand::Address(0x20))
+                .unwrap();
+            assert_eq!(state.get_byte_from_memory(0x20), 0b0010_1111);
+
+            state.registers.program_counter = 0x1000;
+            state
+                .execute_operation(Operation::BBS7, Operand::ZeroPageRelative(0x20, 0x10))
+                .unwrap();
+            assert_eq!(state.registers.program_counter, 0x1000);
+            state
+                .execute_operation(Operation::BBR7, Operand::ZeroPageRelative(0x20, 0xf0))
+                .unwrap();
+            assert_eq!(state.registers.program_counter, 0x0ff0);
+
+            state.registers.accumulator = 0x00;
+            state.set_status_flag(StatusFlag::NEGATIVE, true);
+            state
+                .execute_operation(Operation::BIT, Operand::Immediate(0xC0))
+                .unwrap();
+            assert!(state.get_status_flag(StatusFlag::ZERO));
+            assert!(state.get_status_flag(StatusFlag::NEGATIVE));
+            assert!(!state.get_status_flag(StatusFlag::OVERFLOW));
+        }
+
+        #[test]
+        fn it_sets_valid_flags_in_cmos_decimal_mode() {
+            let mut state = ComputerState::initialize_with_variant(CpuVariant::Wdc65C02);
+            state.set_status_flag(StatusFlag::DECIMAL, true);
+
+            check_accumulator_op(
+                &mut state,
+                Operation::ADC,
+                Some(0x99),
+                Some(0x01),
+                0x00,
+                vec![StatusFlag::DECIMAL, StatusFlag::CARRY, StatusFlag::ZERO],
+            );
+            check_accumulator_op(
+                &mut state,
+                Operation::SBC,
+                Some(0x20),
+                Some(0x0F),
+                0x0B,
+                vec![StatusFlag::DECIMAL, StatusFlag::CARRY],
+            );
+        }
+
+        #[test]
+        fn it_runs_cmos_programs() {
+            let program = asm6502!([Wdc65C02]
+                sed;
+                bra :+;
+                .byte 0, 0;         // skipped
+            :   stz $10;
+                lda ($20);
+                adc #$01;
+                bbr0 $10, *         // not taken, the bit is clear once STZ ran
+            );
+            let mut image = vec![0; 0x10000];
+            image[..program.len()].copy_from_slice(&program);
+            image[0x20] = 0x00;
+            image[0x21] = 0x30;
+            image[0x3000] = 0x19;
+            image[0x10] = 0xFF;
+
+            let mut state =
+                ComputerState::initialize_from_image_with_variant(image, CpuVariant::Wdc65C02);
+            state.multiple_steps(5).unwrap();
+
+            assert_eq!(state.registers.accumulator, 0x20);
+            assert_eq!(state.registers.program_counter, 11);
+            assert_eq!(state.cycles, 2 + 3 + 3 + 5 + 3);
+        }
+
+        #[test]
+        fn it_wraps_indirect_jump_pointer_only_on_nmos() {
+            let program = asm6502!(jmp ($10FF));
+            let mut image = vec![0; 0x10000];
+            image[..program.len()].copy_from_slice(&program);
+            image[0x10FF] = 0x34;
+            image[0x1000] = 0x12;
+            image[0x1100] = 0x56;
+
+            let mut nmos = ComputerState::initialize_from_image(image.clone());
+            nmos.step().unwrap();
+            assert_eq!(nmos.registers.program_counter, 0x1234);
+
+            let mut cmos =
+                ComputerState::initialize_from_image_with_variant(image, CpuVariant::Wdc65C02);
+            cmos.step().unwrap();
+            assert_eq!(cmos.registers.program_counter, 0x5634);
+            assert_eq!(cmos.cycles, 6);
+        }
+
+        #[test]
+        fn it_waits_for_interrupt() {
+            let program = asm6502!([Wdc65C02] wai);
+            let mut image = vec![0xEA; 0x10000];
+            image[..program.len()].copy_from_slice(&program);
+
+            let mut state =
+                ComputerState::initialize_from_image_with_variant(image, CpuVariant::Wdc65C02);
+            state.set_status_flag(StatusFlag::INTERRUPT, true);
+            state.set_status_flag(StatusFlag::DECIMAL, true);
+            state.multiple_steps(3).unwrap();
+            assert_eq!(state.registers.program_counter, 1);
+            assert_eq!(state.cycles, 3 + 1 + 1);
+
+            // A masked IRQ resumes execution without being serviced
+            state.set_irq_line(true);
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 2);
+
+            state.registers.program_counter = 0;
+            state.step().unwrap();
+            state.set_irq_line(false);
+            state.trigger_nmi();
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 0xEAEA);
+            assert!(!state.get_status_flag(StatusFlag::DECIMAL));
+        }
+
+        #[test]
+        fn it_ignores_decimal_flag_on_the_2a03() {
+            let mut state = ComputerState::initialize_with_variant(CpuVariant::Ricoh2A03);
+            state.set_status_flag(StatusFlag::DECIMAL, true);
+
+            check_accumulator_op(
+                &mut state,
+                Operation::ADC,
+                Some(0x09),
+                Some(0x01),
+                0x0A,
+                vec![StatusFlag::DECIMAL],
+            );
+        }
+
+        #[test]
+        fn it_mirrors_memory_across_the_6507_address_space() {
+            let mut image = vec![0; 0x2000];
+            image[0x1FFC] = 0x00;
+            image[0x1FFD] = 0xF0;
+
+            let mut state =
+                ComputerState::initialize_from_image_with_variant(image, CpuVariant::Mos6507);
+            state.reset();
+            assert_eq!(state.registers.program_counter, 0xF000);
+
+            state.write_byte_to_memory(0xF080, 0x42);
+            assert_eq!(state.get_byte_from_memory(0x1080), 0x42);
+            assert_eq!(state.get_byte_from_memory(0x3080), 0x42);
+        }
+
+        #[test]
+        fn it_maps_the_6510_io_port() {
+            let mut state = ComputerState::initialize_with_variant(CpuVariant::Mos6510);
+            assert_eq!(state.io_port_pins(), Some(0xFF));
+
+            state.write_byte_to_memory(0x0000, 0x0F);
+            state.write_byte_to_memory(0x0001, 0x05);
+            state.set_io_port_input(0x30);
+            assert_eq!(state.get_byte_from_memory(0x0000), 0x0F);
+            assert_eq!(state.get_byte_from_memory(0x0001), 0x35);
+            assert_eq!(state.io_port_pins(), Some(0x35));
+            assert_eq!(state.memory[0x0001], 0x05);
+
+            assert_eq!(ComputerState::initialize().io_port_pins(), None);
+        }
+
+        #[test]
+        fn it_reports_where_unknown_opcodes_are() {
+            let mut image = vec![0xEA; 0x10000];
+            image[0x0004] = 0x02;
+            let mut state = ComputerState::initialize_from_image(image);
+            let error = state.multiple_steps(5).unwrap_err();
+
+            assert_eq!(
+                error,
+                CpuError::UnknownOpcode {
+                    opcode: 0x02,
+                    program_counter: 0x0004,
+                    cycles: 8,
+                }
+            );
+            assert_eq!(error.instruction(), None);
+
+            // The state is left at the faulting instruction
+            assert_eq!(state.registers.program_counter, 0x0004);
+            assert_eq!(state.cycles, 8);
+            assert_eq!(state.step().unwrap_err(), error);
+
+            let mut state = ComputerState::initialize_from_image(vec![0x02; 0x10000]);
+            assert_eq!(state.tick().unwrap_err().program_counter(), 0x0000);
+        }
+
+        #[test]
+        fn it_reports_what_each_step_did() {
+            let program = asm6502!(start: lda #0; beq start; bne $0085);
+            let mut image = vec![0; 0x10000];
+            image[..program.len()].copy_from_slice(&program);
+            let mut state = ComputerState::initialize_from_image(image);
+            state.set_status_flag(StatusFlag::INTERRUPT, false);
+            state.set_irq_line(true);
+
+            let result = state.step().unwrap();
+            assert_eq!(result.instruction, None);
+            assert_eq!(result.cycles, 7);
+            state.set_irq_line(false);
+            state.registers.program_counter = 0;
+
+            let result = state.step().unwrap();
+            assert_eq!(
+                result.instruction,
+                Some(Instruction(OperandMode::Immediate, Operation::LDA))
+            );
+            assert_eq!(result.cycles, 2);
+            assert!(!result.branch_taken);
+
+            let result = state.step().unwrap();
+            assert_eq!(result.cycles, 3);
+            assert!(result.branch_taken);
+            assert_eq!(state.registers.program_counter, 0x0000);
+
+            state.registers.program_counter = 4;
+            let result = state.step().unwrap();
+            assert_eq!(result.cycles, 2);
+            assert!(!result.branch_taken);
+        }
+
+        #[test]
+        fn it_steps_across_the_top_of_memory() {
+            // JSR $0200 at $FFFD pushes $FFFF, and the RTS at $0200 returns to $0000
+            let mut image = vec![0; 0x10000];
+            image[0xFFFD..].copy_from_slice(&[0x20, 0x00, 0x02]);
+            image[0x0200] = 0x60;
+            let mut state = ComputerState::initialize_from_image(image);
+            state.registers.program_counter = 0xFFFD;
+            state.registers.stack_pointer = 0xFF;
+            let mut ticked = state.clone();
+
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 0x0200);
+            assert_eq!(state.peek_word_from_memory(0x01FE), 0xFFFF);
+            state.step().unwrap();
+            assert_eq!(state.registers.program_counter, 0x0000);
+
+            while ticked.cycles < state.cycles {
+                ticked.tick().unwrap();
+            }
+            assert!(ticked == state);
+
+            // LDA $1234 at $FFFE, with its operand wrapping around to $0000
+            let mut image = vec![0; 0x10000];
+            image[0xFFFE..].copy_from_slice(&[0xAD, 0x34]);
+            image[0x0000] = 0x12;
+            image[0x1234] = 0x42;
+            let mut state = ComputerState::initialize_from_image(image);
+            state.registers.program_counter = 0xFFFE;
+            state.step().unwrap();
+            assert_eq!(state.registers.accumulator, 0x42);
+            assert_eq!(state.registers.program_counter, 0x0001);
+        }
+
         #[test]
         fn test_program_counter() {
-            let program = vec![0xEA, 0xEA, 0xEA, 0x69, 0x01, 0x69, 0x01];
+            let program = asm6502!(nop; nop; nop; adc #$01; adc #$01);
             let mut state = ComputerState::initialize_from_image(program);
 
             assert_eq!(state.registers.program_counter, 0);
-            state = state.step().unwrap();
+            state.step().unwrap();
             assert_eq!(state.registers.program_counter, 1);
-            state = state.step().unwrap();
+            state.step().unwrap();
             assert_eq!(state.registers.program_counter, 2);
-            state = state.step().unwrap();
+            state.step().unwrap();
             assert_eq!(state.registers.program_counter, 3);
-            state = state.step().unwrap();
+            state.step().unwrap();
             assert_eq!(state.registers.program_counter, 5);
-            state = state.step().unwrap();
+            state.step().unwrap();
             assert_eq!(state.registers.program_counter, 7);
         }
     }
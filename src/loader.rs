@@ -0,0 +1,458 @@
+mod o65;
+
+use std::error::Error;
+use std::fmt;
+
+use crate::bus::Bus;
+use crate::variant::CpuVariant;
+use crate::ComputerState;
+pub use o65::{O65Export, O65Layout, O65Module};
+
+/// The Atari run address, which .xex files set by loading a segment over it
+const ATARI_RUNAD: u32 = 0x02E0;
+
+/// A file format for programs, which says where its bytes go and sometimes where to start
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Bytes with no header, loaded at the origin
+    Raw { origin: u16 },
+    /// A Commodore program: the load address, little-endian, then the bytes
+    Prg,
+    /// Intel HEX records, with the start from a start segment or start linear address record
+    IntelHex,
+    /// Motorola S-records, with the start from an S7, S8 or S9 record
+    SRecord,
+    /// An Atari executable: $FFFF, then segments of start and end address and the bytes, with
+    /// the start from the segment loading RUNAD ($02E0) or else the first segment's address
+    Xex,
+}
+
+/// Bytes to be loaded at an address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u16,
+    pub data: Vec<u8>,
+}
+
+/// A parsed program file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedImage {
+    pub segments: Vec<Segment>,
+    /// Where execution starts, if the format says
+    pub start: Option<u16>,
+}
+
+/// Why a program file couldn't be loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// The file ends in the middle of a header or segment
+    Truncated,
+    /// The file doesn't start with the format's header
+    InvalidHeader,
+    /// A record in a text format doesn't parse or has the wrong checksum, with its 1-based line
+    InvalidRecord { line: usize, reason: &'static str },
+    /// Bytes would be loaded above $FFFF
+    OutOfRange { address: u32 },
+    /// A feature of the format the loader doesn't handle
+    Unsupported(&'static str),
+    /// An o65 module imports a symbol the host didn't provide
+    UndefinedSymbol(String),
+    /// An o65 relocation entry or export can't be applied, like one past the end of its segment
+    InvalidRelocation(&'static str),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Truncated => write!(f, "file ends in the middle of a segment"),
+            LoadError::InvalidHeader => write!(f, "file doesn't have the format's header"),
+            LoadError::InvalidRecord { line, reason } => write!(f, "line {}: {}", line, reason),
+            LoadError::OutOfRange { address } => {
+                write!(f, "data at ${:X} is outside the address space", address)
+            }
+            LoadError::Unsupported(feature) => write!(f, "{} aren't supported", feature),
+            LoadError::UndefinedSymbol(name) => write!(f, "undefined symbol '{}'", name),
+            LoadError::InvalidRelocation(reason) => write!(f, "invalid relocation: {}", reason),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl LoadedImage {
+    pub fn parse(data: &[u8], format: ImageFormat) -> Result<LoadedImage, LoadError> {
+        match format {
+            ImageFormat::Raw { origin } => {
+                let segment = segment(origin as u32, data.to_vec())?;
+                Ok(LoadedImage {
+                    segments: vec![segment],
+                    start: None,
+                })
+            }
+            ImageFormat::Prg => {
+                if data.len() < 2 {
+                    return Err(LoadError::Truncated);
+                }
+                let address = u16::from_le_bytes([data[0], data[1]]);
+                Ok(LoadedImage {
+                    segments: vec![segment(address as u32, data[2..].to_vec())?],
+                    start: None,
+                })
+            }
+            ImageFormat::IntelHex => parse_intel_hex(data),
+            ImageFormat::SRecord => parse_s_records(data),
+            ImageFormat::Xex => parse_xex(data),
+        }
+    }
+
+    /// 64K of memory with the segments in place and zeros elsewhere
+    pub fn image(&self) -> Vec<u8> {
+        let mut image = vec![0; 0x10000];
+        for segment in &self.segments {
+            let start = segment.address as usize;
+            image[start..start + segment.data.len()].copy_from_slice(&segment.data);
+        }
+        image
+    }
+}
+
+fn segment(address: u32, data: Vec<u8>) -> Result<Segment, LoadError> {
+    let end = address + data.len() as u32;
+    if end > 0x10000 {
+        return Err(LoadError::OutOfRange {
+            address: address.max(0x10000),
+        });
+    }
+    Ok(Segment {
+        address: address as u16,
+        data,
+    })
+}
+
+fn start_address(address: u32) -> Result<u16, LoadError> {
+    if address > 0xFFFF {
+        return Err(LoadError::OutOfRange { address });
+    }
+    Ok(address as u16)
+}
+
+/// The non-empty lines of a text format, with their 1-based line numbers
+fn text_records(data: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    data.split(|&b| b == b'\n')
+        .map(trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(index, line)| (index + 1, line))
+}
+
+/// The line without the whitespace around it, like the \r of a CRLF line ending
+fn trim(line: &[u8]) -> &[u8] {
+    let start = line.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(line.len());
+    let end = line.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(start, |i| i + 1);
+    &line[start..end]
+}
+
+fn decode_hex(text: &[u8]) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    text.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn parse_intel_hex(data: &[u8]) -> Result<LoadedImage, LoadError> {
+    let mut segments = Vec::new();
+    let mut start = None;
+    let mut base = 0u32;
+    for (line, text) in text_records(data) {
+        let invalid = |reason| LoadError::InvalidRecord { line, reason };
+        if text[0] != b':' {
+            return Err(invalid("record doesn't start with ':'"));
+        }
+        let bytes = decode_hex(&text[1..]).ok_or_else(|| invalid("invalid hex digits"))?;
+        if bytes.len() < 5 || bytes.len() != 5 + bytes[0] as usize {
+            return Err(invalid("length doesn't match the record"));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(invalid("checksum doesn't match"));
+        }
+
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let payload = &bytes[4..bytes.len() - 1];
+        let word = |payload: &[u8]| match payload {
+            [high, low] => Ok(u16::from_be_bytes([*high, *low]) as u32),
+            _ => Err(invalid("address records have two bytes")),
+        };
+        match bytes[3] {
+            0x00 => segments.push(segment(base + address, payload.to_vec())?),
+            0x01 => break,
+            0x02 => base = word(payload)? << 4,
+            0x04 => base = word(payload)? << 16,
+            0x03 | 0x05 => {
+                let value = match payload {
+                    [a, b, c, d] => u32::from_be_bytes([*a, *b, *c, *d]),
+                    _ => return Err(invalid("start records have four bytes")),
+                };
+                // Type 03 is a CS:IP pair
+                let address = if bytes[3] == 0x03 {
+                    (value >> 16) * 16 + (value & 0xFFFF)
+                } else {
+                    value
+                };
+                start = Some(start_address(address)?);
+            }
+            _ => return Err(invalid("unknown record type")),
+        }
+    }
+    Ok(LoadedImage { segments, start })
+}
+
+fn parse_s_records(data: &[u8]) -> Result<LoadedImage, LoadError> {
+    let mut segments = Vec::new();
+    let mut start = None;
+    for (line, text) in text_records(data) {
+        let invalid = |reason| LoadError::InvalidRecord { line, reason };
+        if text.len() < 2 || !text[0].eq_ignore_ascii_case(&b'S') {
+            return Err(invalid("record doesn't start with 'S' and a type"));
+        }
+        let record_type = text[1];
+        let bytes = decode_hex(&text[2..]).ok_or_else(|| invalid("invalid hex digits"))?;
+        if bytes.is_empty() || bytes.len() != 1 + bytes[0] as usize {
+            return Err(invalid("length doesn't match the record"));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0xFF {
+            return Err(invalid("checksum doesn't match"));
+        }
+
+        let address_length = match record_type {
+            b'0' | b'1' | b'5' | b'9' => 2,
+            b'2' | b'6' | b'8' => 3,
+            b'3' | b'7' => 4,
+            _ => return Err(invalid("unknown record type")),
+        };
+        let fields = &bytes[1..bytes.len() - 1];
+        if fields.len() < address_length {
+            return Err(invalid("record is too short for its address"));
+        }
+        let (address, payload) = fields.split_at(address_length);
+        let address = address.iter().fold(0u32, |value, b| value << 8 | *b as u32);
+        match record_type {
+            b'1' | b'2' | b'3' => segments.push(segment(address, payload.to_vec())?),
+            b'7' | b'8' | b'9' => start = Some(start_address(address)?),
+            _ => {}
+        }
+    }
+    Ok(LoadedImage { segments, start })
+}
+
+fn parse_xex(data: &[u8]) -> Result<LoadedImage, LoadError> {
+    if !data.starts_with(&[0xFF, 0xFF]) {
+        return Err(LoadError::InvalidHeader);
+    }
+    let mut segments = Vec::new();
+    let mut run_address = None;
+    let mut rest = &data[2..];
+    while !rest.is_empty() {
+        // Segments after the first can repeat the $FFFF header
+        if rest.starts_with(&[0xFF, 0xFF]) {
+            rest = &rest[2..];
+        }
+        if rest.len() < 4 {
+            return Err(LoadError::Truncated);
+        }
+        let start = u16::from_le_bytes([rest[0], rest[1]]) as u32;
+        let end = u16::from_le_bytes([rest[2], rest[3]]) as u32;
+        if end < start {
+            return Err(LoadError::InvalidHeader);
+        }
+        let length = (end - start + 1) as usize;
+        let data = rest.get(4..4 + length).ok_or(LoadError::Truncated)?;
+        if start <= ATARI_RUNAD && ATARI_RUNAD < end {
+            let offset = (ATARI_RUNAD - start) as usize;
+            run_address = Some(u16::from_le_bytes([data[offset], data[offset + 1]]));
+        }
+        segments.push(segment(start, data.to_vec())?);
+        rest = &rest[4 + length..];
+    }
+    let start = run_address.or_else(|| segments.first().map(|segment| segment.address));
+    Ok(LoadedImage { segments, start })
+}
+
+impl ComputerState {
+    /// Builds a state with 64K of RAM from a program file and runs the reset sequence
+    /// If the file has a start address, it's written to the reset vector first so execution
+    /// starts there
+    pub fn boot_from_file(
+        data: &[u8],
+        format: ImageFormat,
+        variant: CpuVariant,
+    ) -> Result<ComputerState, LoadError> {
+        let loaded = LoadedImage::parse(data, format)?;
+        let mut image = loaded.image();
+        if let Some(start) = loaded.start {
+            image[0xFFFC..=0xFFFD].copy_from_slice(&start.to_le_bytes());
+        }
+        let mut state = ComputerState::initialize_from_image_with_variant(image, variant);
+        state.reset();
+        Ok(state)
+    }
+}
+
+impl<B: Bus> ComputerState<B> {
+    /// Writes a program file's segments through the bus, so ROM ignores them, and moves the
+    /// program counter to its start address if it has one
+    pub fn load(&mut self, data: &[u8], format: ImageFormat) -> Result<LoadedImage, LoadError> {
+        let loaded = LoadedImage::parse(data, format)?;
+        self.write_segments(&loaded.segments);
+        if let Some(start) = loaded.start {
+            self.registers.program_counter = start;
+        }
+        Ok(loaded)
+    }
+
+    fn write_segments(&mut self, segments: &[Segment]) {
+        for segment in segments {
+            for (offset, value) in segment.data.iter().enumerate() {
+                self.write_byte_to_memory(segment.address as usize + offset, *value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    mod describe_loaded_image {
+        use super::*;
+
+        fn segment(address: u16, data: &[u8]) -> Segment {
+            Segment {
+                address,
+                data: data.to_vec(),
+            }
+        }
+
+        const PROGRAM: [u8; 6] = [0xA9, 0x01, 0x8D, 0x00, 0x03, 0x00];
+
+        #[test]
+        fn it_parses_raw_and_prg_files() {
+            let raw = LoadedImage::parse(&PROGRAM, ImageFormat::Raw { origin: 0xC000 }).unwrap();
+            assert_eq!(raw.segments, vec![segment(0xC000, &PROGRAM)]);
+            assert_eq!(raw.start, None);
+
+            let prg = LoadedImage::parse(&[0x01, 0x08, 0x0B, 0x08], ImageFormat::Prg).unwrap();
+            assert_eq!(prg.segments, vec![segment(0x0801, &[0x0B, 0x08])]);
+
+            assert_eq!(LoadedImage::parse(&[0x01], ImageFormat::Prg), Err(LoadError::Truncated));
+            assert_eq!(
+                LoadedImage::parse(&[0; 2], ImageFormat::Raw { origin: 0xFFFF }),
+                Err(LoadError::OutOfRange { address: 0x10000 })
+            );
+        }
+
+        #[test]
+        fn it_parses_intel_hex() {
+            let file = b":06020000A9018D000300BE\r\n\n:0400000500000200F5\n:00000001FF\n:bogus";
+            let image = LoadedImage::parse(file, ImageFormat::IntelHex).unwrap();
+            assert_eq!(image.segments, vec![segment(0x0200, &PROGRAM)]);
+            assert_eq!(image.start, Some(0x0200));
+
+            let image =
+                LoadedImage::parse(b":0400000300100020C9", ImageFormat::IntelHex).unwrap();
+            assert_eq!(image.start, Some(0x0120));
+
+            let error = |file: &[u8]| LoadedImage::parse(file, ImageFormat::IntelHex).unwrap_err();
+            assert_eq!(
+                error(b"\n:06020000A9018D000300BF"),
+                LoadError::InvalidRecord {
+                    line: 2,
+                    reason: "checksum doesn't match"
+                }
+            );
+            assert_eq!(
+                error(b":020000040001F9\n:06020000A9018D000300BE"),
+                LoadError::OutOfRange { address: 0x10200 }
+            );
+            assert!(matches!(error(b"06020000"), LoadError::InvalidRecord { line: 1, .. }));
+            assert!(matches!(error(b":0602"), LoadError::InvalidRecord { line: 1, .. }));
+        }
+
+        #[test]
+        fn it_parses_s_records() {
+            let file = b"S00600004844521B\nS1090200A9018D000300BA\nS205001000EA00\nS9030200FA\n";
+            let image = LoadedImage::parse(file, ImageFormat::SRecord).unwrap();
+            assert_eq!(
+                image.segments,
+                vec![segment(0x0200, &PROGRAM), segment(0x1000, &[0xEA])]
+            );
+            assert_eq!(image.start, Some(0x0200));
+
+            assert_eq!(
+                LoadedImage::parse(b"S1090200A9018D000300BB", ImageFormat::SRecord),
+                Err(LoadError::InvalidRecord {
+                    line: 1,
+                    reason: "checksum doesn't match"
+                })
+            );
+        }
+
+        #[test]
+        fn it_parses_xex_segments_and_the_run_address() {
+            let mut file = vec![0xFF, 0xFF, 0x00, 0x20, 0x05, 0x20];
+            file.extend_from_slice(&PROGRAM);
+            file.extend_from_slice(&[0xFF, 0xFF, 0xE0, 0x02, 0xE1, 0x02, 0x03, 0x20]);
+            let image = LoadedImage::parse(&file, ImageFormat::Xex).unwrap();
+            assert_eq!(
+                image.segments,
+                vec![segment(0x2000, &PROGRAM), segment(0x02E0, &[0x03, 0x20])]
+            );
+            assert_eq!(image.start, Some(0x2003));
+
+            let image = LoadedImage::parse(&file[..12], ImageFormat::Xex).unwrap();
+            assert_eq!(image.start, Some(0x2000));
+
+            assert_eq!(
+                LoadedImage::parse(&file[2..], ImageFormat::Xex),
+                Err(LoadError::InvalidHeader)
+            );
+            assert_eq!(
+                LoadedImage::parse(&file[..11], ImageFormat::Xex),
+                Err(LoadError::Truncated)
+            );
+        }
+    }
+
+    mod describe_load {
+        use super::*;
+
+        #[test]
+        fn it_loads_through_the_bus_and_moves_to_the_start() {
+            let mut state = ComputerState::initialize();
+            let file = b":06020000A9018D000300BE\n:0400000500000200F5\n:00000001FF";
+            state.load(file, ImageFormat::IntelHex).unwrap();
+
+            assert_eq!(state.peek_byte_from_memory(0x0202), 0x8D);
+            assert_eq!(state.registers.program_counter, 0x0200);
+            state.multiple_steps(2).unwrap();
+            assert_eq!(state.peek_byte_from_memory(0x0300), 0x01);
+        }
+
+        #[test]
+        fn it_boots_at_the_start_address_or_the_reset_vector() {
+            let file = b"S1090200A9018D000300BA\nS9030200FA";
+            let state =
+                ComputerState::boot_from_file(file, ImageFormat::SRecord, CpuVariant::Nmos6502)
+                    .unwrap();
+            assert_eq!(state.registers.program_counter, 0x0200);
+            assert_eq!(state.peek_word_from_memory(0xFFFC), 0x0200);
+            assert_eq!(state.registers.stack_pointer, 0xFD);
+
+            let rom = [0xEA, 0x00, 0xF0];
+            let format = ImageFormat::Raw { origin: 0xFFFB };
+            let state = ComputerState::boot_from_file(&rom, format, CpuVariant::Wdc65C02).unwrap();
+            assert_eq!(state.registers.program_counter, 0xF000);
+        }
+    }
+}
@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+
+use super::{LoadError, Segment};
+use crate::bus::Bus;
+use crate::ComputerState;
+
+const MAGIC: [u8; 6] = [0x01, 0x00, b'o', b'6', b'5', 0x00];
+
+const MODE_65816: u16 = 0x8000;
+const MODE_PAGE_RELOCATION: u16 = 0x4000;
+const MODE_32_BIT: u16 = 0x2000;
+const MODE_CHAIN: u16 = 0x0400;
+const MODE_BSS_ZERO: u16 = 0x0200;
+
+const SEGMENT_UNDEFINED: u8 = 0;
+const SEGMENT_ABSOLUTE: u8 = 1;
+const SEGMENT_TEXT: u8 = 2;
+const SEGMENT_DATA: u8 = 3;
+const SEGMENT_BSS: u8 = 4;
+const SEGMENT_ZERO_PAGE: u8 = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum RelocationKind {
+    Word,
+    /// The high byte, with the low byte it was computed with unless relocation is page-wise
+    High(u8),
+    Low,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Target {
+    Segment(u8),
+    /// An index into the undefined references
+    Undefined(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Relocation {
+    /// From the start of the segment
+    offset: usize,
+    kind: RelocationKind,
+    target: Target,
+}
+
+/// Where each segment of an o65 module goes in memory
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct O65Layout {
+    pub text: u16,
+    pub data: u16,
+    pub bss: u16,
+    pub zero_page: u16,
+}
+
+/// A symbol an o65 module exports, with its value relative to the segment's assembled base
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct O65Export {
+    pub name: String,
+    /// 1 for absolute values, 2 to 5 for the text, data, bss and zero page segments
+    pub segment: u8,
+    pub value: u16,
+}
+
+/// A relocatable object in André Fachat's o65 format, for the 6502
+/// 65816 objects, chained files and 65816 segment relocations aren't supported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct O65Module {
+    pub mode: u16,
+    /// The addresses the module was assembled for, which relocation moves it from
+    pub assembled_at: O65Layout,
+    pub bss_length: u16,
+    pub zero_page_length: u16,
+    pub stack_size: u16,
+    /// Header options as (type, bytes), e.g. type 0 is the file name
+    pub options: Vec<(u8, Vec<u8>)>,
+    pub text: Vec<u8>,
+    pub data: Vec<u8>,
+    /// The symbols the module imports, which the host provides when loading it
+    pub undefined: Vec<String>,
+    pub exports: Vec<O65Export>,
+    text_relocations: Vec<Relocation>,
+    data_relocations: Vec<Relocation>,
+}
+
+/// Reads the file front to back, with words 16 or 32 bits wide depending on the mode
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    wide: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], LoadError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or(LoadError::Truncated)?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, LoadError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn word(&mut self) -> Result<u32, LoadError> {
+        let bytes = self.bytes(if self.wide { 4 } else { 2 })?;
+        Ok(bytes.iter().rev().fold(0, |value, b| value << 8 | *b as u32))
+    }
+
+    /// A word that has to fit in the 6502's address space
+    fn address(&mut self) -> Result<u16, LoadError> {
+        let word = self.word()?;
+        if word > 0xFFFF {
+            return Err(LoadError::OutOfRange { address: word });
+        }
+        Ok(word as u16)
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let rest = &self.data[self.position..];
+        let length = rest.iter().position(|&b| b == 0).ok_or(LoadError::Truncated)?;
+        self.position += length + 1;
+        Ok(String::from_utf8_lossy(&rest[..length]).into_owned())
+    }
+}
+
+impl O65Module {
+    pub fn parse(file: &[u8]) -> Result<O65Module, LoadError> {
+        if !file.starts_with(&MAGIC) {
+            return Err(LoadError::InvalidHeader);
+        }
+        let mut reader = Reader {
+            data: file,
+            position: MAGIC.len(),
+            wide: false,
+        };
+        let mode = reader.byte()? as u16 | (reader.byte()? as u16) << 8;
+        if mode & MODE_65816 != 0 {
+            return Err(LoadError::Unsupported("65816 o65 objects"));
+        }
+        if mode & MODE_CHAIN != 0 {
+            return Err(LoadError::Unsupported("chained o65 files"));
+        }
+        reader.wide = mode & MODE_32_BIT != 0;
+
+        let text_base = reader.address()?;
+        let text_length = reader.address()?;
+        let data_base = reader.address()?;
+        let data_length = reader.address()?;
+        let bss_base = reader.address()?;
+        let bss_length = reader.address()?;
+        let zero_page_base = reader.address()?;
+        let zero_page_length = reader.address()?;
+        let stack_size = reader.address()?;
+
+        let mut options = Vec::new();
+        loop {
+            let length = reader.byte()? as usize;
+            if length == 0 {
+                break;
+            }
+            let option = reader.bytes(length.max(2) - 1)?;
+            options.push((option[0], option[1..].to_vec()));
+        }
+
+        let text = reader.bytes(text_length as usize)?.to_vec();
+        let data = reader.bytes(data_length as usize)?.to_vec();
+        let undefined = (0..reader.word()?)
+            .map(|_| reader.string())
+            .collect::<Result<Vec<String>, LoadError>>()?;
+        let text_relocations = read_relocations(&mut reader, mode, text.len())?;
+        let data_relocations = read_relocations(&mut reader, mode, data.len())?;
+        let exports = (0..reader.word()?)
+            .map(|_| {
+                Ok(O65Export {
+                    name: reader.string()?,
+                    segment: reader.byte()?,
+                    value: reader.address()?,
+                })
+            })
+            .collect::<Result<Vec<O65Export>, LoadError>>()?;
+
+        Ok(O65Module {
+            mode,
+            assembled_at: O65Layout {
+                text: text_base,
+                data: data_base,
+                bss: bss_base,
+                zero_page: zero_page_base,
+            },
+            bss_length,
+            zero_page_length,
+            stack_size,
+            options,
+            text,
+            data,
+            undefined,
+            exports,
+            text_relocations,
+            data_relocations,
+        })
+    }
+
+    /// The text at the base, with the data and bss after it, and the zero page where it was
+    /// assembled for
+    pub fn layout_at(&self, base: u16) -> O65Layout {
+        let data = base.wrapping_add(self.text.len() as u16);
+        O65Layout {
+            text: base,
+            data,
+            bss: data.wrapping_add(self.data.len() as u16),
+            zero_page: self.assembled_at.zero_page,
+        }
+    }
+
+    /// The text and data segments moved to the layout, with references to the undefined
+    /// symbols resolved from the imports, and the exports at their new addresses
+    pub fn relocate(
+        &self,
+        layout: O65Layout,
+        imports: &HashMap<String, u16>,
+    ) -> Result<(Vec<Segment>, HashMap<String, u16>), LoadError> {
+        let page_wise = self.mode & MODE_PAGE_RELOCATION != 0;
+        let segment_bases = [
+            (layout.text, self.assembled_at.text),
+            (layout.data, self.assembled_at.data),
+            (layout.bss, self.assembled_at.bss),
+            (layout.zero_page, self.assembled_at.zero_page),
+        ];
+        if page_wise && segment_bases.iter().any(|(new, old)| (new ^ old) & 0xFF != 0) {
+            return Err(LoadError::Unsupported("page-wise relocations to bases within a page"));
+        }
+
+        let offset = |target: Target| match target {
+            Target::Segment(segment) => self.offset(segment, layout),
+            Target::Undefined(index) => {
+                let name = self
+                    .undefined
+                    .get(index)
+                    .ok_or(LoadError::InvalidRelocation("no such undefined symbol"))?;
+                imports
+                    .get(name)
+                    .copied()
+                    .ok_or_else(|| LoadError::UndefinedSymbol(name.clone()))
+            }
+        };
+
+        let mut text = self.text.clone();
+        let mut data = self.data.clone();
+        for (bytes, relocations) in [
+            (&mut text, &self.text_relocations),
+            (&mut data, &self.data_relocations),
+        ] {
+            for relocation in relocations {
+                apply(bytes, relocation, offset(relocation.target)?, page_wise);
+            }
+        }
+
+        let exports = self
+            .exports
+            .iter()
+            .map(|export| {
+                let offset = self.offset(export.segment, layout)?;
+                Ok((export.name.clone(), export.value.wrapping_add(offset)))
+            })
+            .collect::<Result<HashMap<String, u16>, LoadError>>()?;
+
+        let mut segments = vec![
+            super::segment(layout.text as u32, text)?,
+            super::segment(layout.data as u32, data)?,
+        ];
+        if self.mode & MODE_BSS_ZERO != 0 {
+            segments.push(super::segment(layout.bss as u32, vec![0; self.bss_length as usize])?);
+        }
+        Ok((segments, exports))
+    }
+
+    /// How far a segment moves
+    fn offset(&self, segment: u8, layout: O65Layout) -> Result<u16, LoadError> {
+        let (new, old) = match segment {
+            SEGMENT_ABSOLUTE => return Ok(0),
+            SEGMENT_TEXT => (layout.text, self.assembled_at.text),
+            SEGMENT_DATA => (layout.data, self.assembled_at.data),
+            SEGMENT_BSS => (layout.bss, self.assembled_at.bss),
+            SEGMENT_ZERO_PAGE => (layout.zero_page, self.assembled_at.zero_page),
+            _ => return Err(LoadError::InvalidRelocation("unknown segment")),
+        };
+        Ok(new.wrapping_sub(old))
+    }
+}
+
+/// Offsets in a relocation table are from the previous entry, starting one byte before the
+/// segment, with 255 skipping ahead 254 bytes without an entry
+fn read_relocations(
+    reader: &mut Reader,
+    mode: u16,
+    segment_length: usize,
+) -> Result<Vec<Relocation>, LoadError> {
+    let mut relocations = Vec::new();
+    let mut position: isize = -1;
+    loop {
+        let offset = match reader.byte()? {
+            0 => return Ok(relocations),
+            255 => {
+                position += 254;
+                continue;
+            }
+            offset => offset,
+        };
+        position += offset as isize;
+        let type_byte = reader.byte()?;
+        let target = match type_byte & 0x1F {
+            SEGMENT_UNDEFINED => Target::Undefined(reader.word()? as usize),
+            segment => Target::Segment(segment),
+        };
+        let kind = match type_byte & 0xE0 {
+            0x80 => RelocationKind::Word,
+            0x40 if mode & MODE_PAGE_RELOCATION != 0 => RelocationKind::High(0),
+            0x40 => RelocationKind::High(reader.byte()?),
+            0x20 => RelocationKind::Low,
+            _ => return Err(LoadError::Unsupported("65816 segment relocations")),
+        };
+        let width = if kind == RelocationKind::Word { 2 } else { 1 };
+        if position as usize + width > segment_length {
+            return Err(LoadError::InvalidRelocation("past the end of the segment"));
+        }
+        relocations.push(Relocation {
+            offset: position as usize,
+            kind,
+            target,
+        });
+    }
+}
+
+fn apply(bytes: &mut [u8], relocation: &Relocation, offset: u16, page_wise: bool) {
+    let position = relocation.offset;
+    match relocation.kind {
+        RelocationKind::Word => {
+            let word = u16::from_le_bytes([bytes[position], bytes[position + 1]]);
+            let relocated = word.wrapping_add(offset).to_le_bytes();
+            bytes[position..position + 2].copy_from_slice(&relocated);
+        }
+        RelocationKind::High(low) => {
+            let value = u16::from_le_bytes([low, bytes[position]]);
+            let offset = if page_wise { offset & 0xFF00 } else { offset };
+            bytes[position] = (value.wrapping_add(offset) >> 8) as u8;
+        }
+        RelocationKind::Low => {
+            bytes[position] = bytes[position].wrapping_add(offset as u8);
+        }
+    }
+}
+
+impl<B: Bus> ComputerState<B> {
+    /// Relocates an o65 module to the layout and writes it through the bus, resolving its
+    /// imports from the host's symbols, and returns its exports at their new addresses
+    pub fn load_o65(
+        &mut self,
+        file: &[u8],
+        layout: O65Layout,
+        imports: &HashMap<String, u16>,
+    ) -> Result<HashMap<String, u16>, LoadError> {
+        let module = O65Module::parse(file)?;
+        let (segments, exports) = module.relocate(layout, imports)?;
+        self.write_segments(&segments);
+        Ok(exports)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    /// A module assembled with text at $1000, data at $2000, bss at $3000 and zero page at $10
+    ///
+    /// ```text
+    /// start:  lda data        ; word in data
+    ///         jsr print       ; imported
+    ///         lda #<data
+    ///         ldx #>data
+    ///         sta pointer     ; zero page
+    ///         rts
+    /// data:   .word start
+    /// ```
+    fn module_file() -> Vec<u8> {
+        let mut file = MAGIC.to_vec();
+        file.extend_from_slice(&[0x00, 0x02]); // mode: zero the bss
+        for word in [0x1000u16, 14, 0x2000, 2, 0x3000, 4, 0x0010, 2, 0] {
+            file.extend_from_slice(&word.to_le_bytes());
+        }
+        file.extend_from_slice(&[6, 0, b'm', b'o', b'd', 0, 0]);
+        file.extend_from_slice(&[
+            0xAD, 0x00, 0x20, 0x20, 0x00, 0x00, 0xA9, 0x00, 0xA2, 0x20, 0x85, 0x10, 0x60, 0xEA,
+        ]);
+        file.extend_from_slice(&[0x00, 0x10]);
+        file.extend_from_slice(&[1, 0]);
+        file.extend_from_slice(b"print\0");
+        file.extend_from_slice(&[2, 0x83, 3, 0x80, 0, 0, 3, 0x23, 2, 0x43, 0x00, 2, 0x25, 0]);
+        file.extend_from_slice(&[1, 0x82, 0]);
+        file.extend_from_slice(&[2, 0]);
+        file.extend_from_slice(b"start\0\x02\x00\x10");
+        file.extend_from_slice(b"buffer\0\x04\x00\x30");
+        file
+    }
+
+    fn imports() -> HashMap<String, u16> {
+        HashMap::from([(String::from("print"), 0xFFD2)])
+    }
+
+    mod describe_o65_module {
+        use super::*;
+
+        #[test]
+        fn it_parses_the_header_and_segments() {
+            let module = O65Module::parse(&module_file()).unwrap();
+            assert_eq!(module.mode, 0x0200);
+            assert_eq!(
+                module.assembled_at,
+                O65Layout {
+                    text: 0x1000,
+                    data: 0x2000,
+                    bss: 0x3000,
+                    zero_page: 0x0010
+                }
+            );
+            assert_eq!(module.bss_length, 4);
+            assert_eq!(module.options, vec![(0, b"mod\0".to_vec())]);
+            assert_eq!(module.text.len(), 14);
+            assert_eq!(module.undefined, vec![String::from("print")]);
+            assert_eq!(module.text_relocations.len(), 5);
+            assert_eq!(
+                module.exports[1],
+                O65Export {
+                    name: String::from("buffer"),
+                    segment: SEGMENT_BSS,
+                    value: 0x3000
+                }
+            );
+        }
+
+        #[test]
+        fn it_relocates_segments_and_resolves_symbols() {
+            let module = O65Module::parse(&module_file()).unwrap();
+            let layout = module.layout_at(0x8000);
+            assert_eq!(layout.data, 0x800E);
+            assert_eq!(layout.bss, 0x8010);
+
+            let (segments, exports) = module.relocate(layout, &imports()).unwrap();
+            assert_eq!(
+                segments[0].data,
+                vec![
+                    0xAD, 0x0E, 0x80, 0x20, 0xD2, 0xFF, 0xA9, 0x0E, 0xA2, 0x80, 0x85, 0x10, 0x60,
+                    0xEA,
+                ]
+            );
+            assert_eq!(segments[1], Segment { address: 0x800E, data: vec![0x00, 0x80] });
+            assert_eq!(segments[2], Segment { address: 0x8010, data: vec![0; 4] });
+            assert_eq!(exports["start"], 0x8000);
+            assert_eq!(exports["buffer"], 0x8010);
+
+            let layout = O65Layout {
+                zero_page: 0x40,
+                ..layout
+            };
+            let (segments, _) = module.relocate(layout, &imports()).unwrap();
+            assert_eq!(segments[0].data[11], 0x40);
+        }
+
+        #[test]
+        fn it_rejects_invalid_modules() {
+            let file = module_file();
+            let module = O65Module::parse(&file).unwrap();
+            assert_eq!(
+                module.relocate(module.layout_at(0x8000), &HashMap::new()),
+                Err(LoadError::UndefinedSymbol(String::from("print")))
+            );
+            assert_eq!(O65Module::parse(&file[1..]), Err(LoadError::InvalidHeader));
+            assert_eq!(O65Module::parse(&file[..40]), Err(LoadError::Truncated));
+
+            // The data segment's relocation moved past its end, and then to an unknown segment
+            let mut file = file;
+            let table = file.windows(5).position(|w| w == [1, 0x82, 0, 2, 0]).unwrap();
+            file[table] = 2;
+            assert_eq!(
+                O65Module::parse(&file),
+                Err(LoadError::InvalidRelocation("past the end of the segment"))
+            );
+            file[table] = 1;
+            file[table + 1] = 0x87;
+            let module = O65Module::parse(&file).unwrap();
+            assert_eq!(
+                module.relocate(module.layout_at(0x8000), &imports()),
+                Err(LoadError::InvalidRelocation("unknown segment"))
+            );
+
+            file[7] = 0x80;
+            assert_eq!(
+                O65Module::parse(&file).unwrap_err().to_string(),
+                "65816 o65 objects aren't supported"
+            );
+        }
+    }
+
+    mod describe_load_o65 {
+        use super::*;
+
+        #[test]
+        fn it_writes_the_relocated_module_to_memory() {
+            let mut state = ComputerState::initialize_from_image(vec![0xFF; 0x10000]);
+            let layout = O65Module::parse(&module_file()).unwrap().layout_at(0xC000);
+            let exports = state.load_o65(&module_file(), layout, &imports()).unwrap();
+
+            assert_eq!(exports["start"], 0xC000);
+            assert_eq!(state.peek_word_from_memory(0xC004), 0xFFD2);
+            assert_eq!(state.peek_word_from_memory(0xC00E), 0xC000);
+            assert_eq!(state.peek_byte_from_memory(0xC010), 0x00);
+            assert_eq!(state.peek_byte_from_memory(0xC014), 0xFF);
+        }
+    }
+}
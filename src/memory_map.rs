@@ -0,0 +1,386 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::bus::Bus;
+use crate::snapshot::{Reader, Snapshot, SnapshotError};
+
+/// What reads from an unmapped address return
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum OpenBus {
+    /// The last value driven on the data bus, which is what floats back on most systems
+    #[default]
+    LastValue,
+    /// A fixed value, e.g. $FF for a bus with pull-up resistors
+    Fixed(u8),
+}
+
+/// Why a memory map couldn't be built, with the start address of the region at fault
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryMapError {
+    /// The region covers no addresses, like an empty ROM or a reversed range
+    EmptyRegion { start: u16 },
+    /// The region runs past $FFFF
+    OutOfRange { start: u16 },
+    /// The region shares addresses with an earlier one, which starts at other
+    Overlap { start: u16, other: u16 },
+    /// The mirror's source range is empty
+    EmptyMirrorSource { start: u16 },
+}
+
+impl fmt::Display for MemoryMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryMapError::EmptyRegion { start } => write!(f, "region at ${:04X} is empty", start),
+            MemoryMapError::OutOfRange { start } => {
+                write!(f, "region at ${:04X} runs past the end of the address space", start)
+            }
+            MemoryMapError::Overlap { start, other } => {
+                write!(f, "region at ${:04X} overlaps the region at ${:04X}", start, other)
+            }
+            MemoryMapError::EmptyMirrorSource { start } => {
+                write!(f, "mirror at ${:04X} has an empty source", start)
+            }
+        }
+    }
+}
+
+impl Error for MemoryMapError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RegionKind {
+    Ram(Vec<u8>),
+    Rom(Vec<u8>),
+    /// Repeats the region starting at the address, every given number of bytes
+    Mirror(u16, u32),
+    Unmapped(OpenBus),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Region {
+    start: u16,
+    size: u32,
+    kind: RegionKind,
+}
+
+impl Region {
+    fn end(&self) -> u32 {
+        self.start as u32 + self.size
+    }
+
+    fn contains(&self, address: u16) -> bool {
+        address >= self.start && (address as u32) < self.end()
+    }
+}
+
+/// An address space composed of RAM, ROM, mirrored and unmapped regions
+/// Writes to ROM and unmapped addresses are ignored, and addresses no region covers behave as
+/// unmapped with the map's default open bus behaviour
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMap {
+    regions: Vec<Region>,
+    open_bus: OpenBus,
+    data_bus: u8,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMapBuilder {
+    regions: Vec<Region>,
+    open_bus: OpenBus,
+}
+
+impl MemoryMapBuilder {
+    /// Zeroed RAM covering the range
+    pub fn ram(self, range: RangeInclusive<u16>) -> MemoryMapBuilder {
+        let size = range_size(&range);
+        self.add(*range.start(), size, RegionKind::Ram(vec![0; size as usize]))
+    }
+
+    /// Read-only memory holding the bytes, starting at the address
+    pub fn rom(self, start: u16, bytes: Vec<u8>) -> MemoryMapBuilder {
+        self.add(start, bytes.len() as u32, RegionKind::Rom(bytes))
+    }
+
+    /// Makes the range repeat the source range, e.g. $0800-$1FFF repeating the 2K at $0000-$07FF
+    pub fn mirror(
+        self,
+        range: RangeInclusive<u16>,
+        source: RangeInclusive<u16>,
+    ) -> MemoryMapBuilder {
+        let kind = RegionKind::Mirror(*source.start(), range_size(&source));
+        self.add(*range.start(), range_size(&range), kind)
+    }
+
+    /// Leaves the range unmapped, reading with the given open bus behaviour
+    pub fn unmapped(self, range: RangeInclusive<u16>, open_bus: OpenBus) -> MemoryMapBuilder {
+        self.add(*range.start(), range_size(&range), RegionKind::Unmapped(open_bus))
+    }
+
+    /// Open bus behaviour for addresses no region covers, the last bus value by default
+    pub fn open_bus(mut self, open_bus: OpenBus) -> MemoryMapBuilder {
+        self.open_bus = open_bus;
+        self
+    }
+
+    pub fn build(self) -> Result<MemoryMap, MemoryMapError> {
+        for (i, region) in self.regions.iter().enumerate() {
+            let start = region.start;
+            if region.size == 0 {
+                return Err(MemoryMapError::EmptyRegion { start });
+            }
+            if region.end() > 0x10000 {
+                return Err(MemoryMapError::OutOfRange { start });
+            }
+            if let RegionKind::Mirror(_, 0) = region.kind {
+                return Err(MemoryMapError::EmptyMirrorSource { start });
+            }
+            let overlapped = self.regions[..i].iter().find(|other| {
+                (region.start as u32) < other.end() && (other.start as u32) < region.end()
+            });
+            if let Some(other) = overlapped {
+                return Err(MemoryMapError::Overlap { start, other: other.start });
+            }
+        }
+
+        Ok(MemoryMap {
+            regions: self.regions,
+            open_bus: self.open_bus,
+            data_bus: 0,
+        })
+    }
+
+    fn add(mut self, start: u16, size: u32, kind: RegionKind) -> MemoryMapBuilder {
+        self.regions.push(Region { start, size, kind });
+        self
+    }
+}
+
+/// Number of addresses in the range, zero if it's reversed
+fn range_size(range: &RangeInclusive<u16>) -> u32 {
+    (*range.end() as u32 + 1).saturating_sub(*range.start() as u32)
+}
+
+impl MemoryMap {
+    pub fn builder() -> MemoryMapBuilder {
+        MemoryMapBuilder::default()
+    }
+
+    /// Follows mirrors to the region backing the address
+    /// Returns the region and the offset into it, or None if nothing is mapped there
+    fn resolve(&self, address: u16) -> Option<(usize, usize)> {
+        let mut address = address;
+        // Bounded, so mirrors of each other resolve to unmapped instead of looping forever
+        for _ in 0..=self.regions.len() {
+            let index = self.regions.iter().position(|r| r.contains(address))?;
+            let region = &self.regions[index];
+            match region.kind {
+                RegionKind::Mirror(source_start, source_size) => {
+                    let offset = (address - region.start) as u32 % source_size;
+                    address = (source_start as u32 + offset) as u16;
+                }
+                _ => return Some((index, (address - region.start) as usize)),
+            }
+        }
+        None
+    }
+
+    fn ram_regions(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.regions.iter().filter_map(|region| match &region.kind {
+            RegionKind::Ram(bytes) => Some(bytes),
+            _ => None,
+        })
+    }
+
+    fn read_open_bus(&self, open_bus: OpenBus) -> u8 {
+        match open_bus {
+            OpenBus::LastValue => self.data_bus,
+            OpenBus::Fixed(value) => value,
+        }
+    }
+}
+
+impl Bus for MemoryMap {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.peek(address);
+        self.data_bus = value;
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.data_bus = value;
+        if let Some((index, offset)) = self.resolve(address) {
+            if let RegionKind::Ram(bytes) = &mut self.regions[index].kind {
+                bytes[offset] = value;
+            }
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match self.resolve(address) {
+            Some((index, offset)) => match &self.regions[index].kind {
+                RegionKind::Ram(bytes) | RegionKind::Rom(bytes) => bytes[offset],
+                RegionKind::Unmapped(open_bus) => self.read_open_bus(*open_bus),
+                RegionKind::Mirror(_, _) => unreachable!("Mirrors are resolved to their source"),
+            },
+            None => self.read_open_bus(self.open_bus),
+        }
+    }
+}
+
+/// Saves the data bus and the RAM, since the rest of the map is fixed when it's built
+/// Restoring needs a map with RAM regions of the same sizes
+impl Snapshot for MemoryMap {
+    fn save_snapshot(&self, out: &mut Vec<u8>) {
+        out.push(self.data_bus);
+        let ram: Vec<&Vec<u8>> = self.ram_regions().collect();
+        out.extend_from_slice(&(ram.len() as u16).to_le_bytes());
+        for bytes in ram {
+            out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            out.extend_from_slice(bytes);
+        }
+    }
+
+    fn restore_snapshot(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = Reader::new(data);
+        let data_bus = reader.u8()?;
+        let count = reader.u16()? as usize;
+        if count != self.ram_regions().count() {
+            return Err(SnapshotError::Invalid("memory map has different RAM regions"));
+        }
+        let mut saved = Vec::new();
+        for bytes in self.ram_regions() {
+            let length = reader.u32()? as usize;
+            if length != bytes.len() {
+                return Err(SnapshotError::Invalid("memory map has different RAM regions"));
+            }
+            saved.push(reader.bytes(length)?);
+        }
+
+        self.data_bus = data_bus;
+        let ram = self.regions.iter_mut().filter_map(|region| match &mut region.kind {
+            RegionKind::Ram(bytes) => Some(bytes),
+            _ => None,
+        });
+        for (bytes, saved) in ram.zip(saved) {
+            bytes.copy_from_slice(saved);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    mod describe_memory_map {
+        use super::*;
+
+        #[test]
+        fn it_reads_and_writes_ram() {
+            let mut map = MemoryMap::builder().ram(0x0000..=0x07FF).build().unwrap();
+
+            map.write(0x0123, 0x42);
+            assert_eq!(map.read(0x0123), 0x42);
+            assert_eq!(map.peek(0x0123), 0x42);
+            assert_eq!(map.read(0x07FF), 0x00);
+        }
+
+        #[test]
+        fn it_ignores_writes_to_rom() {
+            let mut map = MemoryMap::builder()
+                .rom(0xFFFC, vec![0x00, 0x80, 0x34, 0x12])
+                .build()
+                .unwrap();
+
+            map.write(0xFFFD, 0xFF);
+            assert_eq!(map.read(0xFFFC), 0x00);
+            assert_eq!(map.read(0xFFFD), 0x80);
+            assert_eq!(map.read(0xFFFF), 0x12);
+        }
+
+        #[test]
+        fn it_mirrors_ranges() {
+            let mut map = MemoryMap::builder()
+                .ram(0x0000..=0x07FF)
+                .mirror(0x0800..=0x1FFF, 0x0000..=0x07FF)
+                .build()
+                .unwrap();
+
+            map.write(0x1801, 0x42);
+            assert_eq!(map.read(0x0001), 0x42);
+            assert_eq!(map.read(0x0801), 0x42);
+            assert_eq!(map.read(0x1001), 0x42);
+
+            map.write(0x07FF, 0x24);
+            assert_eq!(map.read(0x1FFF), 0x24);
+        }
+
+        #[test]
+        fn it_reads_open_bus_from_unmapped_addresses() {
+            let mut map = MemoryMap::builder()
+                .ram(0x0000..=0x00FF)
+                .unmapped(0x4000..=0x4FFF, OpenBus::Fixed(0xFF))
+                .build()
+                .unwrap();
+
+            map.write(0x0010, 0x5A);
+            assert_eq!(map.read(0x4000), 0xFF);
+            assert_eq!(map.read(0x8000), 0xFF);
+            assert_eq!(map.read(0x0010), 0x5A);
+            assert_eq!(map.peek(0x8000), 0x5A);
+            assert_eq!(map.read(0x8000), 0x5A);
+
+            let mut map = MemoryMap::builder()
+                .open_bus(OpenBus::Fixed(0xEA))
+                .build()
+                .unwrap();
+            map.write(0x1234, 0x00);
+            assert_eq!(map.read(0x1234), 0xEA);
+        }
+
+        #[test]
+        fn it_resolves_mirrors_of_each_other_as_unmapped() {
+            let map = MemoryMap::builder()
+                .mirror(0x0000..=0x00FF, 0x0100..=0x01FF)
+                .mirror(0x0100..=0x01FF, 0x0000..=0x00FF)
+                .open_bus(OpenBus::Fixed(0x77))
+                .build()
+                .unwrap();
+
+            assert_eq!(map.peek(0x0042), 0x77);
+        }
+
+        #[test]
+        fn it_rejects_invalid_regions() {
+            let overlapping = MemoryMap::builder()
+                .ram(0x0000..=0x07FF)
+                .rom(0x0700, vec![0; 0x100])
+                .build();
+            assert_eq!(
+                overlapping,
+                Err(MemoryMapError::Overlap {
+                    start: 0x0700,
+                    other: 0x0000
+                })
+            );
+
+            let too_large = MemoryMap::builder().rom(0xFF00, vec![0; 0x101]).build();
+            assert_eq!(too_large, Err(MemoryMapError::OutOfRange { start: 0xFF00 }));
+
+            let empty = MemoryMap::builder().rom(0x8000, vec![]).build();
+            assert_eq!(empty, Err(MemoryMapError::EmptyRegion { start: 0x8000 }));
+
+            #[allow(clippy::reversed_empty_ranges)]
+            let reversed = MemoryMap::builder().ram(0x0100..=0x00FF).build();
+            assert_eq!(reversed, Err(MemoryMapError::EmptyRegion { start: 0x0100 }));
+
+            #[allow(clippy::reversed_empty_ranges)]
+            let mirror = MemoryMap::builder().mirror(0x0800..=0x1FFF, 0x0100..=0x00FF).build();
+            assert_eq!(mirror, Err(MemoryMapError::EmptyMirrorSource { start: 0x0800 }));
+            assert_eq!(
+                mirror.unwrap_err().to_string(),
+                "mirror at $0800 has an empty source"
+            );
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::bus::Bus;
+use crate::ComputerState;
+
+/// What wrote a byte, and when
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WriteRecord {
+    /// The address of the writing instruction's opcode, or the program counter at the time for
+    /// writes from anything else
+    pub program_counter: u16,
+    /// None for writes from interrupt sequences and from outside the CPU, like the loaders
+    pub opcode: Option<u8>,
+    /// The cycle count when the instruction or interrupt started, like in CpuError
+    pub cycles: u32,
+    pub value: u8,
+}
+
+/// The writes to each address, newest last, and who's writing now
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WriteTracker {
+    writes: HashMap<u16, VecDeque<WriteRecord>>,
+    depth: usize,
+    /// The program counter, opcode and cycle count of the instruction or interrupt running
+    source: Option<(u16, Option<u8>, u32)>,
+}
+
+impl WriteTracker {
+    pub(crate) fn record(&mut self, address: u16, value: u8, program_counter: u16, cycles: u32) {
+        let (program_counter, opcode, cycles) =
+            self.source.unwrap_or((program_counter, None, cycles));
+        let writes = self.writes.entry(address).or_default();
+        if writes.len() == self.depth {
+            writes.pop_front();
+        }
+        writes.push_back(WriteRecord {
+            program_counter,
+            opcode,
+            cycles,
+            value,
+        });
+    }
+
+    /// Forgets the newest write to the address, for when it's undone
+    pub(crate) fn undo(&mut self, address: u16) {
+        if let Some(writes) = self.writes.get_mut(&address) {
+            writes.pop_back();
+            if writes.is_empty() {
+                self.writes.remove(&address);
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.writes.clear();
+        self.source = None;
+    }
+}
+
+impl<B: Bus> ComputerState<B> {
+    /// Starts recording who writes each address, keeping the given number of the most recent
+    /// writes per address, at least one. Tracking again starts over
+    /// In tick mode the dummy writes of NMOS read-modify-write instructions are recorded too
+    pub fn track_writes(&mut self, depth: usize) {
+        self.write_tracker = Some(WriteTracker {
+            writes: HashMap::new(),
+            depth: depth.max(1),
+            source: None,
+        });
+    }
+
+    /// Stops tracking and forgets the writes
+    pub fn stop_tracking_writes(&mut self) {
+        self.write_tracker = None;
+    }
+
+    /// The last write to the address since tracking started
+    pub fn last_write(&self, address: u16) -> Option<WriteRecord> {
+        self.writes_to(address).last().copied()
+    }
+
+    /// The tracked writes to the address, oldest first
+    pub fn writes_to(&self, address: u16) -> Vec<WriteRecord> {
+        let address = address & self.variant.address_mask();
+        self.write_tracker
+            .as_ref()
+            .and_then(|tracker| tracker.writes.get(&address))
+            .map_or_else(Vec::new, |writes| writes.iter().copied().collect())
+    }
+
+    /// Attributes the writes from now on to an instruction, or an interrupt without an opcode
+    pub(crate) fn set_write_source(
+        &mut self,
+        program_counter: u16,
+        opcode: Option<u8>,
+        cycles: u32,
+    ) {
+        if let Some(tracker) = &mut self.write_tracker {
+            tracker.source = Some((program_counter, opcode, cycles));
+        }
+    }
+
+    /// Writes from now on come from outside the CPU
+    pub(crate) fn clear_write_source(&mut self) {
+        if let Some(tracker) = &mut self.write_tracker {
+            tracker.source = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::variant::CpuVariant;
+    use nestegg_macros::asm6502;
+
+    mod describe_write_tracking {
+        use super::*;
+
+        fn tracking(depth: usize) -> ComputerState {
+            let program = asm6502! {
+                .org $0200;
+                lda #1; sta $10;
+                jsr count; jsr count;
+                brk;
+                count: inc $10; rts
+            };
+            let mut state = ComputerState::with_program(CpuVariant::Nmos6502, 0x0200, &program);
+            state.track_writes(depth);
+            state
+        }
+
+        #[test]
+        fn it_records_the_last_write_to_each_address() {
+            let mut state = tracking(1);
+            state.multiple_steps(2).unwrap();
+            assert_eq!(
+                state.last_write(0x10),
+                Some(WriteRecord {
+                    program_counter: 0x0202,
+                    opcode: Some(0x85),
+                    cycles: 2,
+                    value: 1
+                })
+            );
+            assert_eq!(state.last_write(0x11), None);
+
+            state.multiple_steps(6).unwrap();
+            let write = state.last_write(0x10).unwrap();
+            assert_eq!((write.program_counter, write.opcode), (0x020B, Some(0xE6)));
+            assert_eq!(write.value, 3);
+            assert_eq!(state.writes_to(0x10).len(), 1);
+
+            // JSR pushed the return address
+            let push = state.last_write(0x01FF).unwrap();
+            assert_eq!((push.program_counter, push.opcode), (0x0207, Some(0x20)));
+        }
+
+        #[test]
+        fn it_keeps_a_bounded_history_per_address() {
+            let mut state = tracking(2);
+            state.multiple_steps(8).unwrap();
+
+            let writes = state.writes_to(0x10);
+            let values: Vec<u8> = writes.iter().map(|write| write.value).collect();
+            assert_eq!(values, vec![2, 3]);
+            assert!(writes[0].cycles < writes[1].cycles);
+
+            state.stop_tracking_writes();
+            assert!(state.writes_to(0x10).is_empty());
+        }
+
+        #[test]
+        fn it_attributes_writes_outside_instructions() {
+            let mut state = tracking(1);
+            state.write_byte_to_memory(0x0300, 0x42);
+            assert_eq!(
+                state.last_write(0x0300),
+                Some(WriteRecord {
+                    program_counter: 0x0200,
+                    opcode: None,
+                    cycles: 0,
+                    value: 0x42
+                })
+            );
+
+            state.write_word_to_memory(0xFFFE, 0x0400);
+            state.set_irq_line(true);
+            state.step().unwrap();
+            let push = state.last_write(0x01FD).unwrap();
+            assert_eq!((push.program_counter, push.opcode), (0x0200, None));
+        }
+
+        #[test]
+        fn it_records_the_same_writes_when_ticking() {
+            let mut stepped = tracking(8);
+            let mut ticked = stepped.clone();
+            stepped.multiple_steps(8).unwrap();
+            while ticked.cycles < stepped.cycles {
+                ticked.tick().unwrap();
+            }
+
+            for address in [0x10, 0x01FE, 0x01FF] {
+                assert_eq!(ticked.last_write(address), stepped.last_write(address));
+            }
+            // Including the dummy writes of INC's old value
+            let values: Vec<u8> = ticked.writes_to(0x10).iter().map(|w| w.value).collect();
+            assert_eq!(values, vec![1, 1, 2, 2, 3]);
+            ticked.write_byte_to_memory(0x0300, 0x42);
+            assert_eq!(ticked.last_write(0x0300).unwrap().opcode, None);
+        }
+
+        #[test]
+        fn it_forgets_writes_which_are_undone() {
+            let mut state = tracking(8);
+            state.record_history(1 << 20);
+            state.multiple_steps(3).unwrap();
+            assert_eq!(state.last_write(0x01FF).unwrap().opcode, Some(0x20));
+
+            assert!(state.step_back());
+            assert_eq!(state.last_write(0x01FF), None);
+            assert_eq!(state.last_write(0x10).unwrap().value, 1);
+            assert_eq!(state.rewind_steps(2), 2);
+            assert_eq!(state.last_write(0x10), None);
+
+            state.multiple_steps(3).unwrap();
+            assert_eq!(state.writes_to(0x10).len(), 1);
+        }
+
+        #[test]
+        fn it_forgets_writes_when_loading_a_snapshot() {
+            let mut state = tracking(8);
+            let snapshot = state.save_state();
+            state.multiple_steps(2).unwrap();
+            state.load_state(&snapshot).unwrap();
+            assert_eq!(state.last_write(0x10), None);
+
+            state.multiple_steps(2).unwrap();
+            assert_eq!(state.writes_to(0x10).len(), 1);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use crate::bus::Bus;
+use crate::error::CpuError;
+use crate::instruction::operation::Operation;
+use crate::ComputerState;
+
+/// Why a run stopped
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// At least the cycle budget has been used up
+    CycleBudget,
+    /// The program counter reached the target address
+    Address,
+    /// The next instruction is a BRK, which hasn't run
+    Break,
+    /// The last instruction jumped or branched to itself, so the CPU would spin there forever
+    InfiniteLoop,
+    /// The predicate returned true
+    Predicate,
+}
+
+/// When a run stops, in addition to failing instructions
+/// Everything but infinite loops is checked before each instruction, including the first, so a
+/// run started at a breakpoint stops straight away
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct StopConditions {
+    cycles: Option<u32>,
+    address: Option<u16>,
+    on_break: bool,
+    on_infinite_loop: bool,
+}
+
+impl StopConditions {
+    /// Stops once the run has taken at least the given number of cycles
+    pub fn cycles(mut self, cycles: u32) -> StopConditions {
+        self.cycles = Some(cycles);
+        self
+    }
+
+    /// Stops when the program counter reaches the address
+    pub fn address(mut self, address: u16) -> StopConditions {
+        self.address = Some(address);
+        self
+    }
+
+    /// Stops before executing a BRK
+    pub fn on_break(mut self) -> StopConditions {
+        self.on_break = true;
+        self
+    }
+
+    /// Stops after a JMP or branch to itself, e.g. a program parked in `JMP *`
+    pub fn on_infinite_loop(mut self) -> StopConditions {
+        self.on_infinite_loop = true;
+        self
+    }
+}
+
+impl<B: Bus> ComputerState<B> {
+    /// Steps until one of the conditions is met or an instruction fails
+    /// Without a cycle budget this runs forever if none of the other conditions are ever met
+    pub fn run(&mut self, conditions: StopConditions) -> Result<StopReason, CpuError> {
+        self.run_until(conditions, |_| false)
+    }
+
+    /// Steps until the number of cycles have passed
+    pub fn run_for_cycles(&mut self, cycles: u32) -> Result<StopReason, CpuError> {
+        self.run(StopConditions::default().cycles(cycles))
+    }
+
+    /// Steps until one of the conditions is met, or the predicate returns true for the state
+    /// before an instruction
+    pub fn run_until<F>(
+        &mut self,
+        conditions: StopConditions,
+        mut predicate: F,
+    ) -> Result<StopReason, CpuError>
+    where
+        F: FnMut(&Self) -> bool,
+    {
+        let start_cycles = self.cycles;
+        loop {
+            let program_counter = self.registers.program_counter;
+            if let Some(cycles) = conditions.cycles {
+                if self.cycles.wrapping_sub(start_cycles) >= cycles {
+                    return Ok(StopReason::CycleBudget);
+                }
+            }
+            if conditions.address == Some(program_counter) {
+                return Ok(StopReason::Address);
+            }
+            if conditions.on_break
+                && !self.instruction_in_progress()
+                && self.peek_byte_from_memory(program_counter as usize) == 0x00
+            {
+                return Ok(StopReason::Break);
+            }
+            if predicate(self) {
+                return Ok(StopReason::Predicate);
+            }
+
+            let result = self.step()?;
+            let jumped_to_itself = match result.instruction {
+                Some(instruction) => {
+                    self.registers.program_counter == program_counter
+                        && (result.branch_taken || instruction.1 == Operation::JMP)
+                }
+                None => false,
+            };
+            if conditions.on_infinite_loop && jumped_to_itself {
+                return Ok(StopReason::InfiniteLoop);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use nestegg_macros::asm6502;
+
+    mod describe_run {
+        use super::*;
+
+        #[test]
+        fn it_runs_for_a_cycle_budget() {
+            let mut state = ComputerState::initialize_from_image(vec![0xEA; 0x10000]);
+
+            assert_eq!(state.run_for_cycles(9).unwrap(), StopReason::CycleBudget);
+            assert_eq!(state.cycles, 10);
+            assert_eq!(state.registers.program_counter, 5);
+        }
+
+        #[test]
+        fn it_runs_to_an_address() {
+            let mut state = ComputerState::initialize_from_image(vec![0xEA; 0x10000]);
+            let conditions = StopConditions::default().address(0x0010).cycles(1000);
+
+            assert_eq!(state.run(conditions).unwrap(), StopReason::Address);
+            assert_eq!(state.registers.program_counter, 0x0010);
+            assert_eq!(state.run(conditions).unwrap(), StopReason::Address);
+            assert_eq!(state.cycles, 32);
+        }
+
+        #[test]
+        fn it_stops_before_break() {
+            let mut image = vec![0xEA; 0x10000];
+            image[0x0003] = 0x00;
+            let mut state = ComputerState::initialize_from_image(image);
+
+            let reason = state.run(StopConditions::default().on_break()).unwrap();
+            assert_eq!(reason, StopReason::Break);
+            assert_eq!(state.registers.program_counter, 0x0003);
+            assert_eq!(state.cycles, 6);
+        }
+
+        #[test]
+        fn it_stops_on_infinite_loops() {
+            let mut image = vec![0xEA; 0x10000];
+            image[0x0002..0x0005].copy_from_slice(&asm6502!(.org $0002; jmp *));
+            image[0x0010..0x0012].copy_from_slice(&asm6502!(bne *));
+            let mut state = ComputerState::initialize_from_image(image);
+            let conditions = StopConditions::default().on_infinite_loop();
+
+            assert_eq!(state.run(conditions).unwrap(), StopReason::InfiniteLoop);
+            assert_eq!(state.registers.program_counter, 0x0002);
+            assert_eq!(state.cycles, 4 + 3);
+
+            state.registers.program_counter = 0x0010;
+            assert_eq!(state.run(conditions).unwrap(), StopReason::InfiniteLoop);
+            assert_eq!(state.registers.program_counter, 0x0010);
+        }
+
+        #[test]
+        fn it_stops_when_the_predicate_holds() {
+            let mut image = vec![0xEA; 0x10000];
+            image[0x0000..0x0004].copy_from_slice(&asm6502!(loop: inx; bne loop; brk));
+            let mut state = ComputerState::initialize_from_image(image);
+
+            let reason = state
+                .run_until(StopConditions::default(), |state| state.registers.x == 0x40)
+                .unwrap();
+            assert_eq!(reason, StopReason::Predicate);
+            assert_eq!(state.registers.x, 0x40);
+            assert_eq!(state.registers.program_counter, 0x0001);
+        }
+
+        #[test]
+        fn it_stops_at_failing_instructions() {
+            let mut image = vec![0xEA; 0x10000];
+            image[0x0002] = 0x02;
+            let mut state = ComputerState::initialize_from_image(image);
+
+            let error = state.run_for_cycles(100).unwrap_err();
+            assert_eq!(error.program_counter(), 0x0002);
+            assert_eq!(state.registers.program_counter, 0x0002);
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::bus::Bus;
+use crate::io_port::IoPort;
+use crate::tick::Sequence;
+use crate::variant::CpuVariant;
+use crate::{ComputerState, RegisterFile};
+
+/// The first bytes of every snapshot
+const MAGIC: &[u8; 8] = b"NESTEGG\x1A";
+
+/// The format version save_state writes, and the only one load_state accepts
+/// Bumped whenever a change would make older versions misread a snapshot; new sections that
+/// older versions can skip don't need it
+pub const SNAPSHOT_VERSION: u16 = 1;
+
+/// Registers, cycle count, variant and interrupt lines
+const CPU_SECTION: [u8; 4] = *b"CPU ";
+/// An instruction or interrupt part way through in tick mode
+const SEQUENCE_SECTION: [u8; 4] = *b"TICK";
+/// The 6510 I/O port
+const IO_PORT_SECTION: [u8; 4] = *b"PORT";
+/// Whatever the bus saves, including any attached devices
+const BUS_SECTION: [u8; 4] = *b"BUS ";
+
+const IRQ_LINE: u8 = 1 << 0;
+const NMI_LINE: u8 = 1 << 1;
+const NMI_PENDING: u8 = 1 << 2;
+const RESET_PENDING: u8 = 1 << 3;
+const WAITING_FOR_INTERRUPT: u8 = 1 << 4;
+const STOPPED: u8 = 1 << 5;
+const BRANCH_TAKEN: u8 = 1 << 6;
+
+/// A bus whose state, including any devices attached to it, can be saved in a snapshot
+pub trait Snapshot {
+    /// Appends the state to the snapshot
+    fn save_snapshot(&self, out: &mut Vec<u8>);
+
+    /// Restores the state save_snapshot wrote, leaving the bus unchanged on errors
+    fn restore_snapshot(&mut self, data: &[u8]) -> Result<(), SnapshotError>;
+}
+
+/// Why a snapshot couldn't be restored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The data doesn't start with the snapshot header
+    NotASnapshot,
+    /// The snapshot was saved in a format version this one can't read
+    UnsupportedVersion { found: u16, supported: u16 },
+    /// The snapshot ends in the middle of a section
+    Truncated,
+    /// A section is missing or holds something the state can't take, like a memory map laid out
+    /// differently
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "data isn't a snapshot"),
+            SnapshotError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "snapshot is format version {}, but only version {} is supported",
+                found, supported
+            ),
+            SnapshotError::Truncated => write!(f, "snapshot ends in the middle of a section"),
+            SnapshotError::Invalid(reason) => write!(f, "invalid snapshot: {}", reason),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+/// Reads the little-endian values a snapshot is made of
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    pub(crate) fn bytes(&mut self, length: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.data.len() < length {
+            return Err(SnapshotError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, SnapshotError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// Flat RAM saves its size and contents, and restores any size
+impl Snapshot for Vec<u8> {
+    fn save_snapshot(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.len() as u32).to_le_bytes());
+        out.extend_from_slice(self);
+    }
+
+    fn restore_snapshot(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = Reader::new(data);
+        let length = reader.u32()? as usize;
+        let memory = reader.bytes(length)?;
+        self.clear();
+        self.extend_from_slice(memory);
+        Ok(())
+    }
+}
+
+impl ComputerState {
+    /// A state with flat RAM, restored from a snapshot
+    pub fn from_snapshot(snapshot: &[u8]) -> Result<ComputerState, SnapshotError> {
+        let mut state = ComputerState::initialize();
+        state.load_state(snapshot)?;
+        Ok(state)
+    }
+}
+
+impl<B: Bus + Snapshot> ComputerState<B> {
+    /// Saves the variant, registers, cycle count, interrupt lines, any instruction part way
+    /// through in tick mode, the 6510 I/O port and the bus as a snapshot
+    ///
+    /// The snapshot is a header, then sections with a 4 byte tag and a 32-bit length, so tools
+    /// can find their way around it without knowing every section
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut snapshot = MAGIC.to_vec();
+        snapshot.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+
+        let mut cpu = vec![variant_number(self.variant)];
+        let registers = &self.registers;
+        cpu.extend_from_slice(&[
+            registers.accumulator,
+            registers.x,
+            registers.y,
+            registers.status,
+            registers.stack_pointer,
+        ]);
+        cpu.extend_from_slice(&registers.program_counter.to_le_bytes());
+        cpu.extend_from_slice(&self.cycles.to_le_bytes());
+        cpu.push(self.interrupt_flags());
+        add_section(&mut snapshot, CPU_SECTION, &cpu);
+
+        if let Some(sequence) = &self.sequence {
+            let mut data = Vec::new();
+            sequence.save_snapshot(&mut data);
+            add_section(&mut snapshot, SEQUENCE_SECTION, &data);
+        }
+        if let Some(port) = &self.io_port {
+            let mut data = Vec::new();
+            port.save_snapshot(&mut data);
+            add_section(&mut snapshot, IO_PORT_SECTION, &data);
+        }
+        let mut bus = Vec::new();
+        self.memory.save_snapshot(&mut bus);
+        add_section(&mut snapshot, BUS_SECTION, &bus);
+        snapshot
+    }
+
+    /// Restores a snapshot save_state wrote, replacing everything it saved and forgetting any
+    /// history and tracked writes
+    /// Snapshots from other format versions are rejected, and the state is unchanged on errors
+    pub fn load_state(&mut self, snapshot: &[u8]) -> Result<(), SnapshotError> {
+        if !snapshot.starts_with(MAGIC) {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let mut reader = Reader::new(&snapshot[MAGIC.len()..]);
+        let version = reader.u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion {
+                found: version,
+                supported: SNAPSHOT_VERSION,
+            });
+        }
+
+        let mut sections = HashMap::new();
+        while !reader.is_empty() {
+            let tag = reader.bytes(4)?;
+            let length = reader.u32()? as usize;
+            sections.insert(tag, reader.bytes(length)?);
+        }
+        let section = |tag: [u8; 4]| sections.get(&tag[..]).map(|data| Reader::new(data));
+
+        let mut cpu = section(CPU_SECTION).ok_or(SnapshotError::Invalid("no CPU section"))?;
+        let variant = match variant_from_number(cpu.u8()?) {
+            Some(variant) => variant,
+            None => return Err(SnapshotError::Invalid("unknown CPU variant")),
+        };
+        let registers = RegisterFile {
+            accumulator: cpu.u8()?,
+            x: cpu.u8()?,
+            y: cpu.u8()?,
+            status: cpu.u8()?,
+            stack_pointer: cpu.u8()?,
+            program_counter: cpu.u16()?,
+        };
+        let cycles = cpu.u32()?;
+        let flags = cpu.u8()?;
+
+        let sequence = match section(SEQUENCE_SECTION) {
+            Some(mut data) => Some(Sequence::restore_snapshot(&mut data, variant)?),
+            None => None,
+        };
+        let io_port = match (variant.has_io_port(), section(IO_PORT_SECTION)) {
+            (true, Some(mut data)) => Some(IoPort::restore_snapshot(&mut data)?),
+            (true, None) => return Err(SnapshotError::Invalid("no 6510 I/O port section")),
+            (false, _) => None,
+        };
+        let bus = sections
+            .get(&BUS_SECTION[..])
+            .ok_or(SnapshotError::Invalid("no bus section"))?;
+        self.memory.restore_snapshot(bus)?;
+
+        self.variant = variant;
+        self.registers = registers;
+        self.cycles = cycles;
+        self.io_port = io_port;
+        self.irq_line = flags & IRQ_LINE != 0;
+        self.nmi_line = flags & NMI_LINE != 0;
+        self.nmi_pending = flags & NMI_PENDING != 0;
+        self.reset_pending = flags & RESET_PENDING != 0;
+        self.waiting_for_interrupt = flags & WAITING_FOR_INTERRUPT != 0;
+        self.stopped = flags & STOPPED != 0;
+        self.branch_taken = flags & BRANCH_TAKEN != 0;
+        self.sequence = sequence;
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        if let Some(tracker) = &mut self.write_tracker {
+            tracker.clear();
+        }
+        Ok(())
+    }
+
+    fn interrupt_flags(&self) -> u8 {
+        [
+            (self.irq_line, IRQ_LINE),
+            (self.nmi_line, NMI_LINE),
+            (self.nmi_pending, NMI_PENDING),
+            (self.reset_pending, RESET_PENDING),
+            (self.waiting_for_interrupt, WAITING_FOR_INTERRUPT),
+            (self.stopped, STOPPED),
+            (self.branch_taken, BRANCH_TAKEN),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |flags, (_, flag)| flags | flag)
+    }
+}
+
+/// The numbers are part of the format, so they can't change
+fn variant_number(variant: CpuVariant) -> u8 {
+    match variant {
+        CpuVariant::Nmos6502 => 0,
+        CpuVariant::Ricoh2A03 => 1,
+        CpuVariant::Mos6507 => 2,
+        CpuVariant::Mos6510 => 3,
+        CpuVariant::Wdc65C02 => 4,
+        CpuVariant::Wdc65SC02 => 5,
+    }
+}
+
+fn variant_from_number(number: u8) -> Option<CpuVariant> {
+    match number {
+        0 => Some(CpuVariant::Nmos6502),
+        1 => Some(CpuVariant::Ricoh2A03),
+        2 => Some(CpuVariant::Mos6507),
+        3 => Some(CpuVariant::Mos6510),
+        4 => Some(CpuVariant::Wdc65C02),
+        5 => Some(CpuVariant::Wdc65SC02),
+        _ => None,
+    }
+}
+
+fn add_section(snapshot: &mut Vec<u8>, tag: [u8; 4], data: &[u8]) {
+    snapshot.extend_from_slice(&tag);
+    snapshot.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    snapshot.extend_from_slice(data);
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::memory_map::MemoryMap;
+    use crate::Bus;
+    use nestegg_macros::asm6502;
+
+    mod describe_snapshot {
+        use super::*;
+
+        fn running(variant: CpuVariant) -> ComputerState {
+            let program = asm6502! {
+                .org $8000;
+                ldx #0;
+                loop: inc $0300,x; inx; bne loop;
+                lda ($10),y; jmp loop
+            };
+            ComputerState::with_program(variant, 0x8000, &program)
+        }
+
+        #[test]
+        fn it_resumes_where_it_was_saved() {
+            let mut state = running(CpuVariant::Nmos6502);
+            state.multiple_steps(20).unwrap();
+            // Part way through INC absolute,X, with an IRQ waiting
+            state.tick().unwrap();
+            state.tick().unwrap();
+            state.tick().unwrap();
+            state.set_irq_line(true);
+            assert!(state.instruction_in_progress());
+
+            let snapshot = state.save_state();
+            let mut restored = ComputerState::from_snapshot(&snapshot).unwrap();
+            assert!(restored == state);
+
+            for _ in 0..100 {
+                state.tick().unwrap();
+                restored.tick().unwrap();
+            }
+            assert!(restored == state);
+        }
+
+        #[test]
+        fn it_saves_the_io_port_and_variant() {
+            let mut state = running(CpuVariant::Mos6510);
+            state.write_byte_to_memory(0x0000, 0x0F);
+            state.write_byte_to_memory(0x0001, 0x05);
+            state.set_io_port_input(0x30);
+
+            let restored = ComputerState::from_snapshot(&state.save_state()).unwrap();
+            assert_eq!(restored.variant(), CpuVariant::Mos6510);
+            assert_eq!(restored.io_port_pins(), Some(0x35));
+            assert!(restored == state);
+
+            for number in 0..=u8::MAX {
+                if let Some(variant) = variant_from_number(number) {
+                    assert_eq!(variant_number(variant), number);
+                    let state = running(variant);
+                    let restored = ComputerState::from_snapshot(&state.save_state()).unwrap();
+                    assert_eq!(restored.variant(), variant);
+                }
+            }
+        }
+
+        #[test]
+        fn it_rejects_other_versions() {
+            let mut snapshot = running(CpuVariant::Nmos6502).save_state();
+            snapshot[8..10].copy_from_slice(&2u16.to_le_bytes());
+
+            let mut state = ComputerState::initialize();
+            let error = state.load_state(&snapshot).unwrap_err();
+            assert_eq!(
+                error,
+                SnapshotError::UnsupportedVersion {
+                    found: 2,
+                    supported: SNAPSHOT_VERSION
+                }
+            );
+            assert_eq!(
+                error.to_string(),
+                "snapshot is format version 2, but only version 1 is supported"
+            );
+            assert!(state == ComputerState::initialize());
+        }
+
+        #[test]
+        fn it_rejects_broken_snapshots() {
+            let snapshot = running(CpuVariant::Nmos6502).save_state();
+            assert_eq!(
+                ComputerState::from_snapshot(&snapshot[..snapshot.len() - 1]).err(),
+                Some(SnapshotError::Truncated)
+            );
+            assert_eq!(
+                ComputerState::from_snapshot(b"not a snapshot").err(),
+                Some(SnapshotError::NotASnapshot)
+            );
+
+            let mut unknown_variant = snapshot.clone();
+            unknown_variant[18] = 42;
+            assert_eq!(
+                ComputerState::from_snapshot(&unknown_variant).err(),
+                Some(SnapshotError::Invalid("unknown CPU variant"))
+            );
+        }
+
+        #[test]
+        fn it_skips_sections_it_doesnt_know() {
+            let state = running(CpuVariant::Wdc65C02);
+            let mut snapshot = state.save_state();
+            add_section(&mut snapshot, *b"NEW ", &[1, 2, 3]);
+
+            assert!(ComputerState::from_snapshot(&snapshot).unwrap() == state);
+        }
+
+        #[test]
+        fn it_restores_memory_maps_with_the_same_layout() {
+            let map = || {
+                MemoryMap::builder()
+                    .ram(0x0000..=0x07FF)
+                    .rom(0xFFFC, vec![0x00, 0x02, 0x00, 0x00])
+                    .build()
+                    .unwrap()
+            };
+            let mut state = ComputerState::initialize_with_bus(map(), CpuVariant::Nmos6502);
+            state.write_byte_to_memory(0x0123, 0x42);
+            state.memory.read(0x0123);
+            let snapshot = state.save_state();
+
+            let mut restored = ComputerState::initialize_with_bus(map(), CpuVariant::Nmos6502);
+            restored.load_state(&snapshot).unwrap();
+            assert!(restored == state);
+
+            let other = MemoryMap::builder().ram(0x0000..=0x0FFF).build().unwrap();
+            let mut other = ComputerState::initialize_with_bus(other, CpuVariant::Nmos6502);
+            assert_eq!(
+                other.load_state(&snapshot),
+                Err(SnapshotError::Invalid("memory map has different RAM regions"))
+            );
+            assert_eq!(other.peek_byte_from_memory(0x0123), 0x00);
+        }
+    }
+}
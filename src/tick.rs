@@ -0,0 +1,1057 @@
+use crate::bus::Bus;
+use crate::error::CpuError;
+use crate::instruction::decode_instruction;
+use crate::instruction::operand_mode::OperandMode;
+use crate::instruction::operation::Operation;
+use crate::instruction::Instruction;
+use crate::snapshot::{Reader, SnapshotError};
+use crate::variant::CpuVariant;
+use crate::{ComputerState, Interrupt, Operand, IRQ_VECTOR, NMI_VECTOR, RESET_VECTOR};
+
+/// A single clock cycle of an instruction or interrupt sequence, each doing one bus access
+/// These follow the per-cycle tables in http://www.6502.org/tutorials/64doc.txt
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Cycle {
+    DummyReadProgramCounter,
+    /// The 65C02 reads the last operand byte again while it adds an index
+    DummyReadLastOperand,
+    DummyReadStack,
+    DummyReadAddress,
+    /// The extra cycles of the 65C02's eight-cycle NOP, whose bus accesses WDC doesn't document,
+    /// are modelled as reads of $FFFF
+    DummyReadLastAddress,
+    /// Reset goes through the motions of pushing with the bus in read mode
+    DummyPush,
+    FetchImmediate,
+    FetchAddressLow,
+    FetchAddressHigh,
+    FetchAddressHighIndexed(Index),
+    FetchAddressHighAndJump,
+    IndexZeroPage(Index),
+    IndexAbsolute,
+    FetchPointer,
+    IndexPointer,
+    ReadPointerLow,
+    ReadPointerHigh,
+    ReadPointerHighIndexed,
+    ReadIndirectLow,
+    ReadIndirectHighAndJump,
+    /// Reads from the address before the carry reaches its high byte, skipped unless indexing
+    /// crossed a page
+    FixPageCrossing,
+    /// Same as FixPageCrossing, but writes can't be undone so it's never skipped
+    FixAddress,
+    Read,
+    /// Skipped unless the 65C02 is adding or substracting in decimal mode
+    DecimalAdjust,
+    Write,
+    ReadForModify,
+    /// The NMOS 6502 writes the unmodified value back while it modifies it, the 65C02 reads it
+    /// again instead
+    DummyWrite,
+    Modify,
+    Implied,
+    /// Executes a stack operation, which does its own bus access
+    Execute,
+    PushProgramCounterHigh,
+    PushProgramCounterLow,
+    PushStatus,
+    PullStatus,
+    PullProgramCounterLow,
+    PullProgramCounterHigh,
+    IncrementProgramCounter,
+    ReadVectorLow,
+    ReadVectorHigh,
+    FetchOffset,
+    FetchOffsetAndBranch,
+    BranchOnBit,
+    /// Skipped unless the branch was taken
+    BranchTaken,
+    /// Skipped unless the branch was taken to another page
+    BranchPageCrossing,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Index {
+    X,
+    Y,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SequenceKind {
+    /// The opcode, with its address and the cycle count when it was fetched for errors
+    Instruction(Instruction, u8, u16, u32),
+    Interrupt,
+    Reset,
+}
+
+/// An instruction or interrupt part way through in tick mode, with the values the CPU has
+/// latched so far
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Sequence {
+    kind: SequenceKind,
+    addressing: &'static [Cycle],
+    access: &'static [Cycle],
+    index: usize,
+    address: u16,
+    pointer: u16,
+    value: u8,
+    page_crossed: bool,
+}
+
+impl Sequence {
+    fn new(kind: SequenceKind, cycles: (&'static [Cycle], &'static [Cycle])) -> Sequence {
+        Sequence {
+            kind,
+            addressing: cycles.0,
+            access: cycles.1,
+            index: 0,
+            address: 0,
+            pointer: 0,
+            value: 0,
+            page_crossed: false,
+        }
+    }
+
+    fn current(&self) -> Option<Cycle> {
+        match self.addressing.get(self.index) {
+            Some(cycle) => Some(*cycle),
+            None => self.access.get(self.index - self.addressing.len()).copied(),
+        }
+    }
+
+    pub(crate) fn instruction(&self) -> Option<Instruction> {
+        match self.kind {
+            SequenceKind::Instruction(instruction, ..) => Some(instruction),
+            _ => None,
+        }
+    }
+
+    fn operation(&self) -> Option<Operation> {
+        match self.kind {
+            SequenceKind::Instruction(Instruction(_, op), ..) => Some(op),
+            _ => None,
+        }
+    }
+
+    /// Saves the kind and the latched values, the cycles follow from the kind
+    pub(crate) fn save_snapshot(&self, out: &mut Vec<u8>) {
+        match self.kind {
+            SequenceKind::Instruction(_, opcode, program_counter, cycles) => {
+                out.extend_from_slice(&[0, opcode]);
+                out.extend_from_slice(&program_counter.to_le_bytes());
+                out.extend_from_slice(&cycles.to_le_bytes());
+            }
+            SequenceKind::Interrupt => out.push(1),
+            SequenceKind::Reset => out.push(2),
+        }
+        out.push(self.index as u8);
+        out.extend_from_slice(&self.address.to_le_bytes());
+        out.extend_from_slice(&self.pointer.to_le_bytes());
+        out.extend_from_slice(&[self.value, self.page_crossed as u8]);
+    }
+
+    pub(crate) fn restore_snapshot(
+        reader: &mut Reader,
+        variant: CpuVariant,
+    ) -> Result<Sequence, SnapshotError> {
+        let mut sequence = match reader.u8()? {
+            0 => {
+                let opcode = reader.u8()?;
+                let program_counter = reader.u16()?;
+                let cycles = reader.u32()?;
+                let instruction = decode_instruction(opcode, variant).ok_or(
+                    SnapshotError::Invalid("instruction in progress isn't one of the variant's"),
+                )?;
+                let Instruction(mode, op) = instruction;
+                Sequence::new(
+                    SequenceKind::Instruction(instruction, opcode, program_counter, cycles),
+                    instruction_cycles(mode, op, variant.is_cmos()),
+                )
+            }
+            1 => Sequence::new(SequenceKind::Interrupt, (&[], INTERRUPT)),
+            2 => Sequence::new(SequenceKind::Reset, (&[], RESET)),
+            _ => return Err(SnapshotError::Invalid("unknown kind of sequence in progress")),
+        };
+        sequence.index = reader.u8()? as usize;
+        sequence.address = reader.u16()?;
+        sequence.pointer = reader.u16()?;
+        sequence.value = reader.u8()?;
+        sequence.page_crossed = reader.bool()?;
+        if sequence.current().is_none() {
+            return Err(SnapshotError::Invalid("sequence in progress is already done"));
+        }
+        Ok(sequence)
+    }
+}
+
+const INTERRUPT: &[Cycle] = &[
+    Cycle::DummyReadProgramCounter,
+    Cycle::DummyReadProgramCounter,
+    Cycle::PushProgramCounterHigh,
+    Cycle::PushProgramCounterLow,
+    Cycle::PushStatus,
+    Cycle::ReadVectorLow,
+    Cycle::ReadVectorHigh,
+];
+const RESET: &[Cycle] = &[
+    Cycle::DummyReadProgramCounter,
+    Cycle::DummyReadProgramCounter,
+    Cycle::DummyPush,
+    Cycle::DummyPush,
+    Cycle::DummyPush,
+    Cycle::ReadVectorLow,
+    Cycle::ReadVectorHigh,
+];
+const BREAK: &[Cycle] = &[
+    Cycle::IncrementProgramCounter,
+    Cycle::PushProgramCounterHigh,
+    Cycle::PushProgramCounterLow,
+    Cycle::PushStatus,
+    Cycle::ReadVectorLow,
+    Cycle::ReadVectorHigh,
+];
+const JUMP_TO_SUBROUTINE: &[Cycle] = &[
+    Cycle::FetchAddressLow,
+    Cycle::DummyReadStack,
+    Cycle::PushProgramCounterHigh,
+    Cycle::PushProgramCounterLow,
+    Cycle::FetchAddressHighAndJump,
+];
+const RETURN_FROM_SUBROUTINE: &[Cycle] = &[
+    Cycle::DummyReadProgramCounter,
+    Cycle::DummyReadStack,
+    Cycle::PullProgramCounterLow,
+    Cycle::PullProgramCounterHigh,
+    Cycle::IncrementProgramCounter,
+];
+const RETURN_FROM_INTERRUPT: &[Cycle] = &[
+    Cycle::DummyReadProgramCounter,
+    Cycle::DummyReadStack,
+    Cycle::PullStatus,
+    Cycle::PullProgramCounterLow,
+    Cycle::PullProgramCounterHigh,
+];
+const PUSH: &[Cycle] = &[Cycle::DummyReadProgramCounter, Cycle::Execute];
+const PULL: &[Cycle] = &[
+    Cycle::DummyReadProgramCounter,
+    Cycle::DummyReadStack,
+    Cycle::Execute,
+];
+const JUMP_ABSOLUTE: &[Cycle] = &[Cycle::FetchAddressLow, Cycle::FetchAddressHighAndJump];
+const JUMP_INDIRECT: &[Cycle] = &[
+    Cycle::FetchAddressLow,
+    Cycle::FetchAddressHigh,
+    Cycle::ReadIndirectLow,
+    Cycle::ReadIndirectHighAndJump,
+];
+const CMOS_JUMP_INDIRECT: &[Cycle] = &[
+    Cycle::FetchAddressLow,
+    Cycle::FetchAddressHigh,
+    Cycle::DummyReadLastOperand,
+    Cycle::ReadIndirectLow,
+    Cycle::ReadIndirectHighAndJump,
+];
+const JUMP_ABSOLUTE_INDIRECT_X: &[Cycle] = &[
+    Cycle::FetchAddressLow,
+    Cycle::FetchAddressHigh,
+    Cycle::IndexAbsolute,
+    Cycle::ReadIndirectLow,
+    Cycle::ReadIndirectHighAndJump,
+];
+const WAIT: &[Cycle] = &[Cycle::DummyReadProgramCounter, Cycle::Implied];
+const BRANCH: &[Cycle] = &[
+    Cycle::FetchOffsetAndBranch,
+    Cycle::BranchTaken,
+    Cycle::BranchPageCrossing,
+];
+const BRANCH_ON_BIT: &[Cycle] = &[
+    Cycle::FetchAddressLow,
+    Cycle::FetchOffset,
+    Cycle::DummyReadAddress,
+    Cycle::BranchOnBit,
+    Cycle::BranchTaken,
+    Cycle::BranchPageCrossing,
+];
+const IMPLIED: &[Cycle] = &[Cycle::Implied];
+const EIGHT_CYCLE_NOP: &[Cycle] = &[Cycle::DummyReadLastAddress; 5];
+const IMMEDIATE: &[Cycle] = &[Cycle::FetchImmediate, Cycle::DecimalAdjust];
+
+const ZERO_PAGE: &[Cycle] = &[Cycle::FetchAddressLow];
+const ZERO_PAGE_X: &[Cycle] = &[Cycle::FetchAddressLow, Cycle::IndexZeroPage(Index::X)];
+const ZERO_PAGE_Y: &[Cycle] = &[Cycle::FetchAddressLow, Cycle::IndexZeroPage(Index::Y)];
+const ABSOLUTE: &[Cycle] = &[Cycle::FetchAddressLow, Cycle::FetchAddressHigh];
+const ABSOLUTE_X: &[Cycle] = &[
+    Cycle::FetchAddressLow,
+    Cycle::FetchAddressHighIndexed(Index::X),
+];
+const ABSOLUTE_Y: &[Cycle] = &[
+    Cycle::FetchAddressLow,
+    Cycle::FetchAddressHighIndexed(Index::Y),
+];
+const INDIRECT_X: &[Cycle] = &[
+    Cycle::FetchPointer,
+    Cycle::IndexPointer,
+    Cycle::ReadPointerLow,
+    Cycle::ReadPointerHigh,
+];
+const INDIRECT_Y: &[Cycle] = &[
+    Cycle::FetchPointer,
+    Cycle::ReadPointerLow,
+    Cycle::ReadPointerHighIndexed,
+];
+const ZERO_PAGE_INDIRECT: &[Cycle] = &[
+    Cycle::FetchPointer,
+    Cycle::ReadPointerLow,
+    Cycle::ReadPointerHigh,
+];
+
+const READ: &[Cycle] = &[Cycle::Read, Cycle::DecimalAdjust];
+const INDEXED_READ: &[Cycle] = &[Cycle::FixPageCrossing, Cycle::Read, Cycle::DecimalAdjust];
+const WRITE: &[Cycle] = &[Cycle::Write];
+const INDEXED_WRITE: &[Cycle] = &[Cycle::FixAddress, Cycle::Write];
+const READ_MODIFY_WRITE: &[Cycle] = &[Cycle::ReadForModify, Cycle::DummyWrite, Cycle::Modify];
+const INDEXED_READ_MODIFY_WRITE: &[Cycle] = &[
+    Cycle::FixAddress,
+    Cycle::ReadForModify,
+    Cycle::DummyWrite,
+    Cycle::Modify,
+];
+const CMOS_SHIFT_ABSOLUTE_X: &[Cycle] = &[
+    Cycle::FixPageCrossing,
+    Cycle::ReadForModify,
+    Cycle::DummyWrite,
+    Cycle::Modify,
+];
+
+fn is_write(op: Operation) -> bool {
+    matches!(
+        op,
+        Operation::STA
+            | Operation::STX
+            | Operation::STY
+            | Operation::STZ
+            | Operation::SAX
+            | Operation::AHX
+            | Operation::SHX
+            | Operation::SHY
+            | Operation::TAS
+    )
+}
+
+fn is_indexed(mode: OperandMode) -> bool {
+    matches!(
+        mode,
+        OperandMode::AbsoluteX | OperandMode::AbsoluteY | OperandMode::IndirectY
+    )
+}
+
+fn is_read_modify_write(op: Operation) -> bool {
+    matches!(
+        op,
+        Operation::ASL
+            | Operation::DEC
+            | Operation::INC
+            | Operation::LSR
+            | Operation::ROL
+            | Operation::ROR
+            | Operation::DCP
+            | Operation::ISC
+            | Operation::RLA
+            | Operation::RRA
+            | Operation::SLO
+            | Operation::SRE
+            | Operation::TRB
+            | Operation::TSB
+            | Operation::RMB0
+            | Operation::RMB1
+            | Operation::RMB2
+            | Operation::RMB3
+            | Operation::RMB4
+            | Operation::RMB5
+            | Operation::RMB6
+            | Operation::RMB7
+            | Operation::SMB0
+            | Operation::SMB1
+            | Operation::SMB2
+            | Operation::SMB3
+            | Operation::SMB4
+            | Operation::SMB5
+            | Operation::SMB6
+            | Operation::SMB7
+    )
+}
+
+/// The cycles after the opcode fetch, as the addressing cycles and those of the access itself
+fn instruction_cycles(
+    mode: OperandMode,
+    op: Operation,
+    cmos: bool,
+) -> (&'static [Cycle], &'static [Cycle]) {
+    match (mode, op) {
+        (_, Operation::BRK) => (&[], BREAK),
+        (_, Operation::JSR) => (&[], JUMP_TO_SUBROUTINE),
+        (_, Operation::RTS) => (&[], RETURN_FROM_SUBROUTINE),
+        (_, Operation::RTI) => (&[], RETURN_FROM_INTERRUPT),
+        (_, Operation::PHA | Operation::PHP | Operation::PHX | Operation::PHY) => (&[], PUSH),
+        (_, Operation::PLA | Operation::PLP | Operation::PLX | Operation::PLY) => (&[], PULL),
+        (_, Operation::WAI | Operation::STP) => (&[], WAIT),
+        (OperandMode::Absolute, Operation::JMP) => (&[], JUMP_ABSOLUTE),
+        (OperandMode::Indirect, Operation::JMP) if cmos => (&[], CMOS_JUMP_INDIRECT),
+        (OperandMode::Indirect, Operation::JMP) => (&[], JUMP_INDIRECT),
+        (OperandMode::AbsoluteIndirectX, Operation::JMP) => (&[], JUMP_ABSOLUTE_INDIRECT_X),
+        (OperandMode::ZeroPageRelative, _) => (&[], BRANCH_ON_BIT),
+        (OperandMode::Immediate, op) if op.is_branch() => (&[], BRANCH),
+        (OperandMode::Implied | OperandMode::Accumulator, _) => (&[], IMPLIED),
+        (OperandMode::OneCycleImplied, _) => (&[], &[]),
+        (OperandMode::EightCycleAbsolute, _) => (ABSOLUTE, EIGHT_CYCLE_NOP),
+        (OperandMode::Immediate, _) => (&[], IMMEDIATE),
+        (mode, op) => {
+            let addressing = match mode {
+                OperandMode::ZeroPage => ZERO_PAGE,
+                OperandMode::ZeroPageX => ZERO_PAGE_X,
+                OperandMode::ZeroPageY => ZERO_PAGE_Y,
+                OperandMode::Absolute => ABSOLUTE,
+                OperandMode::AbsoluteX => ABSOLUTE_X,
+                OperandMode::AbsoluteY => ABSOLUTE_Y,
+                OperandMode::IndirectX => INDIRECT_X,
+                OperandMode::IndirectY => INDIRECT_Y,
+                _ => ZERO_PAGE_INDIRECT,
+            };
+            let indexed = is_indexed(mode);
+            let cmos_shift = cmos
+                && matches!(
+                    op,
+                    Operation::ASL | Operation::LSR | Operation::ROL | Operation::ROR
+                );
+            let access = match (indexed, is_write(op), is_read_modify_write(op)) {
+                (true, true, _) => INDEXED_WRITE,
+                (false, true, _) => WRITE,
+                (true, _, true) if cmos_shift => CMOS_SHIFT_ABSOLUTE_X,
+                (true, _, true) => INDEXED_READ_MODIFY_WRITE,
+                (false, _, true) => READ_MODIFY_WRITE,
+                (true, _, _) => INDEXED_READ,
+                (false, _, _) => READ,
+            };
+            (addressing, access)
+        }
+    }
+}
+
+impl<B: Bus> ComputerState<B> {
+    /// Runs a single clock cycle, doing the same bus access the hardware does in it, including
+    /// the dummy reads and writes
+    /// Instructions and interrupt sequences are spread over as many ticks as they take cycles,
+    /// and a CPU halted by WAI or STP idles without touching the bus
+    pub fn tick(&mut self) -> Result<(), CpuError> {
+        self.cycles += 1;
+        if self.sequence.is_some() {
+            return self.run_next_cycle();
+        }
+
+        let vector = match self.take_pending_interrupt() {
+            Some(Interrupt::Reset) => {
+                self.acknowledge_reset();
+                self.start_sequence(SequenceKind::Reset, (&[], RESET), RESET_VECTOR as u16);
+                return self.run_next_cycle();
+            }
+            Some(Interrupt::Nmi) => NMI_VECTOR,
+            Some(Interrupt::Irq) => IRQ_VECTOR,
+            None if self.waiting_for_interrupt || self.stopped => return Ok(()),
+            None => return self.fetch_opcode(),
+        };
+        self.start_sequence(SequenceKind::Interrupt, (&[], INTERRUPT), vector as u16);
+        self.run_next_cycle()
+    }
+
+    /// True between the ticks of an instruction or interrupt sequence
+    pub fn instruction_in_progress(&self) -> bool {
+        self.sequence.is_some()
+    }
+
+    fn fetch_opcode(&mut self) -> Result<(), CpuError> {
+        let program_counter = self.registers.program_counter;
+        // Not counting this tick, like step() does
+        let cycles = self.cycles - 1;
+        let opcode = self.fetch_byte();
+
+        let decoded_instruction = match decode_instruction(opcode, self.variant) {
+            Some(instruction) => instruction,
+            None => {
+                // Leave the CPU at the opcode, like step() does
+                self.registers.program_counter = program_counter;
+                self.cycles = cycles;
+                return Err(CpuError::UnknownOpcode {
+                    opcode,
+                    program_counter,
+                    cycles,
+                });
+            }
+        };
+        let Instruction(mode, op) = decoded_instruction;
+        let kind = SequenceKind::Instruction(decoded_instruction, opcode, program_counter, cycles);
+        self.branch_taken = false;
+        self.start_sequence(
+            kind,
+            instruction_cycles(mode, op, self.variant.is_cmos()),
+            IRQ_VECTOR as u16,
+        );
+        // The 65C02's one-cycle NOPs are done with the opcode fetch
+        if self.sequence.is_some_and(|sequence| sequence.current().is_none()) {
+            self.sequence = None;
+            self.clear_write_source();
+        }
+        Ok(())
+    }
+
+    /// The pointer starts out as the vector for the sequences which jump through one
+    fn start_sequence(
+        &mut self,
+        kind: SequenceKind,
+        cycles: (&'static [Cycle], &'static [Cycle]),
+        vector: u16,
+    ) {
+        let mut sequence = Sequence::new(kind, cycles);
+        sequence.pointer = vector;
+        self.sequence = Some(sequence);
+        match kind {
+            SequenceKind::Instruction(_, opcode, program_counter, cycles) => {
+                self.set_write_source(program_counter, Some(opcode), cycles)
+            }
+            _ => self.set_write_source(self.registers.program_counter, None, self.cycles - 1),
+        }
+    }
+
+    fn run_next_cycle(&mut self) -> Result<(), CpuError> {
+        let result = self.advance_sequence();
+        // Any writes after the sequence come from outside the CPU
+        if self.sequence.is_none() {
+            self.clear_write_source();
+        }
+        result
+    }
+
+    fn advance_sequence(&mut self) -> Result<(), CpuError> {
+        let mut sequence = match self.sequence.take() {
+            Some(sequence) => sequence,
+            None => return Ok(()),
+        };
+
+        if let Some(cycle) = sequence.current() {
+            self.run_cycle(cycle, &mut sequence)?;
+            sequence.index += 1;
+        }
+        // Skip ahead, so the sequence is done as soon as its last cycle has run
+        while let Some(cycle) = sequence.current() {
+            if !self.skips(cycle, &sequence) {
+                self.sequence = Some(sequence);
+                break;
+            }
+            sequence.index += 1;
+        }
+        Ok(())
+    }
+
+    fn skips(&self, cycle: Cycle, sequence: &Sequence) -> bool {
+        match cycle {
+            Cycle::FixPageCrossing => !sequence.page_crossed,
+            Cycle::DecimalAdjust => {
+                !(self.variant.is_cmos()
+                    && self.decimal_mode_active()
+                    && matches!(sequence.operation(), Some(Operation::ADC | Operation::SBC)))
+            }
+            Cycle::BranchTaken => !self.branch_taken,
+            Cycle::BranchPageCrossing => {
+                !self.branch_taken
+                    || (sequence.pointer ^ self.registers.program_counter) & 0xFF00 == 0
+            }
+            _ => false,
+        }
+    }
+
+    fn run_cycle(&mut self, cycle: Cycle, sequence: &mut Sequence) -> Result<(), CpuError> {
+        let program_counter = self.registers.program_counter;
+        match cycle {
+            Cycle::DummyReadProgramCounter => {
+                self.get_byte_from_memory(program_counter as usize);
+            }
+            Cycle::DummyReadLastOperand => {
+                self.get_byte_from_memory(program_counter.wrapping_sub(1) as usize);
+            }
+            Cycle::DummyReadStack => {
+                self.get_byte_from_memory(self.registers.stack_pointer as usize + 0x100);
+            }
+            Cycle::DummyReadAddress => {
+                self.get_byte_from_memory(sequence.address as usize);
+            }
+            Cycle::DummyReadLastAddress => {
+                self.get_byte_from_memory(0xFFFF);
+            }
+            Cycle::DummyPush => {
+                self.get_byte_from_memory(self.registers.stack_pointer as usize + 0x100);
+                self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(1);
+            }
+            Cycle::FetchImmediate => {
+                sequence.address = program_counter;
+                let value = self.fetch_byte();
+                self.execute(sequence, Operand::Immediate(value))?;
+            }
+            Cycle::FetchAddressLow => sequence.address = self.fetch_byte() as u16,
+            Cycle::FetchAddressHigh => sequence.address |= (self.fetch_byte() as u16) << 8,
+            Cycle::FetchAddressHighIndexed(index) => {
+                let base = sequence.address | (self.fetch_byte() as u16) << 8;
+                self.index_address(sequence, base, index);
+            }
+            Cycle::FetchAddressHighAndJump => {
+                let high = self.get_byte_from_memory(program_counter as usize) as u16;
+                sequence.address |= high << 8;
+                self.registers.program_counter = sequence.address;
+            }
+            Cycle::IndexZeroPage(index) => {
+                self.get_byte_from_memory(sequence.address as usize);
+                let offset = self.index_register(index);
+                sequence.address = (sequence.address as u8).wrapping_add(offset) as u16;
+            }
+            Cycle::IndexAbsolute => {
+                self.get_byte_from_memory(program_counter.wrapping_sub(1) as usize);
+                sequence.address = sequence.address.wrapping_add(self.registers.x as u16);
+            }
+            Cycle::FetchPointer => sequence.pointer = self.fetch_byte() as u16,
+            Cycle::IndexPointer => {
+                self.get_byte_from_memory(sequence.pointer as usize);
+                sequence.pointer = (sequence.pointer as u8).wrapping_add(self.registers.x) as u16;
+            }
+            Cycle::ReadPointerLow => {
+                sequence.address = self.get_byte_from_memory(sequence.pointer as usize) as u16;
+            }
+            Cycle::ReadPointerHigh => {
+                let high_byte_address = (sequence.pointer as u8).wrapping_add(1);
+                let high = self.get_byte_from_memory(high_byte_address as usize) as u16;
+                sequence.address |= high << 8;
+            }
+            Cycle::ReadPointerHighIndexed => {
+                let high_byte_address = (sequence.pointer as u8).wrapping_add(1);
+                let high = self.get_byte_from_memory(high_byte_address as usize) as u16;
+                self.index_address(sequence, sequence.address | high << 8, Index::Y);
+            }
+            Cycle::ReadIndirectLow => {
+                sequence.value = self.get_byte_from_memory(sequence.address as usize);
+            }
+            Cycle::ReadIndirectHighAndJump => {
+                // The NMOS 6502 doesn't carry into the high byte, like in get_indirect_operand
+                let address = sequence.address;
+                let high_byte_address = if self.variant.is_cmos() {
+                    address.wrapping_add(1)
+                } else {
+                    (address & 0xFF00) | (address as u8).wrapping_add(1) as u16
+                };
+                let high = self.get_byte_from_memory(high_byte_address as usize);
+                self.registers.program_counter = u16::from_le_bytes([sequence.value, high]);
+            }
+            Cycle::FixPageCrossing | Cycle::FixAddress => {
+                // The 65C02 reads the last operand byte instead of the invalid address
+                let address = if self.variant.is_cmos() && sequence.page_crossed {
+                    program_counter.wrapping_sub(1)
+                } else {
+                    sequence.pointer
+                };
+                self.get_byte_from_memory(address as usize);
+            }
+            Cycle::Read => {
+                let value = self.get_byte_from_memory(sequence.address as usize);
+                self.execute(sequence, Operand::Latched(sequence.address, value))?;
+            }
+            Cycle::DecimalAdjust => {
+                self.get_byte_from_memory(sequence.address as usize);
+            }
+            Cycle::Write => {
+                // The pointer keeps the high byte of the base address for the unstable stores
+                let operand = match sequence.kind {
+                    SequenceKind::Instruction(Instruction(mode, _), ..) if is_indexed(mode) => {
+                        Operand::Indexed(sequence.address, (sequence.pointer >> 8) as u8)
+                    }
+                    _ => Operand::Address(sequence.address),
+                };
+                self.execute(sequence, operand)?;
+            }
+            Cycle::ReadForModify => {
+                sequence.value = self.get_byte_from_memory(sequence.address as usize);
+            }
+            Cycle::DummyWrite => {
+                if self.variant.is_cmos() {
+                    self.get_byte_from_memory(sequence.address as usize);
+                } else {
+                    self.write_byte_to_memory(sequence.address as usize, sequence.value);
+                }
+            }
+            Cycle::Modify => {
+                self.execute(sequence, Operand::Latched(sequence.address, sequence.value))?;
+            }
+            Cycle::Implied => {
+                self.get_byte_from_memory(program_counter as usize);
+                let operand = match sequence.kind {
+                    SequenceKind::Instruction(Instruction(OperandMode::Accumulator, _), ..) => {
+                        Operand::Accumulator
+                    }
+                    _ => Operand::Implied,
+                };
+                self.execute(sequence, operand)?;
+            }
+            Cycle::Execute => self.execute(sequence, Operand::Implied)?,
+            Cycle::PushProgramCounterHigh => self.push_byte_to_stack((program_counter >> 8) as u8),
+            Cycle::PushProgramCounterLow => self.push_byte_to_stack(program_counter as u8),
+            Cycle::PushStatus => {
+                let status = match sequence.kind {
+                    SequenceKind::Instruction(..) => self.pushed_status(),
+                    _ => self.interrupt_status(),
+                };
+                self.push_byte_to_stack(status);
+            }
+            Cycle::PullStatus => {
+                let status = self.pull_byte_from_stack();
+                self.registers.set_status(status);
+            }
+            Cycle::PullProgramCounterLow => sequence.value = self.pull_byte_from_stack(),
+            Cycle::PullProgramCounterHigh => {
+                let high = self.pull_byte_from_stack();
+                self.registers.program_counter = u16::from_le_bytes([sequence.value, high]);
+            }
+            Cycle::IncrementProgramCounter => {
+                self.get_byte_from_memory(program_counter as usize);
+                self.registers.program_counter = program_counter.wrapping_add(1);
+            }
+            Cycle::ReadVectorLow => {
+                sequence.value = self.get_byte_from_memory(sequence.pointer as usize);
+                match sequence.kind {
+                    SequenceKind::Instruction(..) => self.enter_break(),
+                    SequenceKind::Interrupt => self.enter_interrupt(),
+                    SequenceKind::Reset => self.enter_reset(),
+                }
+            }
+            Cycle::ReadVectorHigh => {
+                let high = self.get_byte_from_memory(sequence.pointer as usize + 1);
+                self.registers.program_counter = u16::from_le_bytes([sequence.value, high]);
+            }
+            Cycle::FetchOffset => sequence.value = self.fetch_byte(),
+            Cycle::FetchOffsetAndBranch => {
+                let offset = self.fetch_byte();
+                sequence.pointer = self.registers.program_counter;
+                self.execute(sequence, Operand::Immediate(offset))?;
+            }
+            Cycle::BranchOnBit => {
+                sequence.pointer = program_counter;
+                let operand = Operand::ZeroPageRelative(sequence.address, sequence.value);
+                self.execute(sequence, operand)?;
+            }
+            Cycle::BranchTaken => {
+                self.get_byte_from_memory(sequence.pointer as usize);
+            }
+            Cycle::BranchPageCrossing => {
+                let unfixed_address = (sequence.pointer & 0xFF00) | (program_counter & 0x00FF);
+                self.get_byte_from_memory(unfixed_address as usize);
+            }
+        }
+        Ok(())
+    }
+
+    fn fetch_byte(&mut self) -> u8 {
+        let value = self.get_byte_from_memory(self.registers.program_counter as usize);
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
+        value
+    }
+
+    fn index_register(&self, index: Index) -> u8 {
+        match index {
+            Index::X => self.registers.x,
+            Index::Y => self.registers.y,
+        }
+    }
+
+    /// Adds the index to the base address, keeping the address the CPU has before the carry
+    /// reaches the high byte in the pointer
+    fn index_address(&self, sequence: &mut Sequence, base: u16, index: Index) {
+        sequence.address = base.wrapping_add(self.index_register(index) as u16);
+        sequence.pointer = (base & 0xFF00) | (sequence.address & 0x00FF);
+        sequence.page_crossed = sequence.pointer != sequence.address;
+    }
+
+    fn execute(&mut self, sequence: &Sequence, operand: Operand) -> Result<(), CpuError> {
+        match sequence.kind {
+            SequenceKind::Instruction(instruction, opcode, program_counter, cycles) => self
+                .execute_operation(instruction.1, operand)
+                .map_err(|reason| CpuError::InvalidOperand {
+                    opcode,
+                    instruction,
+                    program_counter,
+                    cycles,
+                    reason,
+                }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::CpuVariant;
+    use nestegg_macros::asm6502;
+
+    /// Flat RAM that logs every access, as (address, value, is_write)
+    #[derive(Clone, PartialEq, Eq)]
+    struct LoggingBus {
+        ram: Vec<u8>,
+        log: Vec<(u16, u8, bool)>,
+    }
+
+    impl Bus for LoggingBus {
+        fn read(&mut self, address: u16) -> u8 {
+            let value = self.ram[address as usize];
+            self.log.push((address, value, false));
+            value
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.ram[address as usize] = value;
+            self.log.push((address, value, true));
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            self.ram[address as usize]
+        }
+    }
+
+    fn logging_state(program: &[u8], variant: CpuVariant) -> ComputerState<LoggingBus> {
+        let mut ram = vec![0; 0x10000];
+        ram[0x0200..0x0200 + program.len()].copy_from_slice(program);
+        let bus = LoggingBus {
+            ram,
+            log: Vec::new(),
+        };
+        let mut state = ComputerState::initialize_with_bus(bus, variant);
+        state.registers.program_counter = 0x0200;
+        state.registers.stack_pointer = 0xFF;
+        state
+    }
+
+    /// Ticks through one instruction, checking that every tick does exactly one bus access
+    fn tick_instruction(state: &mut ComputerState<LoggingBus>) -> Vec<(u16, u8, bool)> {
+        state.memory.log.clear();
+        loop {
+            let accesses = state.memory.log.len();
+            state.tick().unwrap();
+            assert_eq!(state.memory.log.len(), accesses + 1);
+            if !state.instruction_in_progress() {
+                break;
+            }
+        }
+        state.memory.log.clone()
+    }
+
+    mod describe_tick {
+        use super::*;
+
+        #[test]
+        fn it_reads_the_unfixed_address_when_indexing_crosses_a_page() {
+            let mut state = logging_state(&asm6502!(lda $12F0,x), CpuVariant::Nmos6502);
+            state.registers.x = 0x20;
+            state.memory.ram[0x1310] = 0x42;
+
+            let accesses = tick_instruction(&mut state);
+            assert_eq!(
+                accesses,
+                vec![
+                    (0x0200, 0xBD, false),
+                    (0x0201, 0xF0, false),
+                    (0x0202, 0x12, false),
+                    (0x1210, 0x00, false),
+                    (0x1310, 0x42, false),
+                ]
+            );
+            assert_eq!(state.registers.accumulator, 0x42);
+            assert_eq!(state.cycles, 5);
+        }
+
+        #[test]
+        fn it_writes_unstable_stores_crossing_a_page_to_a_corrupted_address() {
+            // SHX $12F0,Y
+            let mut state = logging_state(&[0x9E, 0xF0, 0x12], CpuVariant::Nmos6502);
+            state.registers.x = 0x0F;
+            state.registers.y = 0x20;
+
+            let accesses = tick_instruction(&mut state);
+            assert_eq!(&accesses[3..], &[(0x1210, 0x00, false), (0x0310, 0x03, true)]);
+
+            let mut stepped = logging_state(&[0x9E, 0xF0, 0x12], CpuVariant::Nmos6502);
+            stepped.registers.x = 0x0F;
+            stepped.registers.y = 0x20;
+            stepped.step().unwrap();
+            assert_eq!(stepped.memory.ram, state.memory.ram);
+        }
+
+        #[test]
+        fn it_writes_twice_in_read_modify_write_instructions() {
+            let mut state = logging_state(&asm6502!(inc $3000), CpuVariant::Nmos6502);
+            state.memory.ram[0x3000] = 0x41;
+
+            let accesses = tick_instruction(&mut state);
+            assert_eq!(
+                &accesses[3..],
+                &[
+                    (0x3000, 0x41, false),
+                    (0x3000, 0x41, true),
+                    (0x3000, 0x42, true)
+                ]
+            );
+        }
+
+        #[test]
+        fn it_reads_twice_in_cmos_read_modify_write_instructions() {
+            let mut state = logging_state(&asm6502!(inc $3000), CpuVariant::Wdc65C02);
+            state.memory.ram[0x3000] = 0x41;
+
+            let accesses = tick_instruction(&mut state);
+            assert_eq!(
+                &accesses[3..],
+                &[
+                    (0x3000, 0x41, false),
+                    (0x3000, 0x41, false),
+                    (0x3000, 0x42, true)
+                ]
+            );
+        }
+
+        #[test]
+        fn it_times_the_cmos_reserved_nops() {
+            let mut state = logging_state(&[0x03, 0x5C, 0x34, 0x12], CpuVariant::Wdc65C02);
+            assert_eq!(tick_instruction(&mut state), vec![(0x0200, 0x03, false)]);
+            assert_eq!(state.cycles, 1);
+
+            let accesses = tick_instruction(&mut state);
+            assert_eq!(accesses.len(), 8);
+            assert_eq!(
+                &accesses[..3],
+                &[
+                    (0x0201, 0x5C, false),
+                    (0x0202, 0x34, false),
+                    (0x0203, 0x12, false)
+                ]
+            );
+            assert_eq!(state.registers.program_counter, 0x0204);
+            assert_eq!(state.cycles, 9);
+        }
+
+        #[test]
+        fn it_runs_interrupt_sequences_cycle_by_cycle() {
+            let mut state = logging_state(&asm6502!(nop), CpuVariant::Nmos6502);
+            state.memory.ram[0xFFFE] = 0x00;
+            state.memory.ram[0xFFFF] = 0x80;
+            state.set_irq_line(true);
+
+            let accesses = tick_instruction(&mut state);
+            assert_eq!(
+                accesses,
+                vec![
+                    (0x0200, 0xEA, false),
+                    (0x0200, 0xEA, false),
+                    (0x01FF, 0x02, true),
+                    (0x01FE, 0x00, true),
+                    (0x01FD, 0x20, true),
+                    (0xFFFE, 0x00, false),
+                    (0xFFFF, 0x80, false),
+                ]
+            );
+            assert_eq!(state.registers.program_counter, 0x8000);
+        }
+
+        #[test]
+        fn it_takes_extra_cycles_for_taken_branches() {
+            // BNE within the page, then one across a page to a BEQ that isn't taken
+            let program = asm6502!(.org $0200; bne $0280);
+            let mut state = logging_state(&program, CpuVariant::Nmos6502);
+
+            let accesses = tick_instruction(&mut state);
+            assert_eq!(&accesses[2..], &[(0x0202, 0x00, false)]);
+            assert_eq!(state.registers.program_counter, 0x0280);
+
+            state.registers.program_counter = 0x02F0;
+            state.memory.ram[0x02F0..0x02F2].copy_from_slice(&asm6502!(.org $02F0; bne $0312));
+            let accesses = tick_instruction(&mut state);
+            assert_eq!(accesses.len(), 4);
+            assert_eq!(state.registers.program_counter, 0x0312);
+
+            state.memory.ram[0x0312..0x0314].copy_from_slice(&asm6502!(beq *));
+            assert_eq!(tick_instruction(&mut state).len(), 2);
+        }
+
+        #[test]
+        fn it_finishes_a_ticked_instruction_when_stepping() {
+            let mut state = ComputerState::initialize_from_image(vec![0xEA; 0x10000]);
+            state.tick().unwrap();
+            assert!(state.instruction_in_progress());
+
+            state.step().unwrap();
+            assert!(!state.instruction_in_progress());
+            assert_eq!(state.registers.program_counter, 1);
+            assert_eq!(state.cycles, 2);
+        }
+
+        /// Runs every opcode in a few situations, both stepping and ticking, and expects the
+        /// same state and cycle count either way
+        #[test]
+        fn it_matches_step_for_all_opcodes() {
+            for variant in [CpuVariant::Nmos6502, CpuVariant::Wdc65C02] {
+                for opcode in 0..=0xFF {
+                    if decode_instruction(opcode, variant).is_none() {
+                        continue;
+                    }
+                    for (program_counter, status) in [(0x0280, 0x00), (0x02F0, 0xFF)] {
+                        let mut image = vec![0; 0x10000];
+                        image[program_counter] = opcode;
+                        image[program_counter + 1] = 0xF4;
+                        image[program_counter + 2] = 0x12;
+                        image[0x00F4] = 0xF8;
+                        image[0x00F5] = 0x34;
+                        image[0x0004] = 0xF0;
+                        image[0x0005] = 0x56;
+                        image[0xFFFC] = 0x00;
+                        image[0xFFFD] = 0x80;
+                        image[0xFFFE] = 0x00;
+                        image[0xFFFF] = 0x90;
+
+                        let mut state = ComputerState::initialize_from_image_with_variant(
+                            image, variant,
+                        );
+                        state.registers.program_counter = program_counter as u16;
+                        state.registers.status = status;
+                        state.registers.accumulator = 0x99;
+                        state.registers.x = 0x10;
+                        state.registers.y = 0x20;
+                        state.registers.stack_pointer = 0xF0;
+
+                        let mut ticked = state.clone();
+                        ticked.tick().unwrap();
+                        while ticked.instruction_in_progress() {
+                            ticked.tick().unwrap();
+                        }
+                        let mut stepped = state;
+                        stepped.step().unwrap();
+
+                        assert!(
+                            stepped == ticked,
+                            "{:?} opcode {:02X} at {:04X}: {:?} after {} cycles stepping, {:?} \
+                             after {} ticking",
+                            variant,
+                            opcode,
+                            program_counter,
+                            stepped.registers,
+                            stepped.cycles,
+                            ticked.registers,
+                            ticked.cycles
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
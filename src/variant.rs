@@ -0,0 +1,54 @@
+/// The chip being emulated, which decides the instruction set, timings and quirks
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CpuVariant {
+    /// The original NMOS 6502, including its undocumented opcodes
+    #[default]
+    Nmos6502,
+    /// The NES CPU, an NMOS 6502 with the decimal mode disconnected
+    Ricoh2A03,
+    /// The Atari 2600 CPU, an NMOS 6502 with only 13 address lines
+    Mos6507,
+    /// The Commodore 64 CPU, an NMOS 6502 with an I/O port at $0000/$0001
+    Mos6510,
+    /// The WDC 65C02, with the Rockwell bit instructions and WAI/STP
+    Wdc65C02,
+    /// The 65SC02, a 65C02 without the bit instructions and WAI/STP
+    Wdc65SC02,
+}
+
+impl CpuVariant {
+    /// True for the CMOS chips, which have the 65C02 instruction set, fix the NMOS decimal flags
+    /// and the JMP indirect page wrap, and clear DECIMAL when taking an interrupt
+    pub const fn is_cmos(self) -> bool {
+        match self {
+            CpuVariant::Nmos6502
+            | CpuVariant::Ricoh2A03
+            | CpuVariant::Mos6507
+            | CpuVariant::Mos6510 => false,
+            CpuVariant::Wdc65C02 | CpuVariant::Wdc65SC02 => true,
+        }
+    }
+
+    /// True if ADC and SBC honour the DECIMAL flag
+    pub const fn has_decimal_mode(self) -> bool {
+        !matches!(self, CpuVariant::Ricoh2A03)
+    }
+
+    /// True if BBR, BBS, RMB, SMB, WAI and STP are available, otherwise their opcodes are NOPs
+    pub const fn has_wdc_extensions(self) -> bool {
+        matches!(self, CpuVariant::Wdc65C02)
+    }
+
+    /// True if the chip has the on-chip I/O port at $0000 (direction) and $0001 (data)
+    pub const fn has_io_port(self) -> bool {
+        matches!(self, CpuVariant::Mos6510)
+    }
+
+    /// Mask applied to every address put on the bus
+    pub const fn address_mask(self) -> u16 {
+        match self {
+            CpuVariant::Mos6507 => 0x1FFF,
+            _ => 0xFFFF,
+        }
+    }
+}
@@ -1,13 +1,16 @@
-use nestegg::ComputerState;
+use nestegg::{
+    assemble, Bus, ComputerState, CpuVariant, ImageFormat, MemoryMap, OpenBus, RegisterFile,
+    StatusFlag, StopConditions, StopReason,
+};
+use nestegg_macros::asm6502;
 
 #[test]
 fn smoketest() {
     let initial_memory = vec![0xEA; 10];
     let expected_memory = initial_memory.clone();
 
-    let final_state = ComputerState::initialize_from_image(initial_memory)
-        .multiple_steps(10)
-        .unwrap();
+    let mut final_state = ComputerState::initialize_from_image(initial_memory);
+    final_state.multiple_steps(10).unwrap();
 
     let final_memory = final_state.memory;
     let cycles = final_state.cycles;
@@ -18,29 +21,29 @@ fn smoketest() {
 
 #[test]
 fn fibonacci_test() {
-    let mut padding = vec![0; 512];
-    let mut program = vec![
-        0x18,             // CLC       - clear carry, otherwise first value may be off by 1
-        0xA2, 0x00,       // LDX $0    - set starting values: 0 to X,
-        0xA0, 0x01,       // LDY $1    -                      1 to Y
-        // .loop
-        0x98,             // TYA       - move Y to A
-        0x86, 0x20,       // STX tmp   - can't add X directly, so store to memory
-        0x65, 0x20,       // ADC tmp   - and add to accumulator
-        0xB0, 0x08,       // BCS .end  - if carry set (result > 255), branch to .end
-        0x48,             // PHA       - push result to stack
-        0xA4, 0x20,       // LDY tmp   - load previously stored X value to Y
-        0xAA,             // TAX       - and move A to X
-        0x90, 0xf5,       // BCC .loop - jump back to start of .loop
-        //     .end
-        0x70, 0x00,       // BCS (0) - jump back to this address, stalling in infloop
-        // .data
-        0x00];            // tmp
-    program.append(&mut padding); // Space for stack
-
-    let final_state = ComputerState::initialize_from_image(program)
-        .multiple_steps(600)
+    let source = "
+        tmp = $20
+                clc         ; otherwise the first value may be off by 1
+                ldx #0      ; set starting values: 0 to X,
+                ldy #1      ;                      1 to Y
+        loop:   tya
+                stx tmp     ; can't add X directly, so store to memory
+                adc tmp     ; and add to accumulator
+                bcs end     ; if carry set (result > 255), stop
+                pha         ; push result to stack
+                ldy tmp     ; load previously stored X value to Y
+                tax         ; and move A to X
+                bcc loop
+        end:    bcs end     ; stall in an infinite loop
+    ";
+    let assembly = assemble(source).unwrap();
+
+    let mut final_state = ComputerState::initialize_from_image(assembly.image.clone());
+    let reason = final_state
+        .run(StopConditions::default().on_infinite_loop())
         .unwrap();
+    assert_eq!(reason, StopReason::InfiniteLoop);
+    assert_eq!(final_state.registers.program_counter(), assembly.symbol("end").unwrap());
 
     let final_memory = final_state.memory;
     let cycles = final_state.cycles;
@@ -49,5 +52,175 @@ fn fibonacci_test() {
     for i in 0..12 {
         assert_eq!(expected[i], final_memory[0x1ff - i]);
     }
-    assert_eq!(cycles, 1254);
+    assert_eq!(cycles, 293);
+}
+
+/// RAM with a serial port at $D000 whose data register pops a byte on every read
+struct SerialBus {
+    ram: Vec<u8>,
+    received: Vec<u8>,
+    sent: Vec<u8>,
+}
+
+impl Bus for SerialBus {
+    fn read(&mut self, address: u16) -> u8 {
+        match address {
+            0xD000 => self.received.pop().unwrap_or(0),
+            _ => self.ram[address as usize],
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xD000 => self.sent.push(value),
+            _ => self.ram[address as usize] = value,
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0xD000 => self.received.last().copied().unwrap_or(0),
+            _ => self.ram[address as usize],
+        }
+    }
+}
+
+#[test]
+fn memory_mapped_io_test() {
+    let program = asm6502! {
+        lda $D000;  // receive a byte
+        adc #$01;
+        sta $D000;  // and send it back, incremented
+        lda $D000;
+        sta $0200
+    };
+    let mut ram = vec![0; 0x10000];
+    ram[..program.len()].copy_from_slice(&program);
+    let bus = SerialBus {
+        ram,
+        received: vec![0x20, 0x10],
+        sent: Vec::new(),
+    };
+
+    let mut state = ComputerState::initialize_with_bus(bus, CpuVariant::Nmos6502);
+    assert_eq!(state.peek_byte_from_memory(0xD000), 0x10);
+    assert_eq!(state.peek_byte_from_memory(0xD000), 0x10);
+
+    state.multiple_steps(5).unwrap();
+
+    assert_eq!(state.memory.sent, vec![0x11]);
+    assert_eq!(state.memory.ram[0x0200], 0x20);
+    assert!(state.memory.received.is_empty());
+}
+
+#[test]
+fn memory_map_test() {
+    let rom = asm6502! {
+        lda #$42;
+        sta $0801;  // through the RAM mirror
+        sta $F000;  // ignored by the ROM
+        lda $4000;  // unmapped, reads $FF
+        sta $02
+    };
+    let memory = MemoryMap::builder()
+        .ram(0x0000..=0x07FF)
+        .mirror(0x0800..=0x1FFF, 0x0000..=0x07FF)
+        .rom(0xF000, rom)
+        .rom(0xFFFC, vec![0x00, 0xF0, 0x00, 0x00])
+        .open_bus(OpenBus::Fixed(0xFF))
+        .build()
+        .unwrap();
+
+    let mut state = ComputerState::initialize_with_bus(memory, CpuVariant::Nmos6502);
+    state.reset();
+    state.multiple_steps(5).unwrap();
+
+    assert_eq!(state.peek_byte_from_memory(0x0001), 0x42);
+    assert_eq!(state.peek_byte_from_memory(0xF000), 0xA9);
+    assert_eq!(state.peek_byte_from_memory(0x0002), 0xFF);
+}
+
+#[test]
+fn subroutine_call_test() {
+    let mut memory = vec![0; 0x10000];
+    let subroutine = asm6502! {
+        stx $10; adc $10; tay; php; pla; rts
+    };
+    memory[0x8000..0x8000 + subroutine.len()].copy_from_slice(&subroutine);
+    // Returns to $1234
+    memory[0x01FE] = 0x33;
+    memory[0x01FF] = 0x12;
+
+    let mut registers = RegisterFile::default();
+    registers.set_accumulator(0xF0);
+    registers.set_x(0x20);
+    registers.set_stack_pointer(0xFD);
+    registers.set_program_counter(0x8000);
+    registers.set_flag(StatusFlag::CARRY, true);
+
+    let mut state =
+        ComputerState::initialize_with_registers(memory, CpuVariant::Nmos6502, registers);
+    let reason = state.run(StopConditions::default().address(0x1234)).unwrap();
+
+    assert_eq!(reason, StopReason::Address);
+    assert_eq!(state.registers.y(), 0x11);
+    assert!(state.get_status_flag(StatusFlag::CARRY));
+    assert!(!state.get_status_flag(StatusFlag::ZERO));
+    assert_eq!(state.registers.status(), 0x21);
+    // PHP pushed the status with BREAK and bit 5 set
+    assert_eq!(state.registers.accumulator(), 0x31);
+    assert_eq!(state.registers.stack_pointer(), 0xFF);
+}
+
+#[test]
+fn prg_loader_test() {
+    let mut file = vec![0x00, 0xC0];
+    file.extend(asm6502! {
+        .org $C000;
+        ldx #0;
+    loop:
+        lda message,x; beq done; sta $0400,x; inx; bne loop;
+    done:
+        brk;
+    message:
+        .byte "HI", 0
+    });
+
+    let mut state = ComputerState::initialize_with_variant(CpuVariant::Mos6510);
+    let loaded = state.load(&file, ImageFormat::Prg).unwrap();
+    assert_eq!(loaded.start, None);
+
+    state.registers.set_program_counter(loaded.segments[0].address);
+    let reason = state.run(StopConditions::default().on_break()).unwrap();
+
+    assert_eq!(reason, StopReason::Break);
+    assert_eq!(state.peek_byte_from_memory(0x0400), b'H');
+    assert_eq!(state.peek_byte_from_memory(0x0401), b'I');
+}
+
+#[test]
+fn save_state_test() {
+    let program = asm6502! {
+        .org $0200;
+        ldx #0;
+    loop:
+        txa; sta $0400,x; inx; cpx #16; bne loop;
+        brk
+    };
+    let mut memory = vec![0; 0x10000];
+    memory[0x0200..0x0200 + program.len()].copy_from_slice(&program);
+    let mut state = ComputerState::initialize_from_image(memory);
+    state.registers.set_program_counter(0x0200);
+    state.multiple_steps(12).unwrap();
+
+    let snapshot = state.save_state();
+    let mut restored = ComputerState::from_snapshot(&snapshot).unwrap();
+    for session in [&mut state, &mut restored] {
+        let reason = session.run(StopConditions::default().on_break()).unwrap();
+        assert_eq!(reason, StopReason::Break);
+    }
+
+    assert_eq!(restored.peek_byte_from_memory(0x040F), 0x0F);
+    assert_eq!(restored.cycles, state.cycles);
+    assert_eq!(restored.registers, state.registers);
 }